@@ -0,0 +1,63 @@
+//! Instructions per second with and without the decode cache, and with the block engine.
+//! Run with
+//! `cargo bench --bench exec`.
+
+use std::{
+    fs,
+    hint::black_box,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use chip_8_rs::{ExecutionEngine, Machine, Rom};
+
+const INSTRUCTIONS: u32 = 5_000_000;
+
+// counts V0 up and adds it into V1 forever
+const COUNTER_LOOP: [u8; 8] = [
+    0x60, 0x00, // V0 = 0
+    0x70, 0x01, // V0 += 1
+    0x81, 0x04, // V1 += V0
+    0x12, 0x02, // jump 0x202
+];
+
+// big frames so ticking the timers does not show up in the numbers
+const INSTRUCTIONS_PER_FRAME: u32 = 10_000;
+
+fn time(program: &[u8], decode_cache: bool, engine: ExecutionEngine) -> Duration {
+    let mut machine = Machine::new();
+    machine.set_decode_cache(decode_cache);
+    machine.set_execution_engine(engine);
+    let mut machine = Rom::load_bytes(program.to_vec(), machine).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..INSTRUCTIONS / INSTRUCTIONS_PER_FRAME {
+        black_box(machine.run_frame(INSTRUCTIONS_PER_FRAME)).unwrap();
+    }
+    start.elapsed()
+}
+
+fn bench(name: &str, program: &[u8]) {
+    let interpreted = time(program, false, ExecutionEngine::Interpreter);
+    let cached = time(program, true, ExecutionEngine::Interpreter);
+    let blocks = time(program, true, ExecutionEngine::Blocks);
+    let per_second = |elapsed: Duration| INSTRUCTIONS as f64 / elapsed.as_secs_f64() / 1e6;
+    let speedup = |elapsed: Duration| interpreted.as_secs_f64() / elapsed.as_secs_f64();
+    println!(
+        "{:<16} interpreted {:>7.1} M/s   cached {:>7.1} M/s ({:.2}x)   blocks {:>7.1} M/s ({:.2}x)",
+        name,
+        per_second(interpreted),
+        per_second(cached),
+        speedup(cached),
+        per_second(blocks),
+        speedup(blocks)
+    );
+}
+
+fn main() {
+    bench("counter loop", &COUNTER_LOOP);
+
+    // ends in a jump to itself, most of the time goes there
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/roms/test_opcode.ch8");
+    bench("test_opcode", &fs::read(path).unwrap());
+}
@@ -0,0 +1,604 @@
+//! Builds loadable ROMs from source text.
+//!
+//! The mnemonics are the ones the [disassembler](crate::disassembler) prints, so its
+//! instructions and `DB`/`DW` data can be fed back in. A line holds an optional `label:`, then an instruction or directive, and
+//! anything after `;` is a comment:
+//!
+//! ```text
+//!         ORG 0x200
+//! SPEED   EQU 4
+//! start:  MOV V0, SPEED
+//!         MOVI sprite
+//!         DRW V0, V1, 2
+//!         JMP start
+//! sprite: DB 0b11110000, 0x90
+//!         DW 0xF0F0
+//! ```
+//!
+//! Numbers are decimal, `0x` hex or `0b` binary. `ORG` sets the address of what follows, the
+//! first one before any code picks the load address, later ones pad forward with zeros.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::machine::instructions::Operation;
+
+const DEFAULT_ORIGIN: u16 = 0x200;
+
+/// Why a source could not be assembled, `line` and `column` start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+/// The assembled program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembled {
+    /// Address of the first byte, 0x200 unless the source starts with another `ORG`
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    /// Every label and its address
+    pub labels: HashMap<String, u16>,
+}
+
+/// Assemble `source` into bytes ready for [`Rom::load_bytes`](crate::Rom::load_bytes)
+pub fn assemble(source: &str) -> Result<Assembled, AssembleError> {
+    let statements = parse(source)?;
+
+    // first pass, find out where everything goes
+    let mut symbols: HashMap<String, u16> = HashMap::new();
+    let mut labels = HashMap::new();
+    let mut origin = None;
+    let mut address = DEFAULT_ORIGIN;
+    for statement in &statements {
+        if let Some(label) = &statement.label {
+            define(&mut symbols, label, address)?;
+            labels.insert(label.text.clone(), address);
+        }
+        match &statement.kind {
+            None => {}
+            Some(StatementKind::Equ { name, value }) => {
+                let value = evaluate(&symbols, value)?;
+                define(&mut symbols, name, value)?;
+            }
+            Some(StatementKind::Org(target)) => {
+                let target_address = evaluate(&symbols, target)?;
+                if origin.is_some() && target_address < address {
+                    return Err(error_at(target, "ORG cannot move backwards"));
+                }
+                origin.get_or_insert(target_address);
+                address = target_address;
+            }
+            Some(kind) => {
+                origin.get_or_insert(address);
+                address = address.wrapping_add(kind.size());
+            }
+        }
+    }
+    let origin = origin.unwrap_or(DEFAULT_ORIGIN);
+
+    // second pass, every symbol is known now
+    let mut bytes = Vec::new();
+    for statement in &statements {
+        let Some(kind) = &statement.kind else {
+            continue;
+        };
+        match kind {
+            StatementKind::Equ { .. } => {}
+            StatementKind::Org(target) => {
+                let target = evaluate(&symbols, target)?;
+                let length = target.wrapping_sub(origin) as usize;
+                if bytes.len() < length {
+                    bytes.resize(length, 0);
+                }
+            }
+            StatementKind::Db(values) => {
+                for value in values {
+                    bytes.push(evaluate_in_range(&symbols, value, 0xFF, "a byte")? as u8);
+                }
+            }
+            StatementKind::Dw(values) => {
+                for value in values {
+                    let word = evaluate(&symbols, value)?;
+                    bytes.extend(word.to_be_bytes());
+                }
+            }
+            StatementKind::Instruction { mnemonic, operands } => {
+                let operation = build_operation(&symbols, mnemonic, operands)?;
+                bytes.extend(operation.encode().to_be_bytes());
+                if let Operation::OpF000LongI { address } = operation {
+                    bytes.extend(address.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    Ok(Assembled {
+        origin,
+        bytes,
+        labels,
+    })
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+struct Statement {
+    label: Option<Token>,
+    // None for a line with only a label
+    kind: Option<StatementKind>,
+}
+
+enum StatementKind {
+    Instruction {
+        mnemonic: Token,
+        operands: Vec<Token>,
+    },
+    Db(Vec<Token>),
+    Dw(Vec<Token>),
+    Org(Token),
+    Equ {
+        name: Token,
+        value: Token,
+    },
+}
+
+impl StatementKind {
+    fn size(&self) -> u16 {
+        match self {
+            StatementKind::Instruction { mnemonic, .. } => {
+                match mnemonic.text.eq_ignore_ascii_case("LONGI") {
+                    true => 4,
+                    false => 2,
+                }
+            }
+            StatementKind::Db(values) => values.len() as u16,
+            StatementKind::Dw(values) => values.len() as u16 * 2,
+            StatementKind::Org(_) | StatementKind::Equ { .. } => 0,
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Vec<Statement>, AssembleError> {
+    let mut statements = Vec::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let mut tokens = tokenize(text, index + 1).into_iter().peekable();
+
+        let label = match tokens.peek() {
+            Some(token) if token.text.ends_with(':') => {
+                let mut label = tokens.next().expect("peeked");
+                label.text.pop();
+                check_symbol_name(&label)?;
+                Some(label)
+            }
+            _ => None,
+        };
+
+        let Some(first) = tokens.next() else {
+            if label.is_some() {
+                statements.push(Statement { label, kind: None });
+            }
+            continue;
+        };
+        let mut rest: Vec<Token> = tokens.collect();
+
+        let kind = if rest
+            .first()
+            .is_some_and(|token| token.text.eq_ignore_ascii_case("EQU"))
+        {
+            check_symbol_name(&first)?;
+            let equ = rest.remove(0);
+            let value = single_operand(&equ, rest)?;
+            StatementKind::Equ { name: first, value }
+        } else {
+            match first.text.to_ascii_uppercase().as_str() {
+                "ORG" => StatementKind::Org(single_operand(&first, rest)?),
+                "DB" => StatementKind::Db(rest),
+                "DW" => StatementKind::Dw(rest),
+                _ => StatementKind::Instruction {
+                    mnemonic: first,
+                    operands: rest,
+                },
+            }
+        };
+        statements.push(Statement {
+            label,
+            kind: Some(kind),
+        });
+    }
+
+    Ok(statements)
+}
+
+/// Split on whitespace and commas, stopping at a `;` comment
+fn tokenize(text: &str, line: usize) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current: Option<Token> = None;
+
+    for (index, character) in text.char_indices() {
+        if character == ';' {
+            break;
+        }
+        if character.is_whitespace() || character == ',' {
+            tokens.extend(current.take());
+            continue;
+        }
+        current
+            .get_or_insert_with(|| Token {
+                text: String::new(),
+                line,
+                column: index + 1,
+            })
+            .text
+            .push(character);
+    }
+    tokens.extend(current);
+    tokens
+}
+
+fn single_operand(directive: &Token, operands: Vec<Token>) -> Result<Token, AssembleError> {
+    let mut operands = operands.into_iter();
+    match (operands.next(), operands.next()) {
+        (Some(operand), None) => Ok(operand),
+        (None, _) => Err(error_at(
+            directive,
+            &format!("{} expects a value", directive.text),
+        )),
+        (Some(_), Some(extra)) => Err(error_at(&extra, "unexpected operand")),
+    }
+}
+
+fn check_symbol_name(token: &Token) -> Result<(), AssembleError> {
+    let mut characters = token.text.chars();
+    let valid = characters
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && characters.all(|character| character.is_ascii_alphanumeric() || character == '_');
+    match valid && parse_register(&token.text).is_none() {
+        true => Ok(()),
+        false => Err(error_at(
+            token,
+            &format!("'{}' is not a valid name", token.text),
+        )),
+    }
+}
+
+fn define(
+    symbols: &mut HashMap<String, u16>,
+    name: &Token,
+    value: u16,
+) -> Result<(), AssembleError> {
+    match symbols.insert(name.text.clone(), value) {
+        Some(_) => Err(error_at(name, &format!("'{}' is defined twice", name.text))),
+        None => Ok(()),
+    }
+}
+
+fn error_at(token: &Token, message: &str) -> AssembleError {
+    AssembleError {
+        line: token.line,
+        column: token.column,
+        message: message.to_string(),
+    }
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    let lowercase = text.to_ascii_lowercase();
+    let (digits, radix) = match lowercase.as_str() {
+        hex if hex.starts_with("0x") => (&hex[2..], 16),
+        binary if binary.starts_with("0b") => (&binary[2..], 2),
+        decimal => (decimal, 10),
+    };
+    u16::from_str_radix(digits, radix).ok()
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['V', 'v'])?;
+    match digit.len() {
+        1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+fn evaluate(symbols: &HashMap<String, u16>, token: &Token) -> Result<u16, AssembleError> {
+    if let Some(number) = parse_number(&token.text) {
+        return Ok(number);
+    }
+    symbols
+        .get(&token.text)
+        .copied()
+        .ok_or_else(|| error_at(token, &format!("unknown value or label '{}'", token.text)))
+}
+
+fn evaluate_in_range(
+    symbols: &HashMap<String, u16>,
+    token: &Token,
+    max: u16,
+    what: &str,
+) -> Result<u16, AssembleError> {
+    let value = evaluate(symbols, token)?;
+    match value <= max {
+        true => Ok(value),
+        false => Err(error_at(
+            token,
+            &format!("{} does not fit in {}", token.text, what),
+        )),
+    }
+}
+
+/// Operand shapes the instructions take
+enum Operand {
+    Register(u8),
+    Value(u16),
+}
+
+fn build_operation(
+    symbols: &HashMap<String, u16>,
+    mnemonic: &Token,
+    operands: &[Token],
+) -> Result<Operation, AssembleError> {
+    let resolved: Vec<Operand> = operands
+        .iter()
+        .map(|operand| match parse_register(&operand.text) {
+            Some(register) => Ok(Operand::Register(register)),
+            None => evaluate(symbols, operand).map(Operand::Value),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let check = |index: usize, max: u16, what: &str| -> Result<(), AssembleError> {
+        match &resolved[index] {
+            Operand::Value(value) if *value > max => Err(error_at(
+                &operands[index],
+                &format!("{} does not fit in {}", operands[index].text, what),
+            )),
+            _ => Ok(()),
+        }
+    };
+
+    use Operand::{Register as R, Value as N};
+    let operation = match (
+        mnemonic.text.to_ascii_uppercase().as_str(),
+        resolved.as_slice(),
+    ) {
+        ("SYS", [N(address)]) => {
+            check(0, 0xFFF, "an address")?;
+            Operation::Op0nnnSys { address: *address }
+        }
+        ("CLS", []) => Operation::Op00e0Cls,
+        ("RET", []) => Operation::Op00eeRet,
+        ("SCD", [N(rows)]) => {
+            check(0, 0xF, "a nibble")?;
+            Operation::Op00cnScd { rows: *rows as u8 }
+        }
+        ("SCR", []) => Operation::Op00fbScr,
+        ("SCL", []) => Operation::Op00fcScl,
+        ("EXIT", []) => Operation::Op00fdExit,
+        ("LOW", []) => Operation::Op00feLow,
+        ("HIGH", []) => Operation::Op00ffHigh,
+        ("JMP", [N(address)]) => {
+            check(0, 0xFFF, "an address")?;
+            Operation::Op1nnnJmp { address: *address }
+        }
+        ("CALL", [N(address)]) => {
+            check(0, 0xFFF, "an address")?;
+            Operation::Op2nnnCall { address: *address }
+        }
+        ("SE", [R(x), N(value)]) => {
+            check(1, 0xFF, "a byte")?;
+            Operation::Op3xnnSe {
+                register: *x,
+                value: *value as u8,
+            }
+        }
+        ("SNE", [R(x), N(value)]) => {
+            check(1, 0xFF, "a byte")?;
+            Operation::Op4xnnSne {
+                register: *x,
+                value: *value as u8,
+            }
+        }
+        ("SE", [R(x), R(y)]) => Operation::Op5xy0Se {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SAVE", [R(x), R(y)]) => Operation::Op5xy2Save {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("LOAD", [R(x), R(y)]) => Operation::Op5xy3Load {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("MOV", [R(x), N(number)]) => {
+            check(1, 0xFF, "a byte")?;
+            Operation::Op6xnnMov {
+                register_x: *x,
+                number: *number as u8,
+            }
+        }
+        ("ADD", [R(x), N(number)]) => {
+            check(1, 0xFF, "a byte")?;
+            Operation::Op7xnnAdd {
+                register_x: *x,
+                number: *number as u8,
+            }
+        }
+        ("YMOVX", [R(x), R(y)]) => Operation::Op8xy0Ymovx {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SETVX2VXORVY", [R(x), R(y)]) => Operation::Op8xy1Setvx2vxorvy {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SETVX2VXANDVY", [R(x), R(y)]) => Operation::Op8xy2Setvx2vxandvy {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SETVX2VXXORVY", [R(x), R(y)]) => Operation::Op8xy3Setvx2vxxorvy {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("ADD", [R(x), R(y)]) => Operation::Op8xy4Add {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SUB", [R(x), R(y)]) => Operation::Op8xy5Sub {
+            register_x: *x,
+            register_y: *y,
+        },
+        // the short form leaves Y at 0, like the 8X06 it used to assemble to
+        ("SHR", [R(x)]) => Operation::Op8xy6Shr {
+            register_x: *x,
+            register_y: 0,
+        },
+        ("SHR", [R(x), R(y)]) => Operation::Op8xy6Shr {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SUBN", [R(x), R(y)]) => Operation::Op8xy7Subn {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SHL", [R(x)]) => Operation::Op8xyeShl {
+            register_x: *x,
+            register_y: 0,
+        },
+        ("SHL", [R(x), R(y)]) => Operation::Op8xyeShl {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("SNE", [R(x), R(y)]) => Operation::Op9xy0Sne {
+            register_x: *x,
+            register_y: *y,
+        },
+        ("MOVI", [N(address)]) => {
+            check(0, 0xFFF, "an address")?;
+            Operation::OpAnnnMovI { address: *address }
+        }
+        ("JMPPLUSV0", [N(address)]) => {
+            check(0, 0xFFF, "an address")?;
+            Operation::OpBnnnJmpPlusV0 {
+                value_nnn: *address,
+            }
+        }
+        ("MOVRAND", [R(x), N(mask)]) => {
+            check(1, 0xFF, "a byte")?;
+            Operation::OpCxnnMovRand {
+                register_x: *x,
+                mask: *mask as u8,
+            }
+        }
+        ("DRW", [R(x), R(y), N(height)]) => {
+            check(2, 0xF, "a nibble")?;
+            Operation::OpDxynDrw {
+                register_x: *x,
+                register_y: *y,
+                height: *height as u8,
+            }
+        }
+        ("SKPRS", [R(x)]) => Operation::OpEx9eSkprs { register_x: *x },
+        ("SKNPRS", [R(x)]) => Operation::OpExa1Sknprs { register_x: *x },
+        ("LONGI", [N(address)]) => Operation::OpF000LongI { address: *address },
+        ("PLANE", [N(planes)]) => {
+            check(0, 0xF, "a nibble")?;
+            Operation::OpFn01Plane {
+                planes: *planes as u8,
+            }
+        }
+        ("AUDIO", []) => Operation::OpF002Audio,
+        ("MOVDT", [R(x)]) => Operation::OpFx07MovDt { register_x: *x },
+        ("WAITKEY", [R(x)]) => Operation::OpFx0aWaitKey { register_x: *x },
+        ("SETDLY", [R(x)]) => Operation::OpFx15SetDly { register_x: *x },
+        ("SETST", [R(x)]) => Operation::OpFx18SetSt { register_x: *x },
+        ("MOVVI", [R(x)]) => Operation::OpFx1eMovVi { register_x: *x },
+        ("FX29", [R(x)]) => Operation::OpFx29 { register_x: *x },
+        ("FX30", [R(x)]) => Operation::OpFx30 { register_x: *x },
+        ("FX33", [R(x)]) => Operation::OpFx33 { register_x: *x },
+        ("PITCH", [R(x)]) => Operation::OpFx3aPitch { register_x: *x },
+        ("FX55", [R(x)]) => Operation::OpFx55 { register_x: *x },
+        ("FX65", [R(x)]) => Operation::OpFx65 { register_x: *x },
+        ("FX75", [R(x)]) => Operation::OpFx75 { register_x: *x },
+        ("FX85", [R(x)]) => Operation::OpFx85 { register_x: *x },
+        (name, _) if is_mnemonic(name) => {
+            return Err(error_at(
+                mnemonic,
+                &format!("wrong operands for {}", mnemonic.text),
+            ))
+        }
+        _ => {
+            return Err(error_at(
+                mnemonic,
+                &format!("unknown instruction '{}'", mnemonic.text),
+            ))
+        }
+    };
+    Ok(operation)
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    const MNEMONICS: [&str; 47] = [
+        "SYS",
+        "CLS",
+        "RET",
+        "SCD",
+        "SCR",
+        "SCL",
+        "EXIT",
+        "LOW",
+        "HIGH",
+        "JMP",
+        "CALL",
+        "SE",
+        "SNE",
+        "SAVE",
+        "LOAD",
+        "MOV",
+        "ADD",
+        "YMOVX",
+        "SETVX2VXORVY",
+        "SETVX2VXANDVY",
+        "SETVX2VXXORVY",
+        "SUB",
+        "SHR",
+        "SUBN",
+        "SHL",
+        "MOVI",
+        "JMPPLUSV0",
+        "MOVRAND",
+        "DRW",
+        "SKPRS",
+        "SKNPRS",
+        "LONGI",
+        "PLANE",
+        "AUDIO",
+        "MOVDT",
+        "WAITKEY",
+        "SETDLY",
+        "SETST",
+        "MOVVI",
+        "FX29",
+        "FX30",
+        "FX33",
+        "PITCH",
+        "FX55",
+        "FX65",
+        "FX75",
+        "FX85",
+    ];
+    MNEMONICS.contains(&name)
+}
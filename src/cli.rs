@@ -0,0 +1,569 @@
+use std::{error::Error, fmt, ops::RangeInclusive};
+
+use chip_8_rs::{
+    debugger, ExecutionEngine, OpcodePolicy, QuirkProfile, TraceFilter, TraceFormat, WatchAction,
+    WatchKind, Watchpoint,
+};
+
+pub const USAGE: &str = "\
+Usage: chip-8-rs <rom> [options]
+       chip-8-rs disasm <rom> [--origin <address>]
+       chip-8-rs asm <source> --output <rom>
+       chip-8-rs test <rom> [--expect <image> | --expect-hash <hash>] [--reference <trace>]
+                      [options]
+
+Options:
+  --ipf <n>          instructions executed per 60 Hz frame (default 10)
+  --quirks <name>    quirk profile: vip, chip48, schip, xochip (default vip)
+  --headless         run without drawing to the terminal
+  --frames <n>       stop after n frames, prints the final screen in headless mode
+  --seed <n>         seed for the CXNN random number generator, runs with the same seed
+                     are identical
+  --random <name>    CXNN generator: seeded (default) or vip, a model of the COSMAC VIP
+                     interpreter's routine, which movies cannot record
+  --trace            log every executed instruction to stderr with its cycle, frame, PC,
+                     opcode, mnemonic, I, SP and the registers it changed
+  --trace-format <name>
+                     text (default) or jsonl, one JSON object per line
+  --trace-range <range>
+                     only trace instructions at these addresses, range as for --watch
+  --trace-frames <start..end>
+                     only trace these frames, counted from 0
+  --trace-out <path> write the trace to a file instead of stderr
+  --unknown-opcodes <policy>
+                     what unknown opcodes and 0NNN machine code calls do: halt (default)
+                     stops with an error, skip goes on and reports them on stderr,
+                     ignore goes on silently
+  --engine <name>    how frames execute: interpreter (default) or blocks, which translates
+                     straight-line code into blocks for speed, with the same results
+  --audio-out <path> record the buzzer to a WAV file, written when the run ends
+  --watch <range>    log instructions touching memory to stderr, range is an address or
+                     start..end, suffix :r or :w to watch only reads or writes,
+                     can be repeated
+  --load-slot <n>    start from save slot n, saved next to the ROM as <rom>.state<n>
+  --save-slot <n>    write save slot n when the run ends
+  --record <movie>   record every key transition to a movie file, with the ROM hash, quirks
+                     and seed needed to play it back, refused for now as the terminal
+                     frontend reads no keys
+  --play <movie>     play a recorded movie back, its quirks, seed and --ipf win over the
+                     command line, the run stops where the recording did unless --frames
+  --rewind <n>       when the run ends, step back n frames before anything is saved or shown
+  --debug            start in the interactive debugger, type help at the prompt,
+                     with --frames the debugger starts after those frames ran and can
+                     rewind into them
+  --gdb <port>       wait for a GDB remote protocol client on 127.0.0.1:<port>
+  -h, --help         show this message
+
+Subcommands:
+  disasm             print a listing of the ROM instead of running it,
+                     --origin sets the load address (default 0x200)
+  asm                assemble a source file into a ROM
+  test               run the ROM headless until it jumps to itself or --frames (default
+                     600) ran out, then compare the screen with --expect, a text image
+                     with . # + % for pixels, or --expect-hash, exits with 1 and a diff
+                     when they differ, without either prints the image and its hash,
+                     --ipf, --quirks, --seed, --unknown-opcodes and --engine work as for a
+                     normal run. --reference checks every instruction against a trace
+                     written by --trace-out instead, and stops with the expected and actual
+                     state at the first one that differs";
+
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+const DEFAULT_ORIGIN: u16 = 0x200;
+const DEFAULT_TEST_FRAMES: u64 = 600;
+
+#[derive(Debug)]
+pub enum Command {
+    Run(Options),
+    Disassemble {
+        rom_path: String,
+        origin: u16,
+    },
+    Assemble {
+        source_path: String,
+        output_path: String,
+    },
+    Test(TestOptions),
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub rom_path: String,
+    pub instructions_per_frame: u32,
+    pub quirks: QuirkProfile,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub seed: Option<u64>,
+    pub vip_random: bool,
+    /// Set by any of the trace flags
+    pub trace: Option<TraceFilter>,
+    pub trace_format: TraceFormat,
+    pub trace_out: Option<String>,
+    pub opcode_policy: OpcodePolicy,
+    pub execution_engine: ExecutionEngine,
+    pub audio_out: Option<String>,
+    pub debug: bool,
+    pub gdb_port: Option<u16>,
+    pub load_slot: Option<u8>,
+    pub save_slot: Option<u8>,
+    pub rewind: Option<u64>,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub watchpoints: Vec<Watchpoint>,
+}
+
+#[derive(Debug)]
+pub struct TestOptions {
+    pub rom_path: String,
+    pub instructions_per_frame: u32,
+    pub quirks: QuirkProfile,
+    pub frames: u64,
+    pub seed: Option<u64>,
+    pub opcode_policy: OpcodePolicy,
+    pub execution_engine: ExecutionEngine,
+    pub expect: Option<Expectation>,
+    /// Path of a trace to check every instruction against
+    pub reference: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Expectation {
+    /// Path of a text image
+    Image(String),
+    Hash(u64),
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Help,
+    MissingRom,
+    MissingValue(String),
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    UnknownArgument(String),
+    /// Two options that cannot be used together
+    ConflictingOptions(&'static str, &'static str),
+    /// An option this frontend cannot honour yet, and why
+    Unavailable(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{}", USAGE),
+            CliError::MissingRom => write!(f, "no ROM path given"),
+            CliError::MissingValue(flag) => write!(f, "{} expects a value", flag),
+            CliError::InvalidValue { flag, value } => {
+                write!(f, "invalid value '{}' for {}", value, flag)
+            }
+            CliError::UnknownArgument(argument) => write!(f, "unknown argument '{}'", argument),
+            CliError::ConflictingOptions(first, second) => {
+                write!(f, "{} cannot be combined with {}", first, second)
+            }
+            CliError::Unavailable(flag, reason) => {
+                write!(f, "{} is not available yet, {}", flag, reason)
+            }
+        }
+    }
+}
+
+impl Error for CliError {}
+
+impl Command {
+    /// Parse the arguments that follow the program name
+    pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+        let mut arguments = arguments.into_iter().peekable();
+        match arguments.peek().map(String::as_str) {
+            Some("disasm") => {
+                arguments.next();
+                parse_disassemble(arguments)
+            }
+            Some("asm") => {
+                arguments.next();
+                parse_assemble(arguments)
+            }
+            Some("test") => {
+                arguments.next();
+                parse_test(arguments)
+            }
+            _ => Options::parse(arguments).map(Command::Run),
+        }
+    }
+}
+
+fn parse_disassemble(arguments: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut arguments = arguments;
+    let mut rom_path = None;
+    let mut origin = DEFAULT_ORIGIN;
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "--origin" => origin = parse_address(&argument, arguments.next())?,
+            flag if flag.starts_with('-') => return Err(CliError::UnknownArgument(argument)),
+            _ if rom_path.is_none() => rom_path = Some(argument),
+            _ => return Err(CliError::UnknownArgument(argument)),
+        }
+    }
+
+    Ok(Command::Disassemble {
+        rom_path: rom_path.ok_or(CliError::MissingRom)?,
+        origin,
+    })
+}
+
+fn parse_assemble(arguments: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut arguments = arguments;
+    let mut source_path = None;
+    let mut output_path = None;
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "-o" | "--output" => {
+                let value = arguments
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                output_path = Some(value);
+            }
+            flag if flag.starts_with('-') => return Err(CliError::UnknownArgument(argument)),
+            _ if source_path.is_none() => source_path = Some(argument),
+            _ => return Err(CliError::UnknownArgument(argument)),
+        }
+    }
+
+    Ok(Command::Assemble {
+        source_path: source_path.ok_or(CliError::MissingRom)?,
+        output_path: output_path.ok_or_else(|| CliError::MissingValue("--output".to_string()))?,
+    })
+}
+
+fn parse_test(arguments: impl Iterator<Item = String>) -> Result<Command, CliError> {
+    let mut arguments = arguments;
+    let mut rom_path = None;
+    let mut options = TestOptions {
+        rom_path: String::new(),
+        instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+        quirks: QuirkProfile::Vip,
+        frames: DEFAULT_TEST_FRAMES,
+        seed: None,
+        opcode_policy: OpcodePolicy::Halt,
+        execution_engine: ExecutionEngine::Interpreter,
+        expect: None,
+        reference: None,
+    };
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "--ipf" => options.instructions_per_frame = parse_number(&argument, arguments.next())?,
+            "--frames" => options.frames = parse_number(&argument, arguments.next())?,
+            "--seed" => options.seed = Some(parse_number(&argument, arguments.next())?),
+            "--unknown-opcodes" => {
+                options.opcode_policy = parse_opcode_policy(&argument, arguments.next())?
+            }
+            "--engine" => {
+                options.execution_engine = parse_execution_engine(&argument, arguments.next())?
+            }
+            "--quirks" => {
+                let value = arguments
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                options.quirks = value.parse().map_err(|_| CliError::InvalidValue {
+                    flag: argument,
+                    value,
+                })?;
+            }
+            "--expect" => {
+                let value = arguments
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                options.expect = Some(Expectation::Image(value));
+            }
+            "--expect-hash" => {
+                let value = arguments
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                let hash =
+                    u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| {
+                        CliError::InvalidValue {
+                            flag: argument,
+                            value,
+                        }
+                    })?;
+                options.expect = Some(Expectation::Hash(hash));
+            }
+            "--reference" => {
+                let value = arguments
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                options.reference = Some(value);
+            }
+            flag if flag.starts_with('-') => return Err(CliError::UnknownArgument(argument)),
+            _ if rom_path.is_none() => rom_path = Some(argument),
+            _ => return Err(CliError::UnknownArgument(argument)),
+        }
+    }
+
+    options.rom_path = rom_path.ok_or(CliError::MissingRom)?;
+    Ok(Command::Test(options))
+}
+
+impl Options {
+    /// Parse the options of a normal run
+    pub fn parse(arguments: impl IntoIterator<Item = String>) -> Result<Options, CliError> {
+        let mut arguments = arguments.into_iter();
+        let mut rom_path = None;
+        let mut options = Options {
+            rom_path: String::new(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            quirks: QuirkProfile::Vip,
+            headless: false,
+            frames: None,
+            seed: None,
+            vip_random: false,
+            trace: None,
+            trace_format: TraceFormat::Text,
+            trace_out: None,
+            opcode_policy: OpcodePolicy::Halt,
+            execution_engine: ExecutionEngine::Interpreter,
+            audio_out: None,
+            debug: false,
+            gdb_port: None,
+            load_slot: None,
+            save_slot: None,
+            rewind: None,
+            record: None,
+            play: None,
+            watchpoints: Vec::new(),
+        };
+
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-h" | "--help" => return Err(CliError::Help),
+                "--headless" => options.headless = true,
+                "--trace" => {
+                    options.trace.get_or_insert_with(TraceFilter::default);
+                }
+                "--trace-format" => {
+                    options.trace_format = parse_trace_format(&argument, arguments.next())?;
+                    options.trace.get_or_insert_with(TraceFilter::default);
+                }
+                "--trace-range" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    let addresses = parse_address_range(&argument, &value)?;
+                    options
+                        .trace
+                        .get_or_insert_with(TraceFilter::default)
+                        .addresses = addresses;
+                }
+                "--trace-frames" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    let frames = parse_frame_range(&argument, &value)?;
+                    options
+                        .trace
+                        .get_or_insert_with(TraceFilter::default)
+                        .frames = frames;
+                }
+                "--trace-out" => {
+                    options.trace_out = Some(
+                        arguments
+                            .next()
+                            .ok_or_else(|| CliError::MissingValue(argument.clone()))?,
+                    );
+                    options.trace.get_or_insert_with(TraceFilter::default);
+                }
+                "--unknown-opcodes" => {
+                    options.opcode_policy = parse_opcode_policy(&argument, arguments.next())?
+                }
+                "--engine" => {
+                    options.execution_engine = parse_execution_engine(&argument, arguments.next())?
+                }
+                "--debug" => options.debug = true,
+                "--ipf" => {
+                    options.instructions_per_frame = parse_number(&argument, arguments.next())?
+                }
+                "--frames" => options.frames = Some(parse_number(&argument, arguments.next())?),
+                "--gdb" => options.gdb_port = Some(parse_number(&argument, arguments.next())?),
+                "--load-slot" => {
+                    options.load_slot = Some(parse_number(&argument, arguments.next())?)
+                }
+                "--save-slot" => {
+                    options.save_slot = Some(parse_number(&argument, arguments.next())?)
+                }
+                "--rewind" => options.rewind = Some(parse_number(&argument, arguments.next())?),
+                "--seed" => options.seed = Some(parse_number(&argument, arguments.next())?),
+                "--record" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    options.record = Some(value);
+                }
+                "--play" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    options.play = Some(value);
+                }
+                "--random" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    options.vip_random = match value.as_str() {
+                        "seeded" => false,
+                        "vip" => true,
+                        _ => {
+                            return Err(CliError::InvalidValue {
+                                flag: argument,
+                                value,
+                            })
+                        }
+                    };
+                }
+                "--audio-out" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    options.audio_out = Some(value);
+                }
+                "--watch" => {
+                    let watchpoint = parse_watchpoint(&argument, arguments.next())?;
+                    options.watchpoints.push(watchpoint);
+                }
+                "--quirks" => {
+                    let value = arguments
+                        .next()
+                        .ok_or_else(|| CliError::MissingValue(argument.clone()))?;
+                    options.quirks = value.parse().map_err(|_| CliError::InvalidValue {
+                        flag: argument,
+                        value,
+                    })?;
+                }
+                flag if flag.starts_with('-') => return Err(CliError::UnknownArgument(argument)),
+                _ if rom_path.is_none() => rom_path = Some(argument),
+                _ => return Err(CliError::UnknownArgument(argument)),
+            }
+        }
+
+        if options.record.is_some() && options.play.is_some() {
+            return Err(CliError::ConflictingOptions("--record", "--play"));
+        }
+        // movies only keep the seed of the seeded generator
+        if options.vip_random {
+            if options.record.is_some() {
+                return Err(CliError::ConflictingOptions("--random vip", "--record"));
+            }
+            if options.play.is_some() {
+                return Err(CliError::ConflictingOptions("--random vip", "--play"));
+            }
+        }
+        // a movie of a run nobody can press keys in would always be empty
+        if options.record.is_some() {
+            return Err(CliError::Unavailable(
+                "--record",
+                "the terminal frontend reads no keys to record",
+            ));
+        }
+        options.rom_path = rom_path.ok_or(CliError::MissingRom)?;
+        Ok(options)
+    }
+}
+
+/// Addresses are hex with or without a 0x prefix, like at the debugger prompt
+fn parse_address(flag: &str, value: Option<String>) -> Result<u16, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    debugger::parse_address(&value).ok_or_else(|| CliError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+    })
+}
+
+/// `start[..end][:r|w|rw]`, every address in hex
+fn parse_watchpoint(flag: &str, value: Option<String>) -> Result<Watchpoint, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    let invalid = || CliError::InvalidValue {
+        flag: flag.to_string(),
+        value: value.clone(),
+    };
+
+    let (range, kind) = match value.split_once(':') {
+        Some((range, "r")) => (range, WatchKind::Read),
+        Some((range, "w")) => (range, WatchKind::Write),
+        Some((range, "rw")) => (range, WatchKind::ReadWrite),
+        Some(_) => return Err(invalid()),
+        None => (value.as_str(), WatchKind::ReadWrite),
+    };
+    let addresses = parse_address_range(flag, range).map_err(|_| invalid())?;
+    Ok(Watchpoint::new(addresses, kind, WatchAction::Log))
+}
+
+/// `start[..end]`, both in hex
+fn parse_address_range(flag: &str, range: &str) -> Result<RangeInclusive<u16>, CliError> {
+    debugger::parse_address_range(range).ok_or_else(|| CliError::InvalidValue {
+        flag: flag.to_string(),
+        value: range.to_string(),
+    })
+}
+
+/// `start[..end]`, in decimal
+fn parse_frame_range(flag: &str, range: &str) -> Result<RangeInclusive<u64>, CliError> {
+    let (start, end) = range.split_once("..").unwrap_or((range, range));
+    let invalid = || CliError::InvalidValue {
+        flag: flag.to_string(),
+        value: range.to_string(),
+    };
+    let start = parse_number(flag, Some(start.to_string())).map_err(|_| invalid())?;
+    let end = parse_number(flag, Some(end.to_string())).map_err(|_| invalid())?;
+    Ok(start..=end)
+}
+
+fn parse_trace_format(flag: &str, value: Option<String>) -> Result<TraceFormat, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    match value.as_str() {
+        "text" => Ok(TraceFormat::Text),
+        "jsonl" => Ok(TraceFormat::JsonLines),
+        _ => Err(CliError::InvalidValue {
+            flag: flag.to_string(),
+            value,
+        }),
+    }
+}
+
+/// The policies that make sense without code, handlers are for library users
+fn parse_opcode_policy(flag: &str, value: Option<String>) -> Result<OpcodePolicy, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    match value.as_str() {
+        "halt" => Ok(OpcodePolicy::Halt),
+        "skip" => Ok(OpcodePolicy::LogAndSkip),
+        "ignore" => Ok(OpcodePolicy::Ignore),
+        _ => Err(CliError::InvalidValue {
+            flag: flag.to_string(),
+            value,
+        }),
+    }
+}
+
+fn parse_execution_engine(flag: &str, value: Option<String>) -> Result<ExecutionEngine, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    match value.as_str() {
+        "interpreter" => Ok(ExecutionEngine::Interpreter),
+        "blocks" => Ok(ExecutionEngine::Blocks),
+        _ => Err(CliError::InvalidValue {
+            flag: flag.to_string(),
+            value,
+        }),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, CliError> {
+    let value = value.ok_or_else(|| CliError::MissingValue(flag.to_string()))?;
+    value.parse().map_err(|_| CliError::InvalidValue {
+        flag: flag.to_string(),
+        value,
+    })
+}
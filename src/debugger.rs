@@ -0,0 +1,553 @@
+//! Breakpoints, stepping and state inspection on top of a [`Machine`].
+//!
+//! [`Debugger`] can be driven directly from code, or line by line through
+//! [`Debugger::execute_command`] which is what the interactive prompt of the terminal binary
+//! does.
+
+use std::{collections::BTreeSet, fmt::Write, ops::RangeInclusive, path::PathBuf};
+
+use crate::{
+    disassembler,
+    machine::{
+        error::MachineError,
+        machine::Machine,
+        savestate::slot_path,
+        watchpoints::{WatchAction, WatchHit, WatchKind, Watchpoint},
+    },
+    rewind::{Rewind, RewindError},
+};
+
+/// `continue`, `next` and `finish` give up after this many instructions, a ROM stuck in a loop
+/// would hang the prompt otherwise
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 10_000_000;
+
+pub const HELP: &str = "\
+break <addr>     set a breakpoint, alias b
+delete <addr>    clear a breakpoint, alias d
+breakpoints      list breakpoints
+watch <range> [r|w|rw] [log]
+                 stop when an instruction reads or writes (default) memory in
+                 range, addr or start..end, log prints the access and keeps going
+unwatch <range>  clear the watchpoints on exactly range
+watchpoints      list watchpoints
+step [n]         execute n instructions (default 1), alias s
+next             step, running 2NNN calls to completion, alias n
+finish           run until the current subroutine returns with 00EE, alias f
+continue         run until a breakpoint, alias c
+regs             print registers, alias r
+stack            print the stack
+timers           print the timers
+mem <addr> [len] print memory (default 64 bytes), alias x
+dis [addr] [n]   disassemble n instructions (default 10) from addr (default PC)
+screen           draw the screen
+rewind [n]       go back to the start of the frame n frames ago (default 1)
+save <n>         write the machine to save slot n
+load <n>         restore the machine from save slot n
+help             this message, alias h
+quit             leave the debugger, alias q";
+
+/// Why execution stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested number of instructions ran
+    Stepped,
+    /// PC reached a breakpoint
+    Breakpoint(u16),
+    /// An instruction touched memory under a stopping watchpoint, PC is already past it
+    Watchpoint(WatchHit),
+    /// A `next` or `finish` got where it was going
+    Returned,
+    /// The program ran 00FD
+    Exited,
+    /// The instruction limit was hit before anything else happened
+    InstructionLimit,
+    /// The instruction at PC failed and was not executed
+    Fault(MachineError),
+}
+
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<u16>,
+    instructions_per_frame: u32,
+    // instructions executed since the last frame tick
+    frame_instructions: u32,
+    instruction_limit: u64,
+    // hits of logging watchpoints, waiting to be printed
+    logged_hits: Vec<WatchHit>,
+    // save slots are files next to this ROM
+    rom_path: Option<PathBuf>,
+    rewind: Rewind,
+}
+
+impl Debugger {
+    /// Timers tick every `instructions_per_frame` instructions, as they would in a normal run
+    pub fn new(machine: Machine, instructions_per_frame: u32) -> Self {
+        let mut rewind = Rewind::default();
+        rewind.record(&machine);
+        Self {
+            machine,
+            breakpoints: BTreeSet::new(),
+            instructions_per_frame: instructions_per_frame.max(1),
+            frame_instructions: 0,
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+            logged_hits: Vec::new(),
+            rom_path: None,
+            rewind,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    pub fn set_instruction_limit(&mut self, instruction_limit: u64) {
+        self.instruction_limit = instruction_limit;
+    }
+
+    /// Keep the history of a run that happened before debugging started, `rewind` must have
+    /// recorded the start of the machine's current frame
+    pub fn set_rewind(&mut self, rewind: Rewind) {
+        self.rewind = rewind;
+    }
+
+    /// Enables the save and load commands, slots are stored next to `rom_path`
+    pub fn set_rom_path(&mut self, rom_path: impl Into<PathBuf>) {
+        self.rom_path = Some(rom_path.into());
+    }
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns false if there was no breakpoint at `address`
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Hits of logging watchpoints since the last call, stopping ones are returned as a
+    /// [`StopReason`] instead
+    pub fn take_logged_hits(&mut self) -> Vec<WatchHit> {
+        std::mem::take(&mut self.logged_hits)
+    }
+}
+
+impl Debugger {
+    /// Execute `count` instructions, stopping early at a breakpoint
+    pub fn step(&mut self, count: u64) -> StopReason {
+        for executed in 0..count {
+            if executed > 0 && self.at_breakpoint() {
+                return StopReason::Breakpoint(self.machine.read_program_counter());
+            }
+            if let Some(reason) = self.execute_one() {
+                return reason;
+            }
+        }
+        StopReason::Stepped
+    }
+
+    /// Step one instruction, a 2NNN call is run until it returns to the next instruction
+    pub fn step_over(&mut self) -> StopReason {
+        let program_counter = self.machine.read_program_counter();
+        let opcode = self.machine.read_opcode(program_counter);
+        if opcode & 0xF000 != 0x2000 {
+            return self.step(1);
+        }
+
+        let return_address = program_counter.wrapping_add(2);
+        let stack_pointer = self.machine.read_stack_pointer();
+        self.run_until(|machine| {
+            machine.read_program_counter() == return_address
+                && machine.read_stack_pointer() == stack_pointer
+        })
+    }
+
+    /// Run until the current subroutine returns, the 00EE is executed before stopping
+    pub fn finish(&mut self) -> StopReason {
+        let stack_pointer = self.machine.read_stack_pointer();
+        let mut returned = false;
+        self.run_until(|machine| {
+            // the 00EE was executed in the previous round
+            if returned {
+                return true;
+            }
+            let opcode = machine.read_opcode(machine.read_program_counter());
+            returned = opcode == 0x00EE && machine.read_stack_pointer() == stack_pointer;
+            false
+        })
+    }
+
+    /// Run until a breakpoint is hit
+    pub fn resume(&mut self) -> StopReason {
+        self.run_until(|_| false)
+    }
+
+    // `done` is checked before every instruction, the one at PC when starting always runs
+    fn run_until(&mut self, mut done: impl FnMut(&Machine) -> bool) -> StopReason {
+        for executed in 0..self.instruction_limit {
+            if executed > 0 {
+                if done(&self.machine) {
+                    return StopReason::Returned;
+                }
+                if self.at_breakpoint() {
+                    return StopReason::Breakpoint(self.machine.read_program_counter());
+                }
+            } else {
+                done(&self.machine);
+            }
+            if let Some(reason) = self.execute_one() {
+                return reason;
+            }
+        }
+        StopReason::InstructionLimit
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        self.breakpoints
+            .contains(&self.machine.read_program_counter())
+    }
+
+    fn execute_one(&mut self) -> Option<StopReason> {
+        if self.machine.has_exited() {
+            return Some(StopReason::Exited);
+        }
+        // a draw under the display wait quirk blocks until the next frame, skip ahead to it
+        if self.machine.is_waiting_for_vblank() {
+            self.tick_frame();
+        }
+
+        if let Err(error) = self.machine.exec() {
+            return Some(StopReason::Fault(error));
+        }
+        self.frame_instructions += 1;
+        if self.frame_instructions >= self.instructions_per_frame {
+            self.tick_frame();
+        }
+
+        let mut stopped_by = None;
+        for hit in self.machine.take_watch_hits() {
+            match hit.action {
+                WatchAction::Stop => stopped_by = stopped_by.or(Some(hit)),
+                WatchAction::Log => self.logged_hits.push(hit),
+            }
+        }
+        stopped_by.map(StopReason::Watchpoint)
+    }
+
+    fn tick_frame(&mut self) {
+        self.machine.tick_frame();
+        self.frame_instructions = 0;
+        self.rewind.record(&self.machine);
+    }
+
+    /// Go back to the start of the frame `frames` frames before the current one, returns the
+    /// frame now current
+    pub fn rewind(&mut self, frames: u64) -> Result<u64, RewindError> {
+        let frame = self.machine.read_frame_count().saturating_sub(frames);
+        self.rewind
+            .rewind_to(&mut self.machine, frame, self.instructions_per_frame)?;
+        self.frame_instructions = 0;
+        self.rewind.record(&self.machine);
+        Ok(frame)
+    }
+}
+
+impl Debugger {
+    /// V0 to VF, I, PC and SP
+    pub fn registers(&self) -> String {
+        let mut output = String::new();
+        for register in 0..16 {
+            let value = self.machine.read_general_purpouse_registers(register);
+            let separator = if register % 8 == 7 { "\n" } else { " " };
+            let _ = write!(output, "V{:X}={:02X}{}", register, value, separator);
+        }
+        let _ = write!(
+            output,
+            "I={:#05X} PC={:#05X} SP={}",
+            self.machine.read_index_register(),
+            self.machine.read_program_counter(),
+            self.machine.read_stack_pointer()
+        );
+        output
+    }
+
+    /// Addresses of the 2NNN calls on the stack, innermost last
+    pub fn stack(&self) -> String {
+        let stack = self.machine.read_stack();
+        let depth = self.machine.read_stack_pointer() as usize;
+        if depth == 0 {
+            return "stack is empty".to_string();
+        }
+        stack[..depth]
+            .iter()
+            .enumerate()
+            .map(|(level, address)| format!("#{} {:#05X}", level + 1, address))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn timers(&self) -> String {
+        format!(
+            "DT={} ST={} frame={}",
+            self.machine.read_delay_timer(),
+            self.machine.read_sound_timer(),
+            self.machine.read_frame_count()
+        )
+    }
+
+    /// Hex dump of `length` bytes from `start`, 16 per line
+    pub fn memory(&self, start: u16, length: u16) -> String {
+        let end = (start as usize + length as usize).min(self.machine.ram_size());
+        (start as usize..end)
+            .step_by(16)
+            .map(|line_start| {
+                let bytes: Vec<String> = (line_start..(line_start + 16).min(end))
+                    .filter_map(|address| self.machine.read_ram(address as u16).ok())
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect();
+                format!("{:#06X}  {}", line_start, bytes.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Listing of `count` instructions starting at `start`
+    pub fn disassemble(&self, start: u16, count: usize) -> String {
+        let end = (start as usize + count * 4).min(self.machine.ram_size());
+        let bytes: Vec<u8> = (start as usize..end)
+            .filter_map(|address| self.machine.read_ram(address as u16).ok())
+            .collect();
+        disassembler::disassemble(&bytes, start)
+            .iter()
+            .take(count)
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The instruction at PC
+    pub fn location(&self) -> String {
+        self.disassemble(self.machine.read_program_counter(), 1)
+    }
+}
+
+impl Debugger {
+    /// Run one prompt command and return what should be printed
+    pub fn execute_command(&mut self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return String::new();
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        match (command, arguments.as_slice()) {
+            ("b" | "break", [address]) => match parse_address(address) {
+                Some(address) => {
+                    self.add_breakpoint(address);
+                    format!("breakpoint at {:#05X}", address)
+                }
+                None => format!("invalid address '{}'", address),
+            },
+            ("d" | "delete", [address]) => match parse_address(address) {
+                Some(address) if self.remove_breakpoint(address) => {
+                    format!("deleted breakpoint at {:#05X}", address)
+                }
+                Some(address) => format!("no breakpoint at {:#05X}", address),
+                None => format!("invalid address '{}'", address),
+            },
+            ("breakpoints", []) => {
+                let breakpoints: Vec<String> = self
+                    .breakpoints()
+                    .map(|address| format!("{:#05X}", address))
+                    .collect();
+                match breakpoints.is_empty() {
+                    true => "no breakpoints".to_string(),
+                    false => breakpoints.join("\n"),
+                }
+            }
+            ("watch", [range, options @ ..]) => self.watch_command(range, options),
+            ("unwatch", [range]) => match parse_address_range(range) {
+                Some(range) if self.machine.remove_watchpoint(&range) => {
+                    format!("deleted watchpoints on {}", format_range(&range))
+                }
+                Some(range) => format!("no watchpoint on {}", format_range(&range)),
+                None => format!("invalid range '{}'", range),
+            },
+            ("watchpoints", []) => {
+                let watchpoints: Vec<String> = self
+                    .machine
+                    .read_watchpoints()
+                    .iter()
+                    .map(|watchpoint| {
+                        format!(
+                            "{} {:?} {:?}",
+                            format_range(&watchpoint.addresses),
+                            watchpoint.kind,
+                            watchpoint.action
+                        )
+                    })
+                    .collect();
+                match watchpoints.is_empty() {
+                    true => "no watchpoints".to_string(),
+                    false => watchpoints.join("\n"),
+                }
+            }
+            ("s" | "step", []) => self.report(|debugger| debugger.step(1)),
+            ("s" | "step", [count]) => match count.parse() {
+                Ok(count) => self.report(|debugger| debugger.step(count)),
+                Err(_) => format!("invalid count '{}'", count),
+            },
+            ("n" | "next", []) => self.report(Debugger::step_over),
+            ("f" | "finish", []) => self.report(Debugger::finish),
+            ("c" | "continue", []) => self.report(Debugger::resume),
+            ("r" | "regs", []) => self.registers(),
+            ("stack", []) => self.stack(),
+            ("timers", []) => self.timers(),
+            ("x" | "mem", [address]) => self.memory_command(address, "64"),
+            ("x" | "mem", [address, length]) => self.memory_command(address, length),
+            ("dis", []) => self.disassemble(self.machine.read_program_counter(), 10),
+            ("dis", [address]) => self.disassemble_command(address, "10"),
+            ("dis", [address, count]) => self.disassemble_command(address, count),
+            ("screen", []) => {
+                self.machine.screen.draw();
+                String::new()
+            }
+            ("rewind", []) => self.rewind_command("1"),
+            ("rewind", [frames]) => self.rewind_command(frames),
+            ("save", [slot]) => self.slot_command(slot, true),
+            ("load", [slot]) => self.slot_command(slot, false),
+            ("h" | "help", []) => HELP.to_string(),
+            _ => format!("unknown command '{}', try help", line.trim()),
+        }
+    }
+
+    fn report(&mut self, run: impl FnOnce(&mut Debugger) -> StopReason) -> String {
+        let stop_reason = run(self);
+        let mut output: String = self
+            .take_logged_hits()
+            .iter()
+            .map(|hit| format!("watch {}\n", hit))
+            .collect();
+        let reason = match stop_reason {
+            StopReason::Stepped | StopReason::Returned => String::new(),
+            StopReason::Breakpoint(address) => format!("breakpoint at {:#05X}\n", address),
+            StopReason::Watchpoint(hit) => format!("watchpoint {}\n", hit),
+            StopReason::Exited => "program exited\n".to_string(),
+            StopReason::Fault(error) => format!("error: {}\n", error),
+            StopReason::InstructionLimit => {
+                format!("stopped after {} instructions\n", self.instruction_limit)
+            }
+        };
+        output.push_str(&reason);
+        output.push_str(&self.location());
+        output
+    }
+
+    fn rewind_command(&mut self, frames: &str) -> String {
+        let Ok(frames) = frames.parse() else {
+            return format!("invalid frame count '{}'", frames);
+        };
+        match self.rewind(frames) {
+            Ok(frame) => format!("rewound to frame {}\n{}", frame, self.location()),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    fn slot_command(&mut self, slot: &str, save: bool) -> String {
+        let Some(rom_path) = &self.rom_path else {
+            return "save slots need a ROM path".to_string();
+        };
+        let Ok(slot) = slot.parse() else {
+            return format!("invalid slot '{}'", slot);
+        };
+        let path = slot_path(rom_path, slot);
+        let result = match save {
+            true => self.machine.save_state_to_file(&path),
+            false => self.machine.load_state_from_file(&path),
+        };
+        match (result, save) {
+            (Ok(()), true) => format!("saved slot {} to {}", slot, path.display()),
+            (Ok(()), false) => format!("loaded slot {}\n{}", slot, self.location()),
+            (Err(error), _) => format!("slot {}: {}", slot, error),
+        }
+    }
+
+    fn watch_command(&mut self, range: &str, options: &[&str]) -> String {
+        let Some(addresses) = parse_address_range(range) else {
+            return format!("invalid range '{}'", range);
+        };
+        let mut kind = WatchKind::ReadWrite;
+        let mut action = WatchAction::Stop;
+        for option in options {
+            match *option {
+                "r" => kind = WatchKind::Read,
+                "w" => kind = WatchKind::Write,
+                "rw" => kind = WatchKind::ReadWrite,
+                "log" => action = WatchAction::Log,
+                _ => return format!("invalid watch option '{}'", option),
+            }
+        }
+
+        let output = format!(
+            "watching {} {:?} {:?}",
+            format_range(&addresses),
+            kind,
+            action
+        );
+        self.machine
+            .add_watchpoint(Watchpoint::new(addresses, kind, action));
+        output
+    }
+
+    fn memory_command(&self, address: &str, length: &str) -> String {
+        match (parse_address(address), length.parse()) {
+            (Some(address), Ok(length)) => self.memory(address, length),
+            _ => format!("invalid range '{} {}'", address, length),
+        }
+    }
+
+    fn disassemble_command(&self, address: &str, count: &str) -> String {
+        match (parse_address(address), count.parse()) {
+            (Some(address), Ok(count)) => self.disassemble(address, count),
+            _ => format!("invalid range '{} {}'", address, count),
+        }
+    }
+}
+
+/// Addresses are hex with or without a 0x prefix, the prompt and the command line both read
+/// them this way
+pub fn parse_address(text: &str) -> Option<u16> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// A single address or `start..end`, both ends included
+pub fn parse_address_range(text: &str) -> Option<RangeInclusive<u16>> {
+    match text.split_once("..") {
+        Some((start, end)) => Some(parse_address(start)?..=parse_address(end)?),
+        None => parse_address(text).map(|address| address..=address),
+    }
+}
+
+fn format_range(range: &RangeInclusive<u16>) -> String {
+    match range.start() == range.end() {
+        true => format!("{:#05X}", range.start()),
+        false => format!("{:#05X}..{:#05X}", range.start(), range.end()),
+    }
+}
@@ -0,0 +1,306 @@
+//! Differential execution: run a ROM and check every instruction against a reference trace,
+//! written by `--trace-out` of an earlier build or converted from another emulator, stopping
+//! at the first one that does something else.
+
+use std::{error::Error, fmt, fs, io, path::Path, str::FromStr};
+
+use crate::machine::{
+    error::MachineError,
+    machine::{Machine, StepOutcome},
+    trace::{TraceFilter, TraceRecord},
+};
+
+#[derive(Debug)]
+pub enum DiffError {
+    Io(io::Error),
+    /// A line of the reference is not a trace record, lines count from 1
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::Io(error) => write!(f, "{}", error),
+            DiffError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for DiffError {}
+
+impl From<io::Error> for DiffError {
+    fn from(error: io::Error) -> Self {
+        DiffError::Io(error)
+    }
+}
+
+/// The records a run is checked against, in either trace format. Records can be missing,
+/// as with a filtered trace, only the cycles that are there get checked
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReferenceTrace {
+    pub records: Vec<TraceRecord>,
+}
+
+impl ReferenceTrace {
+    pub fn load(path: impl AsRef<Path>) -> Result<ReferenceTrace, DiffError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl FromStr for ReferenceTrace {
+    type Err = DiffError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let records = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                line.parse().map_err(|error| DiffError::Parse {
+                    line: index + 1,
+                    message: format!("{}", error),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ReferenceTrace { records })
+    }
+}
+
+/// The first instruction that did something else than the reference says
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub expected: TraceRecord,
+    pub actual: TraceRecord,
+    /// V0 to VF as the reference has them: the state before the instruction with the
+    /// reference's changes applied, or [`Divergence::actual_registers`] when the reference
+    /// left its changes out and registers are not compared
+    pub expected_registers: [u8; 16],
+    /// V0 to VF after the instruction
+    pub actual_registers: [u8; 16],
+}
+
+impl Divergence {
+    fn state(registers: &[u8; 16], record: &TraceRecord) -> String {
+        let registers: Vec<String> = registers
+            .iter()
+            .enumerate()
+            .map(|(register, value)| format!("V{:X}={:02X}", register, value))
+            .collect();
+        format!(
+            "{} I={:04X} SP={:X}",
+            registers.join(" "),
+            record.index_register,
+            record.stack_pointer
+        )
+    }
+
+    fn writes(record: &TraceRecord) -> String {
+        match &record.memory_writes {
+            None => "not recorded".to_string(),
+            Some(memory_writes) if memory_writes.is_empty() => "none".to_string(),
+            Some(memory_writes) => memory_writes
+                .iter()
+                .map(|(address, value)| format!("[{:04X}]={:02X}", address, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (expected, actual) = (&self.expected, &self.actual);
+        writeln!(
+            f,
+            "divergence at cycle {}, frame {}: {:04X} {:04X} {}",
+            actual.cycle, actual.frame, actual.program_counter, actual.opcode, actual.mnemonic
+        )?;
+        if (expected.program_counter, expected.opcode) != (actual.program_counter, actual.opcode) {
+            writeln!(
+                f,
+                "  expected instruction {:04X} {:04X} {}",
+                expected.program_counter, expected.opcode, expected.mnemonic
+            )?;
+        }
+        if expected.frame != actual.frame {
+            writeln!(f, "  expected frame {}", expected.frame)?;
+        }
+        writeln!(
+            f,
+            "  expected {}",
+            Divergence::state(&self.expected_registers, expected)
+        )?;
+        writeln!(
+            f,
+            "  actual   {}",
+            Divergence::state(&self.actual_registers, actual)
+        )?;
+
+        let mut differs: Vec<String> = (0..16)
+            .filter(|&register| {
+                self.expected_registers[register] != self.actual_registers[register]
+            })
+            .map(|register| format!("V{:X}", register))
+            .collect();
+        if expected.index_register != actual.index_register {
+            differs.push("I".to_string());
+        }
+        if expected.stack_pointer != actual.stack_pointer {
+            differs.push("SP".to_string());
+        }
+        if !differs.is_empty() {
+            writeln!(f, "  differs  {}", differs.join(" "))?;
+        }
+        if expected.memory_writes.is_some() && expected.memory_writes != actual.memory_writes {
+            writeln!(f, "  memory writes")?;
+            writeln!(f, "  expected {}", Divergence::writes(expected))?;
+            writeln!(f, "  actual   {}", Divergence::writes(actual))?;
+        }
+        Ok(())
+    }
+}
+
+/// How a differential run ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DiffEnd {
+    /// Every reference record was matched
+    Matched {
+        compared: usize,
+    },
+    Diverged(Box<Divergence>),
+    /// The frames ran out or the program exited before the reference did
+    Incomplete {
+        compared: usize,
+        remaining: usize,
+    },
+    Failed {
+        error: MachineError,
+        compared: usize,
+    },
+}
+
+/// Runs a machine one instruction at a time like [`Machine::run_frame`] with the interpreter,
+/// comparing each against the reference
+pub struct Differ {
+    machine: Machine,
+    reference: ReferenceTrace,
+    // index of the next reference record to check
+    next: usize,
+    instructions_per_frame: u32,
+}
+
+impl Differ {
+    /// Tracing is switched on from here, the reference's cycle 0 is the machine's next instruction
+    pub fn new(
+        mut machine: Machine,
+        reference: ReferenceTrace,
+        instructions_per_frame: u32,
+    ) -> Self {
+        machine.set_trace(Some(TraceFilter::default()));
+        Self {
+            machine,
+            reference,
+            next: 0,
+            instructions_per_frame,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    pub fn run(&mut self, max_frames: u64) -> DiffEnd {
+        for _ in 0..max_frames {
+            for _ in 0..self.instructions_per_frame {
+                if self.next == self.reference.records.len() {
+                    return DiffEnd::Matched {
+                        compared: self.next,
+                    };
+                }
+                if self.machine.has_exited() {
+                    return self.incomplete();
+                }
+                if let Some(divergence) = self.step() {
+                    return divergence;
+                }
+            }
+            self.machine.tick_frame();
+        }
+        match self.next == self.reference.records.len() {
+            true => DiffEnd::Matched {
+                compared: self.next,
+            },
+            false => self.incomplete(),
+        }
+    }
+
+    // None while everything matches
+    fn step(&mut self) -> Option<DiffEnd> {
+        let before: [u8; 16] =
+            std::array::from_fn(|register| self.machine.read_general_purpouse_registers(register));
+        match self.machine.exec() {
+            Ok(StepOutcome::Executed) => {}
+            Ok(_) => return None,
+            Err(error) => {
+                return Some(DiffEnd::Failed {
+                    error,
+                    compared: self.next,
+                })
+            }
+        }
+
+        let actual = self.machine.take_trace().pop()?;
+        let expected = &self.reference.records[self.next];
+        // a filtered reference skips cycles
+        if expected.cycle > actual.cycle {
+            return None;
+        }
+        self.next += 1;
+
+        let actual_registers: [u8; 16] =
+            std::array::from_fn(|register| self.machine.read_general_purpouse_registers(register));
+        // a reference without changes or writes says nothing about them, rather than that
+        // there were none
+        let expected_registers = match &expected.changed_registers {
+            Some(changed_registers) => {
+                let mut expected_registers = before;
+                for (register, value) in changed_registers {
+                    expected_registers[*register as usize & 0xF] = *value;
+                }
+                expected_registers
+            }
+            None => actual_registers,
+        };
+        let writes_match =
+            expected.memory_writes.is_none() || expected.memory_writes == actual.memory_writes;
+        let matches = (expected.frame, expected.program_counter, expected.opcode)
+            == (actual.frame, actual.program_counter, actual.opcode)
+            && expected_registers == actual_registers
+            && (expected.index_register, expected.stack_pointer)
+                == (actual.index_register, actual.stack_pointer)
+            && writes_match;
+        match matches {
+            true => None,
+            false => Some(DiffEnd::Diverged(Box::new(Divergence {
+                expected: expected.clone(),
+                actual,
+                expected_registers,
+                actual_registers,
+            }))),
+        }
+    }
+
+    fn incomplete(&self) -> DiffEnd {
+        DiffEnd::Incomplete {
+            compared: self.next,
+            remaining: self.reference.records.len() - self.next,
+        }
+    }
+}
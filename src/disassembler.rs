@@ -0,0 +1,103 @@
+//! Turns ROM images back into readable listings.
+//!
+//! Mnemonics are those of [`Operation::mnemonic`], the names of the variants without the `Op`
+//! prefix and opcode pattern, `Op1nnnJmp` is `JMP` and `Op8xy4Add` is `ADD`. Variants that only
+//! carry their opcode, like `OpFx33`, use the opcode pattern itself. Words that do not decode
+//! are listed as `DW` data, a trailing odd byte as `DB`.
+
+use std::fmt;
+
+use crate::machine::instructions::{decode, Operation};
+
+/// One row of a listing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    /// The bytes this line covers, 4 for F000 NNNN, 1 for a trailing byte, 2 otherwise
+    pub raw: Vec<u8>,
+    /// None when the bytes are data
+    pub operation: Option<Operation>,
+    pub mnemonic: String,
+    pub operands: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let raw: String = self
+            .raw
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let instruction = match self.operands.is_empty() {
+            true => self.mnemonic.clone(),
+            false => format!("{} {}", self.mnemonic, self.operands),
+        };
+        write!(f, "{:#05X}  {:<8}  {}", self.address, raw, instruction)
+    }
+}
+
+/// Disassemble `rom` as if it was loaded at `origin`, normally 0x200
+pub fn disassemble(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let address = origin.wrapping_add(offset as u16);
+        if offset + 1 == rom.len() {
+            lines.push(data_line(address, &rom[offset..]));
+            break;
+        }
+
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let line = match decode(opcode) {
+            // the address of F000 is the next word, a truncated one means this is data
+            Ok(Operation::OpF000LongI { .. }) if offset + 4 > rom.len() => {
+                data_line(address, &rom[offset..offset + 2])
+            }
+            Ok(Operation::OpF000LongI { .. }) => {
+                let address_nnnn = u16::from_be_bytes([rom[offset + 2], rom[offset + 3]]);
+                instruction_line(
+                    address,
+                    &rom[offset..offset + 4],
+                    Operation::OpF000LongI {
+                        address: address_nnnn,
+                    },
+                )
+            }
+            Ok(operation) => instruction_line(address, &rom[offset..offset + 2], operation),
+            Err(_) => data_line(address, &rom[offset..offset + 2]),
+        };
+        offset += line.raw.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn instruction_line(address: u16, raw: &[u8], operation: Operation) -> Line {
+    let (mnemonic, operands) = operation.mnemonic();
+    Line {
+        address,
+        raw: raw.to_vec(),
+        operation: Some(operation),
+        mnemonic: mnemonic.to_string(),
+        operands,
+    }
+}
+
+fn data_line(address: u16, raw: &[u8]) -> Line {
+    let (mnemonic, operands) = match raw {
+        [byte] => ("DB", format!("{:#04X}", byte)),
+        _ => (
+            "DW",
+            format!("{:#06X}", u16::from_be_bytes([raw[0], raw[1]])),
+        ),
+    };
+    Line {
+        address,
+        raw: raw.to_vec(),
+        operation: None,
+        mnemonic: mnemonic.to_string(),
+        operands,
+    }
+}
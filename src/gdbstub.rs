@@ -0,0 +1,482 @@
+//! GDB remote serial protocol stub, lets standard debugger front ends attach to a
+//! [`Machine`](crate::Machine) over a TCP socket.
+//!
+//! Registers are numbered V0 to VF (0 to 15, 8 bits), I (16, 16 bits), PC (17, 16 bits),
+//! SP (18, 8 bits), then the delay and sound timers (19 and 20, 8 bits), all little endian.
+//! The layout is also served as a target description through `qXfer:features:read`, for the
+//! `chip8` architecture.
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::{
+    debugger::{Debugger, StopReason},
+    machine::error::MachineError,
+};
+
+const REGISTER_COUNT: usize = 21;
+const INDEX_REGISTER: usize = 16;
+const PROGRAM_COUNTER: usize = 17;
+const STACK_POINTER: usize = 18;
+const DELAY_TIMER: usize = 19;
+const SOUND_TIMER: usize = 20;
+
+// instructions run between checks for an interrupt from the client while continuing
+const CONTINUE_SLICE: u64 = 10_000;
+
+// named in the target description, so clients know what they attached to without a
+// `set architecture` first
+const ARCHITECTURE: &str = "chip8";
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+pub struct GdbStub {
+    debugger: Debugger,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger }
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn into_debugger(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Wait for a single client on `address` and serve it until it detaches or disconnects
+    pub fn listen(&mut self, address: impl ToSocketAddrs) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serve an already connected client until it detaches, kills or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        // acknowledgements and replies are tiny, waiting to batch them stalls every round trip
+        stream.set_nodelay(true)?;
+        let mut connection = Connection { stream };
+        while let Some(packet) = connection.read_packet()? {
+            let packet = match packet {
+                Packet::Command(packet) => packet,
+                // nothing is running, just report where we are
+                Packet::Interrupt => {
+                    connection.write_packet(&stop_reply(SIGTRAP))?;
+                    continue;
+                }
+            };
+
+            match self.handle(&packet, &mut connection)? {
+                Some(reply) => connection.write_packet(&reply)?,
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    // None closes the connection
+    fn handle(&mut self, packet: &str, connection: &mut Connection) -> io::Result<Option<String>> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => stop_reply(SIGTRAP),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "Z" => self.breakpoint(arguments, true),
+            "z" => self.breakpoint(arguments, false),
+            "s" => self.step(arguments),
+            "c" => self.resume(arguments, connection)?,
+            "H" => "OK".to_string(),
+            "D" => {
+                connection.write_packet("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            "q" => self.query(arguments),
+            // everything else, including vCont, is unsupported, gdb falls back to the basics
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+".to_string();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_pair(range, ',') {
+                Some((offset, length)) => transfer(&target_description(), offset, length),
+                None => "E01".to_string(),
+            };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+impl GdbStub {
+    fn read_registers(&self) -> String {
+        (0..REGISTER_COUNT)
+            .map(|register| encode_register(register, self.register(register)))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let mut offset = 0;
+        for register in 0..REGISTER_COUNT {
+            let digits = register_size(register) * 2;
+            let Some(value) = data
+                .get(offset..offset + digits)
+                .and_then(|hex| decode_register(register, hex))
+            else {
+                return "E01".to_string();
+            };
+            self.set_register(register, value);
+            offset += digits;
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, arguments: &str) -> String {
+        match usize::from_str_radix(arguments, 16) {
+            Ok(register) if register < REGISTER_COUNT => {
+                encode_register(register, self.register(register))
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let Some((register, hex)) = arguments.split_once('=') else {
+            return "E01".to_string();
+        };
+        let register = match usize::from_str_radix(register, 16) {
+            Ok(register) if register < REGISTER_COUNT => register,
+            _ => return "E01".to_string(),
+        };
+        match decode_register(register, hex) {
+            Some(value) => {
+                self.set_register(register, value);
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    fn register(&self, register: usize) -> u16 {
+        let machine = self.debugger.machine();
+        match register {
+            INDEX_REGISTER => machine.read_index_register(),
+            PROGRAM_COUNTER => machine.read_program_counter(),
+            STACK_POINTER => machine.read_stack_pointer() as u16,
+            DELAY_TIMER => machine.read_delay_timer() as u16,
+            SOUND_TIMER => machine.read_sound_timer() as u16,
+            _ => machine.read_general_purpouse_registers(register) as u16,
+        }
+    }
+
+    fn set_register(&mut self, register: usize, value: u16) {
+        let machine = self.debugger.machine_mut();
+        match register {
+            INDEX_REGISTER => machine.write_to_index_register(value),
+            PROGRAM_COUNTER => machine.write_to_program_counter(value),
+            STACK_POINTER => machine.write_to_stack_pointer(value as u8),
+            DELAY_TIMER => machine.write_to_delay_timer(value as u8),
+            SOUND_TIMER => machine.write_to_sound_timer(value as u8),
+            _ => machine.write_to_general_purpouse_registers(register, value as u8),
+        }
+    }
+}
+
+impl GdbStub {
+    fn read_memory(&self, arguments: &str) -> String {
+        let machine = self.debugger.machine();
+        match parse_pair(arguments, ',') {
+            Some((address, length)) if address.saturating_add(length) <= machine.ram_size() => {
+                (address..address + length)
+                    .filter_map(|address| machine.read_ram(address as u16).ok())
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let Some((range, hex)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        let machine = self.debugger.machine_mut();
+        match (parse_pair(range, ','), decode_hex(hex)) {
+            (Some((address, length)), Some(bytes))
+                if bytes.len() == length && address <= u16::MAX as usize =>
+            {
+                match machine.copy_to_ram(bytes, address as u16) {
+                    Ok(()) => "OK".to_string(),
+                    Err(_) => "E01".to_string(),
+                }
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    // only software breakpoints, type 0, are supported
+    fn breakpoint(&mut self, arguments: &str, insert: bool) -> String {
+        let mut fields = arguments.split(',');
+        let (Some("0"), Some(address)) = (fields.next(), fields.next()) else {
+            return String::new();
+        };
+        let Ok(address) = u16::from_str_radix(address, 16) else {
+            return "E01".to_string();
+        };
+        match insert {
+            true => self.debugger.add_breakpoint(address),
+            false => {
+                self.debugger.remove_breakpoint(address);
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn step(&mut self, arguments: &str) -> String {
+        if let Err(reply) = self.resume_at(arguments) {
+            return reply;
+        }
+        match self.debugger.step(1) {
+            StopReason::Exited => exit_reply(),
+            StopReason::Fault(error) => fault_reply(error),
+            _ => stop_reply(SIGTRAP),
+        }
+    }
+
+    fn resume(&mut self, arguments: &str, connection: &mut Connection) -> io::Result<String> {
+        if let Err(reply) = self.resume_at(arguments) {
+            return Ok(reply);
+        }
+
+        // the first instruction always runs, even on a breakpoint, so continuing from one works
+        self.debugger.set_instruction_limit(CONTINUE_SLICE);
+        let mut reason = self.debugger.resume();
+        while reason == StopReason::InstructionLimit {
+            if connection.poll_interrupt()? {
+                return Ok(stop_reply(SIGINT));
+            }
+            let program_counter = self.debugger.machine().read_program_counter();
+            if self
+                .debugger
+                .breakpoints()
+                .any(|address| address == program_counter)
+            {
+                return Ok(stop_reply(SIGTRAP));
+            }
+            reason = self.debugger.resume();
+        }
+
+        Ok(match reason {
+            StopReason::Exited => exit_reply(),
+            StopReason::Fault(error) => fault_reply(error),
+            _ => stop_reply(SIGTRAP),
+        })
+    }
+
+    // `s` and `c` may carry an address to resume from
+    fn resume_at(&mut self, arguments: &str) -> Result<(), String> {
+        if arguments.is_empty() {
+            return Ok(());
+        }
+        let address = u16::from_str_radix(arguments, 16).map_err(|_| "E01".to_string())?;
+        self.debugger
+            .machine_mut()
+            .write_to_program_counter(address);
+        Ok(())
+    }
+}
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    // None once the client hung up
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            match byte {
+                0x03 => return Ok(Some(Packet::Interrupt)),
+                b'$' => {}
+                // acknowledgements and noise between packets
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if expected != Some(checksum_of(&data)) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(Packet::Command(
+                String::from_utf8_lossy(&data).into_owned(),
+            )));
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+
+    // whether the client sent a break while the machine runs
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let read = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(1) => Ok(byte[0] == 0x03),
+            // hung up, the next blocking read notices
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, byte| sum.wrapping_add(*byte))
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn exit_reply() -> String {
+    "W00".to_string()
+}
+
+// the instruction did not run, PC still points at it
+fn fault_reply(error: MachineError) -> String {
+    match error {
+        MachineError::InvalidOpcode { .. } => stop_reply(SIGILL),
+        _ => stop_reply(SIGSEGV),
+    }
+}
+
+fn register_size(register: usize) -> usize {
+    match register {
+        INDEX_REGISTER | PROGRAM_COUNTER => 2,
+        _ => 1,
+    }
+}
+
+fn encode_register(register: usize, value: u16) -> String {
+    value.to_le_bytes()[..register_size(register)]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn decode_register(register: usize, hex: &str) -> Option<u16> {
+    let bytes = decode_hex(hex)?;
+    match bytes.as_slice() {
+        [low] if register_size(register) == 1 => Some(*low as u16),
+        [low, high] if register_size(register) == 2 => Some(u16::from_le_bytes([*low, *high])),
+        _ => None,
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_pair(text: &str, separator: char) -> Option<(usize, usize)> {
+    let (first, second) = text.split_once(separator)?;
+    Some((
+        usize::from_str_radix(first, 16).ok()?,
+        usize::from_str_radix(second, 16).ok()?,
+    ))
+}
+
+// qXfer answers with an `m` while there is more to read and `l` for the last chunk
+fn transfer(document: &str, offset: usize, length: usize) -> String {
+    let start = offset.min(document.len());
+    let end = (offset + length).min(document.len());
+    let marker = if end == document.len() { 'l' } else { 'm' };
+    format!("{}{}", marker, &document[start..end])
+}
+
+fn target_description() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|register| {
+            format!(
+                "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>",
+                register
+            )
+        })
+        .collect();
+    registers.extend([
+        "<reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>".to_string(),
+        "<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>".to_string(),
+        "<reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>".to_string(),
+        "<reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>".to_string(),
+        "<reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>".to_string(),
+    ]);
+    format!(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><architecture>{}</architecture>\
+         <feature name=\"org.chip8.core\">{}</feature></target>",
+        ARCHITECTURE,
+        registers.join("")
+    )
+}
@@ -0,0 +1,44 @@
+//! Chip 8 emulator core.
+//!
+//! Build a [`Machine`], load a program into it with [`Rom`], then drive it with
+//! [`Machine::exec`] or [`Machine::run_frame`] and inspect its registers or the [`Screen`] framebuffer.
+//! A program that overflows the stack, runs off the end of memory or hits an unknown opcode
+//! makes them return a [`MachineError`].
+//! The terminal binary in `main.rs` is just one consumer of this API.
+
+pub mod assembler;
+pub mod debugger;
+pub mod differ;
+pub mod disassembler;
+pub mod gdbstub;
+pub mod machine;
+pub mod movie;
+pub mod rewind;
+pub mod runner;
+
+pub use debugger::{Debugger, StopReason};
+pub use differ::{DiffEnd, DiffError, Differ, Divergence, ReferenceTrace};
+pub use gdbstub::GdbStub;
+pub use machine::audio::{write_wav, Audio, DEFAULT_PITCH, PATTERN_SIZE};
+pub use machine::blocks::ExecutionEngine;
+pub use machine::error::MachineError;
+pub use machine::instructions::{decode, DecodeError, Operation};
+pub use machine::machine::{KeyEvent, Machine, MemoryMode, StepOutcome};
+pub use machine::policy::{OpcodeDecision, OpcodeHandler, OpcodePolicy, UnknownOpcode};
+pub use machine::quirks::{IndexIncrement, QuirkProfile, Quirks};
+pub use machine::random::{Random, DEFAULT_SEED, VIP_INTERPRETER_PAGE};
+pub use machine::rom::Rom;
+pub use machine::savestate::{slot_path, SaveStateError, SAVE_STATE_VERSION};
+pub use machine::screen::{
+    Screen, ALL_PLANES, HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, PLANE_1, PLANE_2, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+pub use machine::timers::{SoundEvent, Timers, TIMER_FREQUENCY};
+pub use machine::trace::{TraceFilter, TraceFormat, TraceParseError, TraceRecord};
+pub use machine::watchpoints::{Access, WatchAction, WatchHit, WatchKind, Watchpoint};
+pub use movie::{rom_hash, Movie, MovieError, MOVIE_VERSION};
+pub use rewind::{Rewind, RewindError};
+pub use runner::{
+    compare_screen, compare_screen_hash, screen_hash, screen_image, RowDiff, RunEnd, Runner,
+    ScreenMismatch,
+};
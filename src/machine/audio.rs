@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+/// Size of the XO-CHIP audio pattern buffer, 128 one-bit samples
+pub const PATTERN_SIZE: usize = 16;
+/// Pitch register value that plays the pattern back at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+// 4 bits on, 4 bits off, a 500 Hz square wave at the default pitch, used until a program loads its own
+const DEFAULT_PATTERN: [u8; PATTERN_SIZE] = [0xF0; PATTERN_SIZE];
+
+/// XO-CHIP sound: a looping 1-bit pattern played back at a rate set by the pitch register,
+/// audible while the sound timer is nonzero
+#[derive(Clone)]
+pub struct Audio {
+    pattern: [u8; PATTERN_SIZE],
+    pitch: u8,
+    // position inside the 128 bit pattern, kept between calls so the waveform stays continuous
+    phase: f64,
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self {
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            phase: 0.0,
+        }
+    }
+
+    pub fn read_pattern(&self) -> [u8; PATTERN_SIZE] {
+        self.pattern
+    }
+
+    pub fn write_to_pattern(&mut self, pattern: [u8; PATTERN_SIZE]) {
+        self.pattern = pattern;
+    }
+
+    pub fn read_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn write_to_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    /// Pattern bits played per second, 4000 * 2 ^ ((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Fill `output` with mono PCM samples in -1.0..=1.0 at `sample_rate`.
+    /// Silence is written while `sound_timer` is 0
+    pub fn generate(&mut self, sound_timer: u8, sample_rate: u32, output: &mut [f32]) {
+        if sound_timer == 0 {
+            output.fill(0.0);
+            return;
+        }
+
+        let pattern_bits = (PATTERN_SIZE * 8) as f64;
+        let step = self.playback_rate() / sample_rate as f64;
+        for sample in output.iter_mut() {
+            let bit = self.phase as usize;
+            let is_set = self.pattern[bit / 8] & (0b1000_0000 >> (bit % 8)) != 0;
+            *sample = match is_set {
+                true => 1.0,
+                false => -1.0,
+            };
+            self.phase = (self.phase + step) % pattern_bits;
+        }
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write samples made by [`Audio::generate`] as a 16 bit mono WAV file
+pub fn write_wav<W: Write>(writer: &mut W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let data_size = (samples.len() * 2) as u32;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM, 1 channel
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&pcm.to_le_bytes())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use super::instructions::{decode, Operation};
+
+// long enough for any loop body, short enough that translating one is cheap
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// How [`Machine::run_frame`](super::machine::Machine::run_frame) executes instructions,
+/// both give the same results
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExecutionEngine {
+    /// Fetch, decode and execute one instruction at a time
+    #[default]
+    Interpreter,
+    /// Translate straight-line code into blocks that end at a branch, a draw, a key check or a
+    /// memory write, and execute a whole block at once. Code written to after it was
+    /// translated, traces and watchpoints go through the interpreter
+    Blocks,
+}
+
+/// Straight-line operations, only the last one may branch, draw, check a key or write memory
+pub(crate) type Block = Box<[Operation]>;
+
+/// Translated blocks keyed by start address. A write to bytes a block was translated from
+/// drops every block, and those bytes are interpreted from then on
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BlockCache {
+    // the three are grown to the size of ram the first time a block is stored
+    blocks: Vec<Option<Block>>,
+    // bytes some block in `blocks` was translated from
+    translated: Vec<bool>,
+    // bytes written after they were translated
+    modified: Vec<bool>,
+}
+
+impl BlockCache {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take out the block starting at `address` to execute it, translated from `ram` if
+    /// needed. None when the instruction there has to be interpreted
+    pub(crate) fn take(&mut self, address: u16, ram: &[u8]) -> Option<Block> {
+        if let Some(block) = self.blocks.get_mut(address as usize).and_then(Option::take) {
+            return Some(block);
+        }
+        if self.blocks.len() != ram.len() {
+            self.blocks = vec![None; ram.len()];
+            self.translated = vec![false; ram.len()];
+            self.modified = vec![false; ram.len()];
+        }
+
+        let start = usize::from(address);
+        let mut operations = Vec::new();
+        let mut next = start;
+        while operations.len() < MAX_BLOCK_LENGTH {
+            let Some(opcode) = self.opcode_at(ram, next) else {
+                break;
+            };
+            let (operation, length) = match decode(opcode) {
+                // the address is the word that follows
+                Ok(Operation::OpF000LongI { .. }) => match self.opcode_at(ram, next + 2) {
+                    Some(address) => (Operation::OpF000LongI { address }, 4),
+                    None => break,
+                },
+                // the opcode policy decides what these do
+                Ok(Operation::Op0nnnSys { .. }) | Err(_) => break,
+                Ok(operation) => (operation, 2),
+            };
+            operations.push(operation);
+            next += length;
+            if ends_block(operation) {
+                break;
+            }
+        }
+        if operations.is_empty() {
+            return None;
+        }
+
+        self.translated[start..next].fill(true);
+        Some(operations.into())
+    }
+
+    /// Store a block back after executing it, unless a write dropped it in the meantime
+    pub(crate) fn put_back(&mut self, address: u16, block: Block) {
+        if self.translated.get(address as usize) == Some(&true) {
+            self.blocks[address as usize] = Some(block);
+        }
+    }
+
+    // None past the end of ram and for code that was written to
+    fn opcode_at(&self, ram: &[u8], address: usize) -> Option<u16> {
+        if address + 1 >= ram.len() || self.modified[address] || self.modified[address + 1] {
+            return None;
+        }
+        Some(u16::from_be_bytes([ram[address], ram[address + 1]]))
+    }
+
+    /// Note `length` bytes written at `address`, blocks translated from them are dropped
+    pub(crate) fn invalidate(&mut self, address: usize, length: usize) {
+        let end = (address + length).min(self.translated.len());
+        if address >= end || !self.translated[address..end].contains(&true) {
+            return;
+        }
+        self.modified[address..end].fill(true);
+        // blocks can start anywhere before the write, the cheapest way to find them all is
+        // to drop everything, which only happens the first time code is written
+        self.blocks.fill(None);
+        self.translated.fill(false);
+    }
+
+    /// Forget everything, for when ram is replaced as a whole
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+        self.translated.clear();
+        self.modified.clear();
+    }
+}
+
+fn ends_block(operation: Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Op00eeRet
+            | Operation::Op00fdExit
+            | Operation::Op1nnnJmp { .. }
+            | Operation::Op2nnnCall { .. }
+            | Operation::Op3xnnSe { .. }
+            | Operation::Op4xnnSne { .. }
+            | Operation::Op5xy0Se { .. }
+            | Operation::Op5xy2Save { .. }
+            | Operation::Op9xy0Sne { .. }
+            | Operation::OpBnnnJmpPlusV0 { .. }
+            | Operation::OpDxynDrw { .. }
+            | Operation::OpEx9eSkprs { .. }
+            | Operation::OpExa1Sknprs { .. }
+            | Operation::OpFx0aWaitKey { .. }
+            | Operation::OpFx33 { .. }
+            | Operation::OpFx55 { .. }
+    )
+}
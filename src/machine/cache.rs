@@ -0,0 +1,63 @@
+use super::instructions::{DecodeError, Operation};
+
+/// An opcode and what it decoded to, F000 carries the address from the word after it
+pub(crate) type Decoded = (u16, Result<Operation, DecodeError>);
+
+// the longest instruction, F000 NNNN, covers 4 bytes
+const LONGEST_INSTRUCTION: usize = 4;
+
+/// Decoded instructions keyed by address, so a loop decodes its body once instead of on
+/// every pass. A write to ram drops every entry whose instruction covers the written bytes,
+/// so self-modifying code still runs what is in memory
+#[derive(Clone, Debug)]
+pub(crate) struct DecodeCache {
+    enabled: bool,
+    // grown to the size of ram the first time something is stored
+    entries: Vec<Option<Decoded>>,
+}
+
+impl DecodeCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: true,
+            entries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    pub(crate) fn get(&self, address: u16) -> Option<Decoded> {
+        self.entries.get(address as usize).copied().flatten()
+    }
+
+    pub(crate) fn insert(&mut self, address: u16, decoded: Decoded, ram_size: usize) {
+        if !self.enabled {
+            return;
+        }
+        if self.entries.len() != ram_size {
+            self.entries = vec![None; ram_size];
+        }
+        self.entries[address as usize] = Some(decoded);
+    }
+
+    /// Forget the instructions overlapping `length` bytes written at `address`
+    pub(crate) fn invalidate(&mut self, address: usize, length: usize) {
+        let start = address.saturating_sub(LONGEST_INSTRUCTION - 1);
+        let end = (address + length).min(self.entries.len());
+        if start < end {
+            self.entries[start..end].fill(None);
+        }
+    }
+
+    /// Forget everything, for when ram is replaced as a whole
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,71 @@
+use std::{error::Error, fmt};
+
+/// Why an instruction could not run. The machine is left as it was before the instruction,
+/// with PC still pointing at it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MachineError {
+    /// 2NNN with the stack full
+    StackOverflow {
+        program_counter: u16,
+    },
+    /// 00EE with nothing on the stack
+    StackUnderflow {
+        program_counter: u16,
+    },
+    /// A fetch, read or write past the end of ram
+    MemoryOutOfRange {
+        program_counter: u16,
+        address: usize,
+    },
+    InvalidOpcode {
+        program_counter: u16,
+        opcode: u16,
+    },
+}
+
+impl MachineError {
+    /// Address of the instruction that failed
+    pub fn program_counter(&self) -> u16 {
+        match *self {
+            MachineError::StackOverflow { program_counter }
+            | MachineError::StackUnderflow { program_counter }
+            | MachineError::MemoryOutOfRange {
+                program_counter, ..
+            }
+            | MachineError::InvalidOpcode {
+                program_counter, ..
+            } => program_counter,
+        }
+    }
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::StackOverflow { program_counter } => {
+                write!(f, "stack overflow at {:#05x}", program_counter)
+            }
+            MachineError::StackUnderflow { program_counter } => {
+                write!(f, "return with an empty stack at {:#05x}", program_counter)
+            }
+            MachineError::MemoryOutOfRange {
+                program_counter,
+                address,
+            } => write!(
+                f,
+                "memory access to {:#05x} is out of range at {:#05x}",
+                address, program_counter
+            ),
+            MachineError::InvalidOpcode {
+                program_counter,
+                opcode,
+            } => write!(
+                f,
+                "invalid opcode {:04X} at {:#05x}",
+                opcode, program_counter
+            ),
+        }
+    }
+}
+
+impl Error for MachineError {}
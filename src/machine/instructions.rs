@@ -1,102 +1,290 @@
-use super::machine::Machine;
+use std::{error::Error, fmt};
+
+use super::{error::MachineError, machine::Machine};
 
 #[rustfmt::skip]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Operation {
 
-    ///	Execute machine language subroutine at address NNN
+    /// Execute machine language subroutine at address NNN
     Op0nnnSys {address: u16}, 
-///	Clear the screen
+/// Clear the screen
     Op00e0Cls, 
-///	Return from a subroutine
+/// Return from a subroutine
     Op00eeRet, 
-///	Jump to address NNN
+/// Scroll the display down by N pixels
+    Op00cnScd { rows: u8 },
+/// Scroll the display right by 4 pixels
+    Op00fbScr,
+/// Scroll the display left by 4 pixels
+    Op00fcScl,
+/// Exit the interpreter
+    Op00fdExit,
+/// Switch to the 64x32 low resolution display
+    Op00feLow,
+/// Switch to the 128x64 high resolution display
+    Op00ffHigh,
+/// Jump to address NNN
     Op1nnnJmp { address: u16 }, 
-///	Execute subroutine starting at address NNN
+/// Execute subroutine starting at address NNN
     Op2nnnCall { address: u16 }, 
-///	Skip the following instruction if the value of register VX equals NN
+/// Skip the following instruction if the value of register VX equals NN
     Op3xnnSe { register: u8, value: u8 }, 
-///	Skip the following instruction if the value of register VX is not equal to NN
+/// Skip the following instruction if the value of register VX is not equal to NN
     Op4xnnSne { register: u8, value: u8 }, 
-///	Skip the following instruction if the value of register VX is equal to the value of register VY
+/// Skip the following instruction if the value of register VX is equal to the value of register VY
     Op5xy0Se { register_x: u8, register_y: u8 }, 
-///	Store number NN in register VX
+/// Store the values of registers VX to VY inclusive in memory starting at address I, XO-CHIP
+    Op5xy2Save { register_x: u8, register_y: u8 },
+/// Fill registers VX to VY inclusive with the values stored in memory starting at address I, XO-CHIP
+    Op5xy3Load { register_x: u8, register_y: u8 },
+/// Store number NN in register VX
     Op6xnnMov { register_x: u8, number: u8 }, 
-///	Add the value NN to register VX
+/// Add the value NN to register VX
     Op7xnnAdd { register_x: u8, number: u8 }, 
-///	Store the value of register VY in register VX
+/// Store the value of register VY in register VX
     Op8xy0Ymovx { register_x: u8, register_y: u8 }, 
-///	Set VX to VX OR VY
+/// Set VX to VX OR VY
     Op8xy1Setvx2vxorvy { register_x: u8, register_y: u8 }, 
-///	Set VX to VX AND VY
+/// Set VX to VX AND VY
     Op8xy2Setvx2vxandvy { register_x: u8, register_y: u8 }, 
-///	Set VX to VX XOR VY
+/// Set VX to VX XOR VY
     Op8xy3Setvx2vxxorvy { register_x: u8, register_y: u8 }, 
-///	Add the value of register VY to register VXSet VF to 01 if a carry occursSet VF to 00 if a carry does not occur
+/// Add the value of register VY to register VX, VF is set to 01 on a carry and 00 otherwise
     Op8xy4Add { register_x: u8, register_y: u8 }, 
-///	Subtract the value of register VY from register VXSet VF to 00 if a borrow occursSet VF to 01 if a borrow does not occur
+/// Subtract the value of register VY from register VX, VF is set to 00 on a borrow and 01 otherwise
     Op8xy5Sub { register_x: u8, register_y: u8 }, 
-///	Store the value of register VY shifted right one bit in register VX¹Set register VF to the least significant bit prior to the shiftVY is unchanged
-    Op8xy6Shr { register_x: u8 }, 
-///	Set register VX to the value of VY minus VXSet VF to 00 if a borrow occursSet VF to 01 if a borrow does not occur
-    Op8xy7Sub { register_x: u8, register_y: u8 }, 
-///	Store the value of register VY shifted left one bit in register VX¹Set register VF to the most significant bit prior to the shiftVY is unchanged
-    Op8xyeShl { register_x: u8 }, 
-///	Skip the following instruction if the value of register VX is not equal to the value of register VY
+/// Store the value of register VY shifted right one bit in register VX, VF is set to the least
+/// significant bit prior to the shift and VY is unchanged. Without the `shift_uses_vy`
+/// quirk VX is shifted in place
+    Op8xy6Shr { register_x: u8, register_y: u8 }, 
+/// Set register VX to the value of VY minus VX, VF is set to 00 on a borrow and 01 otherwise
+    Op8xy7Subn { register_x: u8, register_y: u8 }, 
+/// Store the value of register VY shifted left one bit in register VX, VF is set to the most
+/// significant bit prior to the shift and VY is unchanged. Without the `shift_uses_vy`
+/// quirk VX is shifted in place
+    Op8xyeShl { register_x: u8, register_y: u8 }, 
+/// Skip the following instruction if the value of register VX is not equal to the value of register VY
     Op9xy0Sne { register_x: u8, register_y: u8 }, 
-///	Store memory address NNN in register I
+/// Store memory address NNN in register I
     OpAnnnMovI { address: u16 }, 
-///	Jump to address NNN + V0
+/// Jump to address NNN + V0
     OpBnnnJmpPlusV0 { value_nnn: u16 }, 
-///	Set VX to a random number with a mask of NN
+/// Set VX to a random number with a mask of NN
     OpCxnnMovRand { register_x: u8, mask: u8 }, 
-///	Draw a sprite at position VX, VY with N bytes of sprite data starting at the address stored in ISet VF to 01 if any set pixels are changed to unset, and 00 otherwise
+/// Draw a sprite at position VX, VY with N bytes of sprite data starting at the address stored
+/// in I, VF is set to 01 if any set pixels are changed to unset and 00 otherwise
     OpDxynDrw { register_x: u8, register_y: u8, height: u8 }, 
-///	Skip the following instruction if the key corresponding to the hex value currently stored in register VX is pressed
+/// Store the 16 bit address NNNN from the following word in register I, XO-CHIP
+    OpF000LongI { address: u16 },
+/// Load the 16 byte audio pattern buffer from memory starting at address I, XO-CHIP
+    OpF002Audio,
+/// Select the drawing planes given by N, XO-CHIP
+    OpFn01Plane { planes: u8 },
+/// Skip the following instruction if the key corresponding to the hex value currently stored in register VX is pressed
     OpEx9eSkprs { register_x: u8 }, 
-///	Skip the following instruction if the key corresponding to the hex value currently stored in register VX is not pressed
+/// Skip the following instruction if the key corresponding to the hex value currently stored in register VX is not pressed
     OpExa1Sknprs { register_x: u8 }, 
-///	Store the current value of the delay timer in register VX
+/// Store the current value of the delay timer in register VX
     OpFx07MovDt { register_x: u8 }, 
-///	Wait for a keypress and store the result in register VX
+/// Wait for a keypress and store the result in register VX
     OpFx0aWaitKey { register_x: u8 }, 
-///	Set the delay timer to the value of register VX
+/// Set the delay timer to the value of register VX
     OpFx15SetDly { register_x: u8 }, 
-///	Set the sound timer to the value of register VX
+/// Set the sound timer to the value of register VX
     OpFx18SetSt { register_x: u8 }, 
-///	Add the value stored in register VX to register I
+/// Add the value stored in register VX to register I
     OpFx1eMovVi { register_x: u8 }, 
-///	Set I to the memory address of the sprite data corresponding to the hexadecimal digit stored in register VX
+/// Set I to the memory address of the sprite data corresponding to the hexadecimal digit stored in register VX
     OpFx29 { register_x: u8 }, 
-///	Store the binary-coded decimal equivalent of the value stored in register VX at addresses I, I + 1, and I + 2
+/// Set I to the memory address of the large 8x10 sprite for the hexadecimal digit stored in register VX
+    OpFx30 { register_x: u8 },
+/// Store the binary-coded decimal equivalent of the value stored in register VX at addresses I, I + 1, and I + 2
     OpFx33 { register_x: u8 }, 
-///	Store the values of registers V0 to VX inclusive in memory starting at address II is set to I + X + 1 after operation²
+/// Set the audio pitch register to the value of register VX, XO-CHIP
+    OpFx3aPitch { register_x: u8 },
+/// Store the values of registers V0 to VX inclusive in memory starting at address I, I is then
+/// advanced as the `load_store` quirk says
     OpFx55 { register_x: u8 }, 
-///	Fill registers V0 to VX inclusive with the values stored in memory starting at address II is set to I + X + 1 after operation²
+/// Fill registers V0 to VX inclusive with the values stored in memory starting at address I, I is
+/// then advanced as the `load_store` quirk says
     OpFx65 { register_x: u8 }, 
+/// Store the values of registers V0 to VX inclusive in the persistent flag registers
+    OpFx75 { register_x: u8 },
+/// Fill registers V0 to VX inclusive with the values stored in the persistent flag registers
+    OpFx85 { register_x: u8 },
 }
 
-pub struct Instruction {
-    operation: Operation,
+/// Why an opcode could not be decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No supported variant defines the opcode
+    UnknownOpcode(u16),
 }
 
-impl Instruction {
-    pub fn new(opcode: Operation, operand: u16) -> Self {
-        Self { operation: opcode }
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04X}", opcode),
+        }
     }
 }
 
+impl Error for DecodeError {}
+
+/// Map an opcode to its [`Operation`] without executing it.
+/// F000 is decoded with an address of 0, the real one is the word that follows it in memory
+#[rustfmt::skip]
+pub fn decode(instruction: u16) -> Result<Operation, DecodeError> {
+    let first: u8  = ((instruction & 0xF000) >> 12) as u8;
+    let second: u8 = ((instruction & 0x0F00) >> 8) as u8;
+    let third: u8  = ((instruction & 0x00F0) >> 4) as u8;
+    let fourth: u8 = (instruction & 0x000F) as u8;
+    let address = instruction & 0x0FFF;
+    let number = (instruction & 0x00FF) as u8;
+
+    let operation = match (first, second, third, fourth) {
+    (0x0, 0x0, 0xE, 0x0) => Operation::Op00e0Cls,
+    (0x0, 0x0, 0xE, 0xE) => Operation::Op00eeRet,
+    (0x0, 0x0, 0xC, n) => Operation::Op00cnScd { rows: n },
+    (0x0, 0x0, 0xF, 0xB) => Operation::Op00fbScr,
+    (0x0, 0x0, 0xF, 0xC) => Operation::Op00fcScl,
+    (0x0, 0x0, 0xF, 0xD) => Operation::Op00fdExit,
+    (0x0, 0x0, 0xF, 0xE) => Operation::Op00feLow,
+    (0x0, 0x0, 0xF, 0xF) => Operation::Op00ffHigh,
+    (0x0, _, _, _) => Operation::Op0nnnSys { address },
+    (0x1, _, _, _) => Operation::Op1nnnJmp { address },
+    (0x2, _, _, _) => Operation::Op2nnnCall { address },
+    (0x3, x, _, _) => Operation::Op3xnnSe { register: x, value: number },
+    (0x4, x, _, _) => Operation::Op4xnnSne { register: x, value: number },
+    (0x5, x, y, 0x0) => Operation::Op5xy0Se { register_x: x, register_y: y },
+    (0x5, x, y, 0x2) => Operation::Op5xy2Save { register_x: x, register_y: y },
+    (0x5, x, y, 0x3) => Operation::Op5xy3Load { register_x: x, register_y: y },
+    (0x6, x, _, _) => Operation::Op6xnnMov { register_x: x, number },
+    (0x7, x, _, _) => Operation::Op7xnnAdd { register_x: x, number },
+    (0x8, x, y, 0x0) => Operation::Op8xy0Ymovx { register_x: x, register_y: y },
+    (0x8, x, y, 0x1) => Operation::Op8xy1Setvx2vxorvy { register_x: x, register_y: y },
+    (0x8, x, y, 0x2) => Operation::Op8xy2Setvx2vxandvy { register_x: x, register_y: y },
+    (0x8, x, y, 0x3) => Operation::Op8xy3Setvx2vxxorvy { register_x: x, register_y: y },
+    (0x8, x, y, 0x4) => Operation::Op8xy4Add { register_x: x, register_y: y },
+    (0x8, x, y, 0x5) => Operation::Op8xy5Sub { register_x: x, register_y: y },
+    (0x8, x, y, 0x6) => Operation::Op8xy6Shr { register_x: x, register_y: y },
+    (0x8, x, y, 0x7) => Operation::Op8xy7Subn { register_x: x, register_y: y },
+    (0x8, x, y, 0xE) => Operation::Op8xyeShl { register_x: x, register_y: y },
+    (0x9, x, y, 0x0) => Operation::Op9xy0Sne { register_x: x, register_y: y },
+    (0xA, _, _, _) => Operation::OpAnnnMovI { address },
+    (0xB, _, _, _) => Operation::OpBnnnJmpPlusV0 { value_nnn: address },
+    (0xC, x, _, _) => Operation::OpCxnnMovRand { register_x: x, mask: number },
+    (0xD, x, y, n) => Operation::OpDxynDrw { register_x: x, register_y: y, height: n },
+    (0xE, x, 0x9, 0xE) => Operation::OpEx9eSkprs { register_x: x },
+    (0xE, x, 0xA, 0x1) => Operation::OpExa1Sknprs { register_x: x },
+    (0xF, 0x0, 0x0, 0x0) => Operation::OpF000LongI { address: 0 },
+    (0xF, n, 0x0, 0x1) => Operation::OpFn01Plane { planes: n },
+    (0xF, 0x0, 0x0, 0x2) => Operation::OpF002Audio,
+    (0xF, x, 0x0, 0x7) => Operation::OpFx07MovDt { register_x: x },
+    (0xF, x, 0x0, 0xA) => Operation::OpFx0aWaitKey { register_x: x },
+    (0xF, x, 0x1, 0x5) => Operation::OpFx15SetDly { register_x: x },
+    (0xF, x, 0x1, 0x8) => Operation::OpFx18SetSt { register_x: x },
+    (0xF, x, 0x1, 0xE) => Operation::OpFx1eMovVi { register_x: x },
+    (0xF, x, 0x2, 0x9) => Operation::OpFx29 { register_x: x },
+    (0xF, x, 0x3, 0x0) => Operation::OpFx30 { register_x: x },
+    (0xF, x, 0x3, 0x3) => Operation::OpFx33 { register_x: x },
+    (0xF, x, 0x3, 0xA) => Operation::OpFx3aPitch { register_x: x },
+    (0xF, x, 0x5, 0x5) => Operation::OpFx55 { register_x: x },
+    (0xF, x, 0x6, 0x5) => Operation::OpFx65 { register_x: x },
+    (0xF, x, 0x7, 0x5) => Operation::OpFx75 { register_x: x },
+    (0xF, x, 0x8, 0x5) => Operation::OpFx85 { register_x: x },
+    _ => return Err(DecodeError::UnknownOpcode(instruction)),
+    };
+    Ok(operation)
+}
+
 #[rustfmt::skip]
-impl Instruction {
-    fn execute(&mut self,machine:&mut Machine){
-        match self.operation {
-            Operation::Op0nnnSys { address } => {machine.op_0nnn_sys(address);}
-            Operation::Op00e0Cls => {machine.op_00e0_cls();}
-            Operation::Op00eeRet => machine.op_00ee_ret(),
+impl Operation {
+    /// The opcode, the reverse of [`decode`].
+    /// F000 NNNN only yields F000, its address is the word that follows
+    pub fn encode(&self) -> u16 {
+        let xy = |x: &u8, y: &u8| ((*x as u16) << 8) | ((*y as u16) << 4);
+        let xnn = |x: &u8, nn: &u8| ((*x as u16) << 8) | *nn as u16;
+        let x = |x: &u8| (*x as u16) << 8;
+
+        match self {
+            Operation::Op0nnnSys { address } => *address,
+            Operation::Op00e0Cls => 0x00E0,
+            Operation::Op00eeRet => 0x00EE,
+            Operation::Op00cnScd { rows } => 0x00C0 | *rows as u16,
+            Operation::Op00fbScr => 0x00FB,
+            Operation::Op00fcScl => 0x00FC,
+            Operation::Op00fdExit => 0x00FD,
+            Operation::Op00feLow => 0x00FE,
+            Operation::Op00ffHigh => 0x00FF,
+            Operation::Op1nnnJmp { address } => 0x1000 | address,
+            Operation::Op2nnnCall { address } => 0x2000 | address,
+            Operation::Op3xnnSe { register, value } => 0x3000 | xnn(register, value),
+            Operation::Op4xnnSne { register, value } => 0x4000 | xnn(register, value),
+            Operation::Op5xy0Se { register_x, register_y } => 0x5000 | xy(register_x, register_y),
+            Operation::Op5xy2Save { register_x, register_y } => 0x5002 | xy(register_x, register_y),
+            Operation::Op5xy3Load { register_x, register_y } => 0x5003 | xy(register_x, register_y),
+            Operation::Op6xnnMov { register_x, number } => 0x6000 | xnn(register_x, number),
+            Operation::Op7xnnAdd { register_x, number } => 0x7000 | xnn(register_x, number),
+            Operation::Op8xy0Ymovx { register_x, register_y } => 0x8000 | xy(register_x, register_y),
+            Operation::Op8xy1Setvx2vxorvy { register_x, register_y } => 0x8001 | xy(register_x, register_y),
+            Operation::Op8xy2Setvx2vxandvy { register_x, register_y } => 0x8002 | xy(register_x, register_y),
+            Operation::Op8xy3Setvx2vxxorvy { register_x, register_y } => 0x8003 | xy(register_x, register_y),
+            Operation::Op8xy4Add { register_x, register_y } => 0x8004 | xy(register_x, register_y),
+            Operation::Op8xy5Sub { register_x, register_y } => 0x8005 | xy(register_x, register_y),
+            Operation::Op8xy6Shr { register_x, register_y } => 0x8006 | xy(register_x, register_y),
+            Operation::Op8xy7Subn { register_x, register_y } => 0x8007 | xy(register_x, register_y),
+            Operation::Op8xyeShl { register_x, register_y } => 0x800E | xy(register_x, register_y),
+            Operation::Op9xy0Sne { register_x, register_y } => 0x9000 | xy(register_x, register_y),
+            Operation::OpAnnnMovI { address } => 0xA000 | address,
+            Operation::OpBnnnJmpPlusV0 { value_nnn } => 0xB000 | value_nnn,
+            Operation::OpCxnnMovRand { register_x, mask } => 0xC000 | xnn(register_x, mask),
+            Operation::OpDxynDrw { register_x, register_y, height } => 0xD000 | xy(register_x, register_y) | *height as u16,
+            Operation::OpEx9eSkprs { register_x } => 0xE09E | x(register_x),
+            Operation::OpExa1Sknprs { register_x } => 0xE0A1 | x(register_x),
+            Operation::OpF000LongI { .. } => 0xF000,
+            Operation::OpFn01Plane { planes } => 0xF001 | x(planes),
+            Operation::OpF002Audio => 0xF002,
+            Operation::OpFx07MovDt { register_x } => 0xF007 | x(register_x),
+            Operation::OpFx0aWaitKey { register_x } => 0xF00A | x(register_x),
+            Operation::OpFx15SetDly { register_x } => 0xF015 | x(register_x),
+            Operation::OpFx18SetSt { register_x } => 0xF018 | x(register_x),
+            Operation::OpFx1eMovVi { register_x } => 0xF01E | x(register_x),
+            Operation::OpFx29 { register_x } => 0xF029 | x(register_x),
+            Operation::OpFx30 { register_x } => 0xF030 | x(register_x),
+            Operation::OpFx33 { register_x } => 0xF033 | x(register_x),
+            Operation::OpFx3aPitch { register_x } => 0xF03A | x(register_x),
+            Operation::OpFx55 { register_x } => 0xF055 | x(register_x),
+            Operation::OpFx65 { register_x } => 0xF065 | x(register_x),
+            Operation::OpFx75 { register_x } => 0xF075 | x(register_x),
+            Operation::OpFx85 { register_x } => 0xF085 | x(register_x),
+        }
+    }
+}
+
+#[rustfmt::skip]
+impl Operation {
+    /// Run the operation on `machine`, PC is advanced afterwards by [`Machine::exec`].
+    /// Operations that set PC themselves set it 2 short of their target
+    pub(crate) fn execute(self, machine: &mut Machine) -> Result<(), MachineError> {
+        match self {
+            Operation::Op0nnnSys { address } => machine.op_0nnn_sys(address)?,
+            Operation::Op00e0Cls => machine.op_00e0_cls(),
+            Operation::Op00eeRet => machine.op_00ee_ret()?,
+            Operation::Op00cnScd { rows } => machine.op_00cn_scd(rows),
+            Operation::Op00fbScr => machine.op_00fb_scr(),
+            Operation::Op00fcScl => machine.op_00fc_scl(),
+            Operation::Op00fdExit => machine.op_00fd_exit(),
+            Operation::Op00feLow => machine.op_00fe_low(),
+            Operation::Op00ffHigh => machine.op_00ff_high(),
             Operation::Op1nnnJmp { address } => machine.op_1nnn_jmp(address),
-            Operation::Op2nnnCall { address } => machine.op_2nnn_call(address),
+            Operation::Op2nnnCall { address } => machine.op_2nnn_call(address)?,
             Operation::Op3xnnSe { register, value } => machine.op_3xnn_se(register, value),
             Operation::Op4xnnSne { register, value } => machine.op_4xnn_sne(register, value),
             Operation::Op5xy0Se { register_x, register_y } => machine.op_5xy0_se(register_x, register_y),
+            Operation::Op5xy2Save { register_x, register_y } => machine.op_5xy2_save(register_x, register_y)?,
+            Operation::Op5xy3Load { register_x, register_y } => machine.op_5xy3_load(register_x, register_y)?,
             Operation::Op6xnnMov { register_x, number } => machine.op_6xnn_mov(register_x, number),
             Operation::Op7xnnAdd { register_x, number } => machine.op_7xnn_add(register_x, number),
             Operation::Op8xy0Ymovx { register_x, register_y } => machine.op_8xy0_ymovx(register_x, register_y),
@@ -105,14 +293,17 @@ impl Instruction {
             Operation::Op8xy3Setvx2vxxorvy { register_x, register_y } => machine.op_8xy3_setvx2vxxorvy(register_x, register_y),
             Operation::Op8xy4Add { register_x, register_y } => machine.op_8xy4_add(register_x, register_y),
             Operation::Op8xy5Sub { register_x, register_y } => machine.op_8xy5_sub(register_x, register_y),
-            Operation::Op8xy6Shr { register_x } => machine.op_8xy6_shr(register_x, register_x),
-            Operation::Op8xy7Sub { register_x, register_y } => machine.op_8xy7_sub(register_x, register_y),
-            Operation::Op8xyeShl { register_x } => machine.op_8xye_shl(register_x, register_x),
+            Operation::Op8xy6Shr { register_x, register_y } => machine.op_8xy6_shr(register_x, register_y),
+            Operation::Op8xy7Subn { register_x, register_y } => machine.op_8xy7_subn(register_x, register_y),
+            Operation::Op8xyeShl { register_x, register_y } => machine.op_8xye_shl(register_x, register_y),
             Operation::Op9xy0Sne { register_x, register_y } => machine.op_9xy0_sne(register_x, register_y),
             Operation::OpAnnnMovI { address } => machine.op_annn_movi(address),
             Operation::OpBnnnJmpPlusV0 { value_nnn } => machine.op_bnnn_jmp_plus_v0(value_nnn),
             Operation::OpCxnnMovRand { register_x, mask } => machine.op_cxnn_mov_rand(register_x, mask),
-            Operation::OpDxynDrw { register_x, register_y, height } => machine.op_dxyn_drw(register_x, register_y, height),
+            Operation::OpDxynDrw { register_x, register_y, height } => machine.op_dxyn_drw(register_x, register_y, height)?,
+            Operation::OpF000LongI { address } => machine.op_f000_longi(address),
+            Operation::OpF002Audio => machine.op_f002_audio()?,
+            Operation::OpFn01Plane { planes } => machine.op_fn01_plane(planes),
             Operation::OpEx9eSkprs { register_x } => machine.op_ex9e_skprs(register_x),
             Operation::OpExa1Sknprs { register_x } => machine.op_exa1_sknprs(register_x),
             Operation::OpFx07MovDt { register_x } => machine.op_fx07_mov_dt(register_x),
@@ -121,55 +312,139 @@ impl Instruction {
             Operation::OpFx18SetSt { register_x } => machine.op_fx18_set_st(register_x),
             Operation::OpFx1eMovVi { register_x } => machine.op_fx1e_mov_vi(register_x),
             Operation::OpFx29 { register_x } => machine.op_fx29(register_x),
-            Operation::OpFx33 { register_x } => machine.op_fx33(register_x),
-            Operation::OpFx55 { register_x } => machine.op_fx55(register_x),
-            Operation::OpFx65 { register_x } => machine.op_fx65(register_x),
+            Operation::OpFx30 { register_x } => machine.op_fx30(register_x),
+            Operation::OpFx33 { register_x } => machine.op_fx33(register_x)?,
+            Operation::OpFx3aPitch { register_x } => machine.op_fx3a_pitch(register_x),
+            Operation::OpFx55 { register_x } => machine.op_fx55(register_x)?,
+            Operation::OpFx65 { register_x } => machine.op_fx65(register_x)?,
+            Operation::OpFx75 { register_x } => machine.op_fx75(register_x),
+            Operation::OpFx85 { register_x } => machine.op_fx85(register_x),
         }
+        Ok(())
     }
 }
 
-#[rustfmt::skip]
-pub fn parse_instruction(instruction: u16, machine: &mut Machine) {
-    let first: u8  = ((instruction & 0xF000) >> 12) as u8;
-    let second: u8 = ((instruction & 0x0F00) >> 8) as u8;
-    let third: u8  = ((instruction & 0x00F0) >> 4) as u8;
-    let fourth: u8 = (instruction & 0x000F) as u8;
+impl Operation {
+    /// Mnemonic and formatted operands, in the syntax the assembler reads
+    pub fn mnemonic(&self) -> (&'static str, String) {
+        let register = |x: &u8| format!("V{:X}", x);
+        let registers = |x: &u8, y: &u8| format!("V{:X}, V{:X}", x, y);
+        let register_byte = |x: &u8, byte: &u8| format!("V{:X}, {:#04X}", x, byte);
+        let address = |nnn: &u16| format!("{:#05X}", nnn);
+
+        match self {
+            Operation::Op0nnnSys { address: nnn } => ("SYS", address(nnn)),
+            Operation::Op00e0Cls => ("CLS", String::new()),
+            Operation::Op00eeRet => ("RET", String::new()),
+            Operation::Op00cnScd { rows } => ("SCD", rows.to_string()),
+            Operation::Op00fbScr => ("SCR", String::new()),
+            Operation::Op00fcScl => ("SCL", String::new()),
+            Operation::Op00fdExit => ("EXIT", String::new()),
+            Operation::Op00feLow => ("LOW", String::new()),
+            Operation::Op00ffHigh => ("HIGH", String::new()),
+            Operation::Op1nnnJmp { address: nnn } => ("JMP", address(nnn)),
+            Operation::Op2nnnCall { address: nnn } => ("CALL", address(nnn)),
+            Operation::Op3xnnSe { register: x, value } => ("SE", register_byte(x, value)),
+            Operation::Op4xnnSne { register: x, value } => ("SNE", register_byte(x, value)),
+            Operation::Op5xy0Se {
+                register_x,
+                register_y,
+            } => ("SE", registers(register_x, register_y)),
+            Operation::Op5xy2Save {
+                register_x,
+                register_y,
+            } => ("SAVE", registers(register_x, register_y)),
+            Operation::Op5xy3Load {
+                register_x,
+                register_y,
+            } => ("LOAD", registers(register_x, register_y)),
+            Operation::Op6xnnMov { register_x, number } => {
+                ("MOV", register_byte(register_x, number))
+            }
+            Operation::Op7xnnAdd { register_x, number } => {
+                ("ADD", register_byte(register_x, number))
+            }
+            Operation::Op8xy0Ymovx {
+                register_x,
+                register_y,
+            } => ("YMOVX", registers(register_x, register_y)),
+            Operation::Op8xy1Setvx2vxorvy {
+                register_x,
+                register_y,
+            } => ("SETVX2VXORVY", registers(register_x, register_y)),
+            Operation::Op8xy2Setvx2vxandvy {
+                register_x,
+                register_y,
+            } => ("SETVX2VXANDVY", registers(register_x, register_y)),
+            Operation::Op8xy3Setvx2vxxorvy {
+                register_x,
+                register_y,
+            } => ("SETVX2VXXORVY", registers(register_x, register_y)),
+            Operation::Op8xy4Add {
+                register_x,
+                register_y,
+            } => ("ADD", registers(register_x, register_y)),
+            Operation::Op8xy5Sub {
+                register_x,
+                register_y,
+            } => ("SUB", registers(register_x, register_y)),
+            Operation::Op8xy6Shr {
+                register_x,
+                register_y,
+            } => ("SHR", registers(register_x, register_y)),
+            Operation::Op8xy7Subn {
+                register_x,
+                register_y,
+            } => ("SUBN", registers(register_x, register_y)),
+            Operation::Op8xyeShl {
+                register_x,
+                register_y,
+            } => ("SHL", registers(register_x, register_y)),
+            Operation::Op9xy0Sne {
+                register_x,
+                register_y,
+            } => ("SNE", registers(register_x, register_y)),
+            Operation::OpAnnnMovI { address: nnn } => ("MOVI", address(nnn)),
+            Operation::OpBnnnJmpPlusV0 { value_nnn } => ("JMPPLUSV0", address(value_nnn)),
+            Operation::OpCxnnMovRand { register_x, mask } => {
+                ("MOVRAND", register_byte(register_x, mask))
+            }
+            Operation::OpDxynDrw {
+                register_x,
+                register_y,
+                height,
+            } => (
+                "DRW",
+                format!("{}, {}", registers(register_x, register_y), height),
+            ),
+            Operation::OpEx9eSkprs { register_x } => ("SKPRS", register(register_x)),
+            Operation::OpExa1Sknprs { register_x } => ("SKNPRS", register(register_x)),
+            Operation::OpF000LongI { address: nnnn } => ("LONGI", format!("{:#06X}", nnnn)),
+            Operation::OpFn01Plane { planes } => ("PLANE", planes.to_string()),
+            Operation::OpF002Audio => ("AUDIO", String::new()),
+            Operation::OpFx07MovDt { register_x } => ("MOVDT", register(register_x)),
+            Operation::OpFx0aWaitKey { register_x } => ("WAITKEY", register(register_x)),
+            Operation::OpFx15SetDly { register_x } => ("SETDLY", register(register_x)),
+            Operation::OpFx18SetSt { register_x } => ("SETST", register(register_x)),
+            Operation::OpFx1eMovVi { register_x } => ("MOVVI", register(register_x)),
+            Operation::OpFx29 { register_x } => ("FX29", register(register_x)),
+            Operation::OpFx30 { register_x } => ("FX30", register(register_x)),
+            Operation::OpFx33 { register_x } => ("FX33", register(register_x)),
+            Operation::OpFx3aPitch { register_x } => ("PITCH", register(register_x)),
+            Operation::OpFx55 { register_x } => ("FX55", register(register_x)),
+            Operation::OpFx65 { register_x } => ("FX65", register(register_x)),
+            Operation::OpFx75 { register_x } => ("FX75", register(register_x)),
+            Operation::OpFx85 { register_x } => ("FX85", register(register_x)),
+        }
+    }
+}
 
-    match (first, second, third, fourth) {
- (0x0, 0x0, 0xE, 0x0) => machine.op_00e0_cls(),
-    (0x0, 0x0, 0xE, 0xE) => machine.op_00ee_ret(),
-    (0x1, _, _, _) => machine.op_1nnn_jmp((instruction & 0x0FFF) as u16),
-    (0x2, _, _, _) => machine.op_2nnn_call((instruction & 0x0FFF) as u16),
-    (0x3, x, _, _) => machine.op_3xnn_se(x, (instruction & 0x00FF) as u8),
-    (0x4, x, _, _) => machine.op_4xnn_sne(x, (instruction & 0x00FF) as u8),
-    (0x5, x, y, 0x0) => machine.op_5xy0_se(x, y),
-    (0x6, x, _, _) => machine.op_6xnn_mov(x, (instruction & 0x00FF) as u8),
-    (0x7, x, _, _) => machine.op_7xnn_add(x, (instruction & 0x00FF) as u8),
-    (0x8, x, y, 0x0) => machine.op_8xy0_ymovx(x, y),
-    (0x8, x, y, 0x1) => machine.op_8xy1_setvx2vxorvy(x, y),
-    (0x8, x, y, 0x2) => machine.op_8xy2_setvx2vxandvy(x, y),
-    (0x8, x, y, 0x3) => machine.op_8xy3_setvx2vxxorvy(x, y),
-    (0x8, x, y, 0x4) => machine.op_8xy4_add(x, y),
-    (0x8, x, y, 0x5) => machine.op_8xy5_sub(x, y),
-    (0x8, x, y, 0x6) => machine.op_8xy6_shr(x,y),
-    (0x8, x, y, 0x7) => machine.op_8xy7_sub(x, y),
-    (0x8, x, y, 0xE) => machine.op_8xye_shl(x,y),
-    (0x9, x, y, 0x0) => machine.op_9xy0_sne(x, y),
-    (0xA, _, _, _) => machine.op_annn_movi(instruction & 0x0FFF),
-    (0xB, _, _, _) => machine.op_bnnn_jmp_plus_v0(instruction & 0x0FFF),
-    (0xC, x, _, _) => machine.op_cxnn_mov_rand(x, (instruction & 0x00FF) as u8),
-    (0xD, x, y, n) => machine.op_dxyn_drw2(x, y, n),
-    (0xE, x, 0x9, 0xE) => machine.op_ex9e_skprs(x),
-    (0xE, x, 0xA, 0x1) => machine.op_exa1_sknprs(x),
-    (0xF, x, 0x0, 0x7) => machine.op_fx07_mov_dt(x),
-    (0xF, x, 0x0, 0xA) => machine.op_fx0a_wait_key(x),
-    (0xF, x, 0x1, 0x5) => machine.op_fx15_set_dly(x),
-    (0xF, x, 0x1, 0x8) => machine.op_fx18_set_st(x),
-    (0xF, x, 0x1, 0xE) => machine.op_fx1e_mov_vi(x),
-    (0xF, x, 0x2, 0x9) => machine.op_fx29(x),
-    (0xF, x, 0x3, 0x3) => machine.op_fx33(x),
-    (0xF, x, 0x5, 0x5) => machine.op_fx55(x),
-    (0xF, x, 0x6, 0x5) => machine.op_fx65(x),
-    _ => machine.increment_program_counter(0),
+/// Canonical mnemonic and operands, `DRW V0, V1, 5`
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mnemonic() {
+            (mnemonic, operands) if operands.is_empty() => write!(f, "{}", mnemonic),
+            (mnemonic, operands) => write!(f, "{} {}", mnemonic, operands),
+        }
     }
 }
@@ -1,8 +1,22 @@
-use std::{u16, u8, usize};
-
-use super::{instructions::parse_instruction, screen::Screen};
+use super::{
+    audio::Audio,
+    blocks::{BlockCache, ExecutionEngine},
+    cache::{DecodeCache, Decoded},
+    error::MachineError,
+    instructions::{decode, DecodeError, Operation},
+    policy::{OpcodeDecision, OpcodePolicy, UnknownOpcode},
+    quirks::Quirks,
+    random::Random,
+    screen::Screen,
+    timers::{SoundEvent, Timers},
+    trace::{TraceFilter, TraceRecord, Tracer},
+    watchpoints::{Access, WatchHit, Watchpoint, Watchpoints},
+};
+use std::ops::RangeInclusive;
 
 const RAM_SIZE: usize = 4096;
+// XO-CHIP can address all of 64 KiB
+const XO_CHIP_RAM_SIZE: usize = 65536;
 const SOFT_MIN: u16 = 0x200;
 const FONTSET_SIZE: usize = 80;
 const FONTSET: [u8; FONTSET_SIZE] = [
@@ -23,6 +37,59 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+pub(crate) const FONTSET_ADDRESS: u16 = 0x50;
+// SUPER-CHIP 8x10 digits for FX30, stored right after the small font
+const BIG_FONTSET_SIZE: usize = 160;
+const BIG_FONTSET: [u8; BIG_FONTSET_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+pub(crate) const BIG_FONTSET_ADDRESS: u16 = FONTSET_ADDRESS + FONTSET_SIZE as u16;
+
+/// How much memory the machine has
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryMode {
+    /// 4 KiB, the original chip 8 and SUPER-CHIP
+    Chip8,
+    /// 64 KiB, XO-CHIP
+    XoChip,
+}
+
+/// A key going down or up, as seen by [`Machine::set_pressed_keys`] and
+/// [`Machine::unset_pressed_keys`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// [`Machine::read_frame_count`] when the key changed
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// What a call to [`Machine::exec`] did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    /// Nothing ran, a draw under the display wait quirk blocks until the next frame tick
+    WaitingForVblank,
+    /// Nothing ran, the program stopped itself with 00FD
+    Exited,
+    /// Nothing ran, a Stop watchpoint matched and its hits were not taken yet
+    StoppedByWatchpoint,
+}
 
 #[derive(Clone)]
 pub struct Machine {
@@ -30,34 +97,385 @@ pub struct Machine {
     program_counter: u16,
     stack_pointer: u8,
     index_register: u16,
-    sound_timer: u8,
-    delay_timer: u8,
-    ram: [u8; RAM_SIZE],
-    stack: [u16; 16],
-    pressed_keys: [bool; 16],
+    timers: Timers,
+    pub audio: Audio,
+    // frames elapsed since power on, one per timer tick
+    pub(crate) frame_count: u64,
+    memory_mode: MemoryMode,
+    pub(crate) ram: Vec<u8>,
+    pub(crate) stack: [u16; 16],
+    pub(crate) pressed_keys: [bool; 16],
     pub screen: Screen,
+    quirks: Quirks,
+    // set by DXYN under the display wait quirk, cleared by the next frame tick
+    pub(crate) waiting_for_vblank: bool,
+    // set by the SUPER-CHIP 00FD exit instruction, nothing runs afterwards
+    pub(crate) exited: bool,
+    // FX75/FX85 user flags, kept outside of ram so frontends can persist them between runs
+    flag_registers: [u8; 16],
+    // source of CXNN's random bytes
+    random: Random,
+    pub(crate) tracer: Option<Tracer>,
+    // what the opcode policy did with the current instruction, for its trace record
+    traced_decision: Option<OpcodeDecision>,
+    // bytes the current instruction wrote, for its trace record
+    traced_writes: Vec<(u16, u8)>,
+    decode_cache: DecodeCache,
+    execution_engine: ExecutionEngine,
+    // used by the block engine only
+    blocks: BlockCache,
+    // what unknown opcodes and 0NNN do
+    opcode_policy: OpcodePolicy,
+    // collected under OpcodePolicy::LogAndSkip
+    unknown_opcodes: Vec<UnknownOpcode>,
+    watchpoints: Watchpoints,
+    // PC and opcode of the instruction being executed, memory accesses outside of one are not watched
+    current_instruction: Option<(u16, u16)>,
+    // key transitions, collected only while input recording is on
+    key_events: Option<Vec<KeyEvent>>,
 }
 impl Machine {
-    pub fn push_to_stack(&mut self, value: u16) {
-        self.stack_pointer = self.stack_pointer.overflowing_add(1).0;
+    pub(crate) fn push_to_stack(&mut self, value: u16) -> Result<(), MachineError> {
+        if self.stack_pointer as usize >= self.stack.len() {
+            return Err(MachineError::StackOverflow {
+                program_counter: self.program_counter,
+            });
+        }
         self.stack[self.stack_pointer as usize] = value;
+        self.stack_pointer += 1;
+        Ok(())
+    }
+
+    pub(crate) fn pop_from_stack(&mut self) -> Result<u16, MachineError> {
+        if self.stack_pointer == 0 {
+            return Err(MachineError::StackUnderflow {
+                program_counter: self.program_counter,
+            });
+        }
+        self.stack_pointer -= 1;
+        Ok(self.stack[self.stack_pointer as usize])
+    }
+
+    /// Entries in use, from 0 for an empty stack to 16 for a full one
+    pub fn read_stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    /// Values past 16 are clamped, the stack has 16 entries
+    pub fn write_to_stack_pointer(&mut self, value: u8) {
+        self.stack_pointer = value.min(self.stack.len() as u8);
+    }
+
+    pub fn read_stack(&self) -> [u16; 16] {
+        self.stack
+    }
+}
+impl Machine {
+    /// Execute the instruction at PC. On an error nothing is executed and PC stays on the
+    /// faulting instruction
+    pub fn exec(&mut self) -> Result<StepOutcome, MachineError> {
+        if self.waiting_for_vblank {
+            return Ok(StepOutcome::WaitingForVblank);
+        }
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.watchpoints.is_stopped() {
+            return Ok(StepOutcome::StoppedByWatchpoint);
+        }
+
+        let opcode_end = usize::from(self.program_counter) + 1;
+        if opcode_end >= self.ram.len() {
+            return Err(MachineError::MemoryOutOfRange {
+                program_counter: self.program_counter,
+                address: opcode_end,
+            });
+        }
+        let (merged_instruction, decoded) = self.decode_at(self.program_counter);
+        let program_counter = self.program_counter;
+        let registers_before = self.general_purpouse_registers;
+
+        self.current_instruction = Some((program_counter, merged_instruction));
+        let executed = match decoded {
+            Ok(operation) => operation.execute(self),
+            Err(_) => self.handle_unknown_opcode(merged_instruction),
+        };
+        self.current_instruction = None;
+        let decision = self.traced_decision.take();
+        let memory_writes = std::mem::take(&mut self.traced_writes);
+        if executed.is_ok() {
+            self.increment_program_counter(2);
+        }
+        // an opcode policy that stops the run still gets its record, the last one in the
+        // trace then shows where and why
+        if self.tracer.is_some() && (executed.is_ok() || decision.is_some()) {
+            self.trace_instruction(
+                (program_counter, merged_instruction, decoded),
+                registers_before,
+                decision,
+                memory_writes,
+            );
+        }
+        executed?;
+        Ok(StepOutcome::Executed)
+    }
+
+    /// The opcode at `address` and its operation, from the decode cache when it is there
+    fn decode_at(&mut self, address: u16) -> Decoded {
+        if let Some(decoded) = self.decode_cache.get(address) {
+            return decoded;
+        }
+        let opcode = self.read_opcode(address);
+        let decoded = match decode(opcode) {
+            // the address is the word that follows
+            Ok(Operation::OpF000LongI { .. }) => Ok(Operation::OpF000LongI {
+                address: self.read_opcode(address.overflowing_add(2).0),
+            }),
+            decoded => decoded,
+        };
+        self.decode_cache
+            .insert(address, (opcode, decoded), self.ram.len());
+        (opcode, decoded)
+    }
+
+    /// Execute the block at PC, or a single instruction when it has to be interpreted, but no
+    /// more than `max_instructions`. Returns how many instructions that was
+    fn exec_block(&mut self, max_instructions: u32) -> Result<u32, MachineError> {
+        let interpreted = self.tracer.is_some()
+            || self.waiting_for_vblank
+            || self.exited
+            || !self.watchpoints.watchpoints().is_empty();
+        let start = self.program_counter;
+        let block = match interpreted {
+            true => None,
+            false => self.blocks.take(start, &self.ram),
+        };
+        let Some(block) = block else {
+            self.exec()?;
+            return Ok(1);
+        };
+
+        // a jump to itself, how programs usually stop, would do nothing for the rest
+        if let [Operation::Op1nnnJmp { address }] = *block {
+            if address == start {
+                self.blocks.put_back(start, block);
+                return Ok(max_instructions);
+            }
+        }
+        let mut executed = 0;
+        let mut result = Ok(());
+        for operation in block.iter().take(max_instructions as usize) {
+            result = operation.execute(self);
+            if result.is_err() {
+                break;
+            }
+            self.increment_program_counter(2);
+            executed += 1;
+        }
+        self.blocks.put_back(start, block);
+        result.map(|_| executed)
+    }
+
+    pub fn read_execution_engine(&self) -> ExecutionEngine {
+        self.execution_engine
+    }
+
+    /// Pick how [`Machine::run_frame`] executes instructions, [`Machine::exec`] always
+    /// interprets a single one
+    pub fn set_execution_engine(&mut self, execution_engine: ExecutionEngine) {
+        self.execution_engine = execution_engine;
+        self.blocks.clear();
+    }
+
+    /// Whether decoded instructions are kept between executions, on by default
+    pub fn is_decode_cache_enabled(&self) -> bool {
+        self.decode_cache.is_enabled()
     }
 
-    pub fn pop_from_stack(&mut self) -> u16 {
-        let value = self.stack[self.stack_pointer as usize];
-        self.stack_pointer = self.stack_pointer.overflowing_sub(1).0;
-        value
+    /// Turn the decode cache off to decode every instruction as it is fetched, like a plain
+    /// interpreter loop
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache.set_enabled(enabled);
+    }
+
+    /// The big endian opcode stored at `address`, fetching is never reported to watchpoints.
+    /// Bytes past the end of ram read as 0
+    pub fn read_opcode(&self, address: u16) -> u16 {
+        let byte = |address: u16| self.ram.get(usize::from(address)).copied().unwrap_or(0);
+        ((byte(address) as u16) << 8) | (byte(address.overflowing_add(1).0) as u16)
     }
 }
 impl Machine {
-    pub fn exec(&mut self) {
-        let instruction1 = self.read_ram(self.program_counter);
-        let instruction2 = self.read_ram(self.program_counter.overflowing_add(1).0);
-        let merged_instruction = ((instruction1 as u16) << 8) | (instruction2 as u16);
-        println!("{:#x}", merged_instruction);
+    /// Watch reads and/or writes made by instructions to a range of ram
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.add(watchpoint);
+    }
+
+    /// Returns false if no watchpoint covered exactly `addresses`
+    pub fn remove_watchpoint(&mut self, addresses: &RangeInclusive<u16>) -> bool {
+        self.watchpoints.remove(addresses)
+    }
+
+    pub fn read_watchpoints(&self) -> &[Watchpoint] {
+        self.watchpoints.watchpoints()
+    }
+
+    /// True after a Stop watchpoint matched, nothing executes until the hits are taken
+    pub fn is_stopped_by_watchpoint(&self) -> bool {
+        self.watchpoints.is_stopped()
+    }
+
+    /// Watchpoint hits since the last call, oldest first, resumes a machine stopped by one
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watchpoints.take_hits()
+    }
+}
+impl Machine {
+    pub fn read_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Switch interpreter behaviors, every instruction consults these as it runs
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub(crate) fn wait_for_vblank(&mut self) {
+        self.waiting_for_vblank = true;
+    }
+
+    /// Whether exec does nothing until the next frame tick because of the display wait quirk
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    /// Whether the program stopped itself with 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.exited = true;
+    }
+}
+impl Machine {
+    /// Start collecting a [`TraceRecord`] for every executed instruction that passes `filter`,
+    /// None stops and drops what was collected. Cycles count from here
+    pub fn set_trace(&mut self, filter: Option<TraceFilter>) {
+        self.tracer = filter.map(Tracer::new);
+    }
 
-        parse_instruction(merged_instruction, self);
-        self.increment_program_counter(2);
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.is_some()
+    }
+
+    /// Trace records since the last call, oldest first
+    pub fn take_trace(&mut self) -> Vec<TraceRecord> {
+        self.tracer
+            .as_mut()
+            .map(Tracer::take_records)
+            .unwrap_or_default()
+    }
+
+    fn trace_instruction(
+        &mut self,
+        (program_counter, opcode, decoded): (u16, u16, Result<Operation, DecodeError>),
+        registers_before: [u8; 16],
+        decision: Option<OpcodeDecision>,
+        memory_writes: Vec<(u16, u8)>,
+    ) {
+        let frame = self.frame_count;
+        let Some(tracer) = &mut self.tracer else {
+            return;
+        };
+        let Some(cycle) = tracer.count(program_counter, frame) else {
+            return;
+        };
+        let mnemonic = match decoded {
+            Ok(operation) => operation.to_string(),
+            Err(_) => format!("DW {:#06X}", opcode),
+        };
+        let changed_registers = (0..16)
+            .filter(|&register| {
+                registers_before[register] != self.general_purpouse_registers[register]
+            })
+            .map(|register| (register as u8, self.general_purpouse_registers[register]))
+            .collect();
+        tracer.record(TraceRecord {
+            cycle,
+            frame,
+            program_counter,
+            opcode,
+            mnemonic,
+            changed_registers: Some(changed_registers),
+            index_register: self.index_register,
+            stack_pointer: self.stack_pointer,
+            memory_writes: Some(memory_writes),
+            decision,
+        });
+    }
+
+    pub fn read_opcode_policy(&self) -> &OpcodePolicy {
+        &self.opcode_policy
+    }
+
+    /// Decide what unknown opcodes and 0NNN machine code calls do, see [`OpcodePolicy`]
+    pub fn set_opcode_policy(&mut self, opcode_policy: OpcodePolicy) {
+        self.opcode_policy = opcode_policy;
+    }
+
+    /// Opcodes skipped under [`OpcodePolicy::LogAndSkip`] since the last call, oldest first
+    pub fn take_unknown_opcodes(&mut self) -> Vec<UnknownOpcode> {
+        std::mem::take(&mut self.unknown_opcodes)
+    }
+
+    /// Apply the opcode policy to the current instruction. The decision goes into the
+    /// instruction's trace record, also when it stops the run
+    pub(crate) fn handle_unknown_opcode(&mut self, opcode: u16) -> Result<(), MachineError> {
+        let program_counter = self.program_counter;
+        let (decision, handled) = match self.opcode_policy.clone() {
+            OpcodePolicy::Halt => (
+                OpcodeDecision::Halted,
+                Err(MachineError::InvalidOpcode {
+                    program_counter,
+                    opcode,
+                }),
+            ),
+            OpcodePolicy::LogAndSkip => (OpcodeDecision::Skipped, Ok(())),
+            OpcodePolicy::Ignore => (OpcodeDecision::Ignored, Ok(())),
+            OpcodePolicy::Handler(handler) => (OpcodeDecision::Handled, handler(self, opcode)),
+        };
+
+        let unknown_opcode = UnknownOpcode {
+            program_counter,
+            opcode,
+            decision,
+        };
+        if self.tracer.is_some() {
+            self.traced_decision = Some(decision);
+        }
+        if decision == OpcodeDecision::Skipped {
+            self.unknown_opcodes.push(unknown_opcode);
+        }
+        handled
+    }
+
+    /// Switch CXNN to the seeded generator, the same seed always yields the same numbers
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random = Random::seeded(seed);
+    }
+
+    pub fn read_random(&self) -> &Random {
+        &self.random
+    }
+
+    /// Pick where CXNN gets its numbers from, see [`Random`]
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    pub(crate) fn next_random(&mut self) -> u8 {
+        self.random.next_byte()
     }
 }
 impl Machine {
@@ -67,14 +485,58 @@ impl Machine {
 
     // would trigger on key press down
     pub fn set_pressed_keys(&mut self, pressed_key: u16) {
+        self.record_key_event(pressed_key, true);
         self.pressed_keys[pressed_key as usize] = true;
     }
 
     // would trigger on key release
     pub fn unset_pressed_keys(&mut self, released_key: u16) {
+        self.record_key_event(released_key, false);
         self.pressed_keys[released_key as usize] = false;
     }
+
+    /// Start or stop collecting every key transition, stopping drops what was collected
+    pub fn set_input_recording(&mut self, recording: bool) {
+        self.key_events = match recording {
+            true => Some(self.key_events.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// Key transitions since the last call while input recording is on, oldest first
+    pub fn take_key_events(&mut self) -> Vec<KeyEvent> {
+        self.key_events
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record_key_event(&mut self, key: u16, pressed: bool) {
+        let frame = self.frame_count;
+        // only actual transitions, holding a key down is not an event
+        if self.pressed_keys[key as usize] == pressed {
+            return;
+        }
+        if let Some(key_events) = &mut self.key_events {
+            key_events.push(KeyEvent {
+                frame,
+                key: key as u8,
+                pressed,
+            });
+        }
+    }
 }
+impl Machine {
+    pub fn read_flag_registers(&self) -> [u8; 16] {
+        self.flag_registers
+    }
+
+    /// Restore flags saved by an earlier run
+    pub fn write_to_flag_registers(&mut self, flags: [u8; 16]) {
+        self.flag_registers = flags;
+    }
+}
+
 impl Machine {
     pub fn read_general_purpouse_registers(&self, index: usize) -> u8 {
         self.general_purpouse_registers[index]
@@ -87,21 +549,67 @@ impl Machine {
 
 impl Machine {
     pub fn read_delay_timer(&self) -> u8 {
-        self.delay_timer
+        self.timers.read_delay_timer()
     }
 
     pub fn write_to_delay_timer(&mut self, value: u8) {
-        self.delay_timer = value;
+        self.timers.write_to_delay_timer(value);
     }
 }
 
 impl Machine {
     pub fn read_sound_timer(&self) -> u8 {
-        self.sound_timer
+        self.timers.read_sound_timer()
     }
 
     pub fn write_to_sound_timer(&mut self, value: u8) {
-        self.sound_timer = value;
+        self.timers.write_to_sound_timer(value);
+    }
+
+    /// Sound on/off events raised since the last call, oldest first
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        self.timers.take_sound_events()
+    }
+
+    /// Fill `output` with the PCM samples the buzzer currently produces, see [`Audio::generate`]
+    pub fn generate_audio(&mut self, sample_rate: u32, output: &mut [f32]) {
+        let sound_timer = self.read_sound_timer();
+        self.audio.generate(sound_timer, sample_rate, output);
+    }
+}
+
+impl Machine {
+    /// Advance one 60 Hz frame: count the timers down once.
+    /// Frontends call this on a wall clock, headless runs call it whenever they like
+    /// which keeps them deterministic
+    pub fn tick_frame(&mut self) {
+        self.timers.tick();
+        self.waiting_for_vblank = false;
+        self.frame_count += 1;
+    }
+
+    /// Execute `instructions_per_frame` instructions with the execution engine, then tick the
+    /// timers once. An error ends the frame early without ticking
+    pub fn run_frame(&mut self, instructions_per_frame: u32) -> Result<(), MachineError> {
+        let mut remaining = instructions_per_frame;
+        while remaining > 0 {
+            if self.watchpoints.is_stopped() {
+                break;
+            }
+            remaining -= match self.execution_engine {
+                ExecutionEngine::Interpreter => {
+                    self.exec()?;
+                    1
+                }
+                ExecutionEngine::Blocks => self.exec_block(remaining)?,
+            };
+        }
+        self.tick_frame();
+        Ok(())
+    }
+
+    pub fn read_frame_count(&self) -> u64 {
+        self.frame_count
     }
 }
 
@@ -122,30 +630,95 @@ impl Machine {
     pub fn write_to_program_counter(&mut self, value: u16) {
         self.program_counter = value;
     }
-    pub fn increment_program_counter(&mut self, value: u16) {
+    pub(crate) fn increment_program_counter(&mut self, value: u16) {
         self.program_counter = self.program_counter.overflowing_add(value).0
     }
+
+    /// Skip the instruction after the current one, which is 4 bytes long if it is XO-CHIP F000 NNNN
+    pub(crate) fn skip_next_instruction(&mut self) {
+        let next_instruction = self.program_counter.overflowing_add(2).0;
+        let is_long_instruction = self.read_opcode(next_instruction) == 0xF000;
+        match is_long_instruction {
+            true => self.increment_program_counter(4),
+            false => self.increment_program_counter(2),
+        }
+    }
 }
 impl Machine {
-    pub fn copy_to_ram(&mut self, data: Vec<u8>, start_address: u16) {
-        // I  discovered that memory protection was not a thing for chip 8, so I will let them
-        // write to the forbidden zone, but i want logs.
-        // for out of bounds, the emulator should halt and display an error message
-        const MIN: u16 = 0x200;
-        // after validations
-        if start_address < MIN {
-            println!("accessing {:?} ", start_address)
+    /// Nothing is written when `data` does not fit in ram
+    pub fn copy_to_ram(&mut self, data: Vec<u8>, start_address: u16) -> Result<(), MachineError> {
+        // memory protection was not a thing for chip 8, programs may write below 0x200 too,
+        // watchpoints and traces show those writes. Out of bounds, the emulator halts with an error
+        let end_address = usize::from(start_address) + data.len();
+        if end_address > self.ram.len() {
+            return Err(MachineError::MemoryOutOfRange {
+                program_counter: self.program_counter,
+                address: usize::from(start_address).max(self.ram.len()),
+            });
         }
-
         for (i, line) in data.into_iter().enumerate() {
-            self.ram[usize::from(start_address) + i] = line
+            let address = usize::from(start_address) + i;
+            if let Some(current_instruction) = self.current_instruction {
+                let old_value = self.ram[address];
+                self.watchpoints.check(
+                    current_instruction,
+                    address as u16,
+                    Access::Write,
+                    old_value,
+                    line,
+                );
+                if self.tracer.is_some() {
+                    self.traced_writes.push((address as u16, line));
+                }
+            }
+            self.ram[address] = line
         }
+        let length = end_address - usize::from(start_address);
+        self.decode_cache
+            .invalidate(usize::from(start_address), length);
+        self.blocks.invalidate(usize::from(start_address), length);
+        Ok(())
+    }
+    pub fn read_ram(&self, address: u16) -> Result<u8, MachineError> {
+        let Some(value) = self.ram.get(usize::from(address)).copied() else {
+            return Err(MachineError::MemoryOutOfRange {
+                program_counter: self.program_counter,
+                address: usize::from(address),
+            });
+        };
+        if let Some(current_instruction) = self.current_instruction {
+            self.watchpoints
+                .check(current_instruction, address, Access::Read, value, value);
+        }
+        Ok(value)
+    }
+
+    /// Drop every decoded instruction and block, for when ram is replaced as a whole
+    pub(crate) fn clear_translations(&mut self) {
+        self.decode_cache.clear();
+        self.blocks.clear();
     }
-    pub fn read_ram(&self, address: u16) -> u8 {
-        return self.ram[usize::from(address)];
+
+    pub fn read_memory_mode(&self) -> MemoryMode {
+        self.memory_mode
     }
 
-    pub fn update_program_counter(&mut self, new_value: u16) {
+    /// Grow or shrink ram, anything that still fits is kept
+    pub fn set_memory_mode(&mut self, memory_mode: MemoryMode) {
+        let ram_size = match memory_mode {
+            MemoryMode::Chip8 => RAM_SIZE,
+            MemoryMode::XoChip => XO_CHIP_RAM_SIZE,
+        };
+        self.memory_mode = memory_mode;
+        self.ram.resize(ram_size, 0);
+        self.clear_translations();
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    pub(crate) fn update_program_counter(&mut self, new_value: u16) {
         self.program_counter = new_value;
     }
 
@@ -156,16 +729,43 @@ impl Machine {
             program_counter: SOFT_MIN,
             stack_pointer: 0,
             index_register: 0,
-            sound_timer: 0,
-            delay_timer: 0,
-            ram: [0; 4096],
+            timers: Timers::new(),
+            audio: Audio::new(),
+            frame_count: 0,
+            memory_mode: MemoryMode::Chip8,
+            ram: vec![0; RAM_SIZE],
             stack: [0; 16],
             pressed_keys: [false; 16],
             screen,
+            quirks: Quirks::default(),
+            waiting_for_vblank: false,
+            exited: false,
+            flag_registers: [0; 16],
+            random: Random::default(),
+            tracer: None,
+            traced_decision: None,
+            traced_writes: Vec::new(),
+            decode_cache: DecodeCache::new(),
+            execution_engine: ExecutionEngine::default(),
+            blocks: BlockCache::new(),
+            opcode_policy: OpcodePolicy::default(),
+            unknown_opcodes: Vec::new(),
+            watchpoints: Watchpoints::new(),
+            current_instruction: None,
+            key_events: None,
         };
 
-        let fontset: Vec<u8> = FONTSET.try_into().expect("convert fontset to vec");
-        machine.copy_to_ram(fontset, 0x50);
+        let fontset_address = usize::from(FONTSET_ADDRESS);
+        machine.ram[fontset_address..fontset_address + FONTSET_SIZE].copy_from_slice(&FONTSET);
+        let big_fontset_address = usize::from(BIG_FONTSET_ADDRESS);
+        machine.ram[big_fontset_address..big_fontset_address + BIG_FONTSET_SIZE]
+            .copy_from_slice(&BIG_FONTSET);
         machine
     }
 }
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
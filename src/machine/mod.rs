@@ -1,5 +1,17 @@
+pub mod audio;
+pub mod blocks;
+pub mod cache;
+pub mod error;
 pub mod instructions;
+#[allow(clippy::module_inception)]
 pub mod machine;
-pub mod rom;
 pub mod operations;
+pub mod policy;
+pub mod quirks;
+pub mod random;
+pub mod rom;
+pub mod savestate;
 pub mod screen;
+pub mod timers;
+pub mod trace;
+pub mod watchpoints;
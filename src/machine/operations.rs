@@ -1,71 +1,141 @@
-use std::{io::Read, u16, usize};
-
-use super::machine::Machine;
+use super::{
+    audio::PATTERN_SIZE,
+    error::MachineError,
+    machine::{Machine, BIG_FONTSET_ADDRESS},
+    quirks::IndexIncrement,
+    screen::{PLANE_1, PLANE_2},
+};
 
 ///
 /// updates to the program counter are done with "-2" since machine increments PC by 2 after each
 /// execution for instructions that set their own pc
 impl Machine {
     /// Execute machine language subroutine at address NNN
-    pub fn op_0nnn_sys(&mut self, address: u16) {
-        self.update_program_counter(address -2 );
+    /// There is no COSMAC VIP CPU to run it on, the opcode policy decides what happens instead
+    pub(crate) fn op_0nnn_sys(&mut self, address: u16) -> Result<(), MachineError> {
+        self.handle_unknown_opcode(address)
     }
 
     /// Clear the screen
-    pub fn op_00e0_cls(&mut self) {
+    pub(crate) fn op_00e0_cls(&mut self) {
         self.screen.clear_screen();
     }
 
     /// Return from a subroutine
-    pub fn op_00ee_ret(&mut self) {
-        let return_address = self.pop_from_stack();
+    pub(crate) fn op_00ee_ret(&mut self) -> Result<(), MachineError> {
+        let return_address = self.pop_from_stack()?;
         self.update_program_counter(return_address);
+        Ok(())
+    }
+
+    /// Scroll the display down by N pixels
+    pub(crate) fn op_00cn_scd(&mut self, rows: u8) {
+        self.screen.scroll_down(rows as usize);
+    }
+
+    /// Scroll the display right by 4 pixels
+    pub(crate) fn op_00fb_scr(&mut self) {
+        self.screen.scroll_right(4);
+    }
+
+    /// Scroll the display left by 4 pixels
+    pub(crate) fn op_00fc_scl(&mut self) {
+        self.screen.scroll_left(4);
+    }
+
+    /// Exit the interpreter
+    pub(crate) fn op_00fd_exit(&mut self) {
+        self.exit();
+    }
+
+    /// Switch to the 64x32 low resolution display
+    pub(crate) fn op_00fe_low(&mut self) {
+        self.screen.set_hires(false);
+    }
+
+    /// Switch to the 128x64 high resolution display
+    pub(crate) fn op_00ff_high(&mut self) {
+        self.screen.set_hires(true);
     }
 
     /// Jump to address NNN
-    pub fn op_1nnn_jmp(&mut self, address: u16) {
-        self.write_to_program_counter(address);
+    pub(crate) fn op_1nnn_jmp(&mut self, address: u16) {
+        self.update_program_counter(address.wrapping_sub(2));
     }
 
     /// Execute subroutine starting at address NNN
-    pub fn op_2nnn_call(&mut self, address: u16) {
+    pub(crate) fn op_2nnn_call(&mut self, address: u16) -> Result<(), MachineError> {
         let return_address = self.read_program_counter();
-        self.push_to_stack(return_address);
-        self.update_program_counter(address -2);
+        self.push_to_stack(return_address)?;
+        self.update_program_counter(address.wrapping_sub(2));
+        Ok(())
     }
 
     /// Skip the following instruction if the value of register VX equals NN
-    pub fn op_3xnn_se(&mut self, register_x: u8, value: u8) {
+    pub(crate) fn op_3xnn_se(&mut self, register_x: u8, value: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         if register_x_value == value {
-            self.increment_program_counter(2);
+            self.skip_next_instruction();
         }
     }
 
     /// Skip the following instruction if the value of register VX is not equal to NN
-    pub fn op_4xnn_sne(&mut self, register_x: u8, value: u8) {
+    pub(crate) fn op_4xnn_sne(&mut self, register_x: u8, value: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         if register_x_value != value {
-            self.increment_program_counter(2);
+            self.skip_next_instruction();
         }
     }
 
     /// Skip the following instruction if the value of register VX is equal to the value of register VY
-    pub fn op_5xy0_se(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_5xy0_se(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
         if register_x_value == register_y_value {
-            self.increment_program_counter(2);
+            self.skip_next_instruction();
         }
     }
 
+    /// Store the values of registers VX to VY inclusive in memory starting at address I, I is unchanged
+    /// VX is stored first, the range is walked backwards when X is larger than Y. XO-CHIP
+    pub(crate) fn op_5xy2_save(
+        &mut self,
+        register_x: u8,
+        register_y: u8,
+    ) -> Result<(), MachineError> {
+        let values: Vec<u8> = register_range(register_x, register_y)
+            .into_iter()
+            .map(|register| self.read_general_purpouse_registers(register as usize))
+            .collect();
+        let index_register_value = self.read_index_register();
+        self.copy_to_ram(values, index_register_value)
+    }
+
+    /// Fill registers VX to VY inclusive with the values stored in memory starting at address I, I is unchanged
+    /// VX is loaded first, the range is walked backwards when X is larger than Y. XO-CHIP
+    pub(crate) fn op_5xy3_load(
+        &mut self,
+        register_x: u8,
+        register_y: u8,
+    ) -> Result<(), MachineError> {
+        let index_register_value = self.read_index_register();
+        let registers = register_range(register_x, register_y);
+        let values = (0..registers.len())
+            .map(|offset| self.read_ram(index_register_value.overflowing_add(offset as u16).0))
+            .collect::<Result<Vec<u8>, MachineError>>()?;
+        for (register, value) in registers.into_iter().zip(values) {
+            self.write_to_general_purpouse_registers(register as usize, value);
+        }
+        Ok(())
+    }
+
     /// Store number NN in register VX
-    pub fn op_6xnn_mov(&mut self, register_x: u8, value: u8) {
+    pub(crate) fn op_6xnn_mov(&mut self, register_x: u8, value: u8) {
         self.write_to_general_purpouse_registers(register_x as usize, value);
     }
 
     /// Add the value NN to register VX
-    pub fn op_7xnn_add(&mut self, register_x: u8, value: u8) {
+    pub(crate) fn op_7xnn_add(&mut self, register_x: u8, value: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
         let result = register_x_value.overflowing_add(value);
@@ -73,21 +143,22 @@ impl Machine {
     }
 
     /// Store the value of register VY in register VX
-    pub fn op_8xy0_ymovx(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy0_ymovx(&mut self, register_x: u8, register_y: u8) {
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
         self.write_to_general_purpouse_registers(register_x as usize, register_y_value);
     }
 
     /// Set VX to VX OR VY
-    pub fn op_8xy1_setvx2vxorvy(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy1_setvx2vxorvy(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
         let or_operation_result = register_y_value | register_x_value;
         self.write_to_general_purpouse_registers(register_x as usize, or_operation_result);
+        self.reset_vf_after_logic();
     }
 
     /// Set VX to VX AND VY
-    pub fn op_8xy2_setvx2vxandvy(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy2_setvx2vxandvy(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
@@ -95,10 +166,11 @@ impl Machine {
         let and_operation_result = register_x_value & register_y_value;
 
         self.write_to_general_purpouse_registers(register_x as usize, and_operation_result);
+        self.reset_vf_after_logic();
     }
 
     /// Set VX to VX XOR VY
-    pub fn op_8xy3_setvx2vxxorvy(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy3_setvx2vxxorvy(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
@@ -106,12 +178,13 @@ impl Machine {
         let xor_operation_result = register_x_value ^ register_y_value;
 
         self.write_to_general_purpouse_registers(register_x as usize, xor_operation_result);
+        self.reset_vf_after_logic();
     }
 
     /// Add the value of register VY to register VX
     /// Set VF to 01 if a carry occurs
     /// Set VF to 00 if a carry does not occur
-    pub fn op_8xy4_add(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy4_add(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
@@ -120,7 +193,7 @@ impl Machine {
 
         self.write_to_general_purpouse_registers(register_x as usize, result.0);
 
-        if result.1 == true {
+        if result.1 {
             self.write_to_general_purpouse_registers(0xF, 0x01);
         } else {
             self.write_to_general_purpouse_registers(0xF, 0x00);
@@ -130,7 +203,7 @@ impl Machine {
     /// Subtract the value of register VY from register VX
     /// Set VF to 00 if a borrow occurs
     /// Set VF to 01 if a borrow does not occur
-    pub fn op_8xy5_sub(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy5_sub(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
@@ -139,7 +212,7 @@ impl Machine {
 
         self.write_to_general_purpouse_registers(register_x as usize, result.0);
 
-        if result.1 == true {
+        if result.1 {
             self.write_to_general_purpouse_registers(0xF, 0x00);
         } else {
             self.write_to_general_purpouse_registers(0xF, 0x01);
@@ -149,18 +222,20 @@ impl Machine {
     /// Store the value of register VY shifted right one bit in register VX
     /// Set register VF to the least significant bit prior to the shift
     /// VY is unchanged
-    pub fn op_8xy6_shr(&mut self, register_y: u8, register_x: u8) {
-        let register_y_value = self.read_general_purpouse_registers(register_y as usize);
-        let least_significant_bit = register_y_value & 1;
-        self.write_to_general_purpouse_registers(0xF, least_significant_bit);
-        let shifted_value = register_y_value >> 1;
+    /// VX is shifted in place instead unless the shift quirk is set
+    pub(crate) fn op_8xy6_shr(&mut self, register_x: u8, register_y: u8) {
+        let source = self.shift_source(register_x, register_y);
+        let source_value = self.read_general_purpouse_registers(source as usize);
+        let least_significant_bit = source_value & 1;
+        let shifted_value = source_value >> 1;
         self.write_to_general_purpouse_registers(register_x as usize, shifted_value);
+        self.write_to_general_purpouse_registers(0xF, least_significant_bit);
     }
 
     /// Set register VX to the value of VY minus VX
     /// Set VF to 00 if a borrow occurs
     /// Set VF to 01 if a borrow does not occur
-    pub fn op_8xy7_sub(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_8xy7_subn(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
 
@@ -168,115 +243,127 @@ impl Machine {
 
         self.write_to_general_purpouse_registers(register_x as usize, sub_result.0);
 
-        if sub_result.1 == true {
+        if sub_result.1 {
             // means a borrow has occured
-            self.write_to_general_purpouse_registers(0xF, 00);
+            self.write_to_general_purpouse_registers(0xF, 0x00);
         } else {
-            self.write_to_general_purpouse_registers(0xF, 01);
+            self.write_to_general_purpouse_registers(0xF, 0x01);
         }
     }
 
     /// Store the value of register VY shifted left one bit in register VX
     /// Set register VF to the most significant bit prior to the shift
     /// VY is unchanged
-    pub fn op_8xye_shl(&mut self, register_x: u8, register_y: u8) {
-        let register_y_value = self.read_general_purpouse_registers(register_y as usize);
+    /// VX is shifted in place instead unless the shift quirk is set
+    pub(crate) fn op_8xye_shl(&mut self, register_x: u8, register_y: u8) {
+        let source = self.shift_source(register_x, register_y);
+        let source_value = self.read_general_purpouse_registers(source as usize);
 
-        let most_significant_bit = (register_y_value >> 7) & 1;
-        let shifted_value = register_y_value << 1;
+        let most_significant_bit = (source_value >> 7) & 1;
+        let shifted_value = source_value << 1;
         self.write_to_general_purpouse_registers(register_x as usize, shifted_value);
         self.write_to_general_purpouse_registers(0xF, most_significant_bit);
     }
 
     /// Skip the following instruction if the value of register VX is not equal to the value of register VY
-    pub fn op_9xy0_sne(&mut self, register_x: u8, register_y: u8) {
+    pub(crate) fn op_9xy0_sne(&mut self, register_x: u8, register_y: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         let register_y_value = self.read_general_purpouse_registers(register_y as usize);
         if register_x_value != register_y_value {
-            self.increment_program_counter(2);
+            self.skip_next_instruction();
         }
     }
 
     /// Store memory address NNN in register I
-    pub fn op_annn_movi(&mut self, address: u16) {
+    pub(crate) fn op_annn_movi(&mut self, address: u16) {
         self.write_to_index_register(address);
     }
 
     /// Jump to address NNN + V0
-    pub fn op_bnnn_jmp_plus_v0(&mut self, value_nnn: u16) {
+    /// With the jump quirk this is XNN + VX, X being the highest nibble of NNN
+    pub(crate) fn op_bnnn_jmp_plus_v0(&mut self, value_nnn: u16) {
         // having problems here, nnn is actually supposed to be 12 bits,
-        let register_0_value = self.read_general_purpouse_registers(0);
-        let mut to_jump = value_nnn + register_0_value as u16;
+        let offset_register = match self.read_quirks().jump_uses_vx {
+            true => (value_nnn >> 8) as usize,
+            false => 0,
+        };
+        let offset_register_value = self.read_general_purpouse_registers(offset_register);
+        let mut to_jump = value_nnn + offset_register_value as u16;
 
         if to_jump > 0xFFF {
-            to_jump = to_jump - 0xFFF
+            to_jump -= 0xFFF
         }
-        self.update_program_counter(to_jump - 2);
+        self.update_program_counter(to_jump.wrapping_sub(2));
     }
 
     /// Set VX to a random number with a mask of NN
-    pub fn op_cxnn_mov_rand(&mut self, register_x: u8, mask: u8) {
-        //TODO: would be fun to add a more deterministic option here
-        let mut buf = [0; 16];
-        std::fs::File::open("/dev/urandom")
-            .unwrap()
-            .read_exact(&mut buf)
-            .unwrap();
-        let masked_number = buf[0] & mask;
+    pub(crate) fn op_cxnn_mov_rand(&mut self, register_x: u8, mask: u8) {
+        let random_number = self.next_random();
+        let masked_number = random_number & mask;
         self.write_to_general_purpouse_registers(register_x as usize, masked_number);
     }
 
     /// Draw a sprite at position VX, VY with N bytes of sprite data starting at the address stored in I
     /// Set VF to 01 if any set pixels are changed to unset, and 00 otherwise
-    pub fn op_dxyn_drw(&mut self, register_x: u8, register_y: u8, n_bytes: u8) {
-        let register_x_value = self.read_general_purpouse_registers(register_x as usize);
-        let register_y_value = self.read_general_purpouse_registers(register_y as usize);
-        let index_register_value = self.read_index_register();
-
-        let values: Vec<u8> = (0..n_bytes)
-            .map(|f| self.read_ram(index_register_value.overflowing_add(f as u16).0))
+    /// When N is 0 a 16x16 sprite made of 32 bytes is drawn instead (SUPER-CHIP)
+    pub(crate) fn op_dxyn_drw(
+        &mut self,
+        register_x: u8,
+        register_y: u8,
+        n_bytes: u8,
+    ) -> Result<(), MachineError> {
+        let x_coord = self.read_general_purpouse_registers(register_x as usize) as usize;
+        let y_coord = self.read_general_purpouse_registers(register_y as usize) as usize;
+        // The last digit determines how many rows high our sprite is, 0 means a 16x16 sprite
+        let (num_rows, sprite_width) = match n_bytes {
+            0 => (16, 16),
+            n => (n as usize, 8),
+        };
+        let bytes_per_row = sprite_width / 8;
+        let clip_sprites = self.read_quirks().clip_sprites;
+        let screen_width = self.screen.width();
+        let screen_height = self.screen.height();
+
+        // XO-CHIP: every selected plane gets its own sprite data, plane 1 first, one after another
+        // All of it is read before drawing, so a sprite running off the end of ram draws nothing
+        let planes: Vec<u8> = [PLANE_1, PLANE_2]
+            .into_iter()
+            .filter(|plane| self.screen.selected_planes() & plane != 0)
             .collect();
-
-        let update_screen_state =
-            self.screen
-                .update_screen_state(register_x_value, register_y_value, values);
-
-        match update_screen_state {
-            true => self.write_to_general_purpouse_registers(0xF, 0x01),
-            false => self.write_to_general_purpouse_registers(0xF, 0x00),
-        }
-
-        self.screen.draw();
-    }
-
-    pub fn op_dxyn_drw2(&mut self, register_x: u8, register_y: u8, n_bytes: u8) {
-        let x_coord = self.read_general_purpouse_registers(register_x as usize) as u16;
-        let y_coord = self.read_general_purpouse_registers(register_y as usize) as u16;
-        // The last digit determines how many rows high our sprite is
-        let num_rows = n_bytes;
+        let sprite_address = self.read_index_register();
+        let sprite = (0..planes.len() * num_rows * bytes_per_row)
+            .map(|offset| self.read_ram(sprite_address.wrapping_add(offset as u16)))
+            .collect::<Result<Vec<u8>, MachineError>>()?;
 
         // Keep track if any pixels were flipped
         let mut flipped = false;
-        // Iterate over each row of our sprite
-        for y_line in 0..num_rows {
-            // Determine which memory address our row's data is stored
-            let addr = self.read_index_register() + y_line as u16;
-            let pixels = self.read_ram(addr);
-            // Iterate over each column in our row
-            for x_line in 0..8 {
-                // Use a mask to fetch current pixel's bit. Only flip if a 1
-                if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                    // Sprites should wrap around screen, so apply modulo
-                    const SCREEN_WIDTH: usize = 64;
-                    let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                    const SCREEN_HEIGHT: usize = 32;
-                    let y = (y_coord + y_line as u16) as usize % SCREEN_HEIGHT;
-
-                    // Get our pixel's index in the 1D screen array
-                    let idx = x + SCREEN_WIDTH * y;
-                    // Check if we're about to flip the pixel and set
-                    flipped |= self.screen.display[idx];
-                    self.screen.display[idx] ^= true;
+        for (plane, plane_sprite) in planes
+            .into_iter()
+            .zip(sprite.chunks(num_rows * bytes_per_row))
+        {
+            // Iterate over each row of our sprite
+            for (y_line, row) in plane_sprite.chunks(bytes_per_row).enumerate() {
+                let pixels = match row {
+                    [left, right] => (*left as u16) << 8 | *right as u16,
+                    _ => (row[0] as u16) << 8,
+                };
+                // Iterate over each column in our row
+                for x_line in 0..sprite_width {
+                    // Use a mask to fetch current pixel's bit. Only flip if a 1
+                    if (pixels & (0b1000_0000_0000_0000 >> x_line)) != 0 {
+                        // The starting position always wraps, the sprite itself is either clipped
+                        // at the edges or wraps around depending on the quirk
+                        let x = x_coord % screen_width + x_line;
+                        let y = y_coord % screen_height + y_line;
+                        if clip_sprites && (x >= screen_width || y >= screen_height) {
+                            continue;
+                        }
+
+                        // Check if we're about to flip the pixel and set
+                        flipped |=
+                            self.screen
+                                .flip_pixel(x % screen_width, y % screen_height, plane);
+                    }
                 }
             }
         }
@@ -286,48 +373,76 @@ impl Machine {
         } else {
             self.write_to_general_purpouse_registers(0xF, 0);
         }
+
+        if self.read_quirks().display_wait {
+            self.wait_for_vblank();
+        }
+        Ok(())
+    }
+
+    /// Store the 16 bit address NNNN from the following word in register I, XO-CHIP
+    /// The instruction is 4 bytes long, the address is skipped over here
+    pub(crate) fn op_f000_longi(&mut self, address: u16) {
+        self.write_to_index_register(address);
+        self.increment_program_counter(2);
+    }
+
+    /// Load the 16 byte audio pattern buffer from memory starting at address I, XO-CHIP
+    pub(crate) fn op_f002_audio(&mut self) -> Result<(), MachineError> {
+        let index_register_value = self.read_index_register();
+        let mut pattern = [0; PATTERN_SIZE];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_ram(index_register_value.overflowing_add(offset as u16).0)?;
+        }
+        self.audio.write_to_pattern(pattern);
+        Ok(())
+    }
+
+    /// Select the drawing planes given by N, XO-CHIP
+    pub(crate) fn op_fn01_plane(&mut self, planes: u8) {
+        self.screen.select_planes(planes);
     }
 
     /// Skip the following instruction if the key corresponding to the hex value currently stored in register VX is pressed
-    pub fn op_ex9e_skprs(&mut self, register_x: u8) {
+    pub(crate) fn op_ex9e_skprs(&mut self, register_x: u8) {
         let pressed_key = self.read_general_purpouse_registers(register_x as usize);
         const MAX_KEYS: u8 = 16;
-        if pressed_key > MAX_KEYS {
+        if pressed_key >= MAX_KEYS {
             // do nothing, unknown key is being checked
         } else {
             let pressed_keys = self.read_pressed_keys();
-            if pressed_keys[pressed_key as usize] == true {
-                self.increment_program_counter(2);
+            if pressed_keys[pressed_key as usize] {
+                self.skip_next_instruction();
             }
         }
     }
 
     /// Skip the following instruction if the key corresponding to the hex value currently stored in register VX is not pressed
-    pub fn op_exa1_sknprs(&mut self, register_x: u8) {
+    pub(crate) fn op_exa1_sknprs(&mut self, register_x: u8) {
         let pressed_key = self.read_general_purpouse_registers(register_x as usize);
         const MAX_KEYS: u8 = 16;
-        if pressed_key > MAX_KEYS {
+        if pressed_key >= MAX_KEYS {
             // do nothing, unknown key is being checked
         } else {
             let pressed_keys = self.read_pressed_keys();
-            if pressed_keys[pressed_key as usize] == false {
-                self.increment_program_counter(2);
+            if !pressed_keys[pressed_key as usize] {
+                self.skip_next_instruction();
             }
         }
     }
     /// Store the current value of the delay timer in register VX
-    pub fn op_fx07_mov_dt(&mut self, register_x: u8) {
+    pub(crate) fn op_fx07_mov_dt(&mut self, register_x: u8) {
         let delay_timer_value = self.read_delay_timer();
         self.write_to_general_purpouse_registers(register_x as usize, delay_timer_value);
     }
 
     /// Wait for a keypress and store the result in register VX
-    pub fn op_fx0a_wait_key(&mut self, register_x: u8) {
+    pub(crate) fn op_fx0a_wait_key(&mut self, register_x: u8) {
         let keys = self.read_pressed_keys();
         let mut pressed = false;
 
-        for i in 0..keys.len() {
-            if keys[i] {
+        for (i, key) in keys.iter().enumerate() {
+            if *key {
                 self.write_to_general_purpouse_registers(register_x as usize, i as u8);
                 pressed = true;
                 break;
@@ -337,84 +452,144 @@ impl Machine {
         if !pressed {
             // Redo opcode next cycle
             let program_counter = self.read_program_counter();
-            self.write_to_program_counter(program_counter - 2);
+            self.write_to_program_counter(program_counter.wrapping_sub(2));
         }
     }
 
     /// Set the delay timer to the value of register VX
-    pub fn op_fx15_set_dly(&mut self, register_x: u8) {
+    pub(crate) fn op_fx15_set_dly(&mut self, register_x: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         self.write_to_delay_timer(register_x_value);
     }
 
     /// Set the sound timer to the value of register VX
-    pub fn op_fx18_set_st(&mut self, register_x: u8) {
+    pub(crate) fn op_fx18_set_st(&mut self, register_x: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         self.write_to_sound_timer(register_x_value);
     }
 
     /// Add the value stored in register VX to register I
-    pub fn op_fx1e_mov_vi(&mut self, register_x: u8) {
+    pub(crate) fn op_fx1e_mov_vi(&mut self, register_x: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
         let register_i_value = self.read_index_register();
         self.write_to_index_register(register_i_value.overflowing_add(register_x_value as u16).0);
     }
 
     /// Set I to the memory address of the sprite data corresponding to the hexadecimal digit stored in register VX
-    pub fn op_fx29(&mut self, register_x: u8) {
+    pub(crate) fn op_fx29(&mut self, register_x: u8) {
         let register_x_value = self.read_general_purpouse_registers(register_x as usize);
 
-        //invalid default sprite, only 16 are supported, just send 0 for now,
-        // TODO:: make an invalid box to display
+        // only 16 digits are supported, anything else gets the sprite for 0
         if register_x_value > 0xF {
-            println!("oh no");
             self.write_to_index_register(0x50);
         } else {
             self.write_to_index_register(0x50 + (register_x_value as u16 * 5));
         }
     }
 
+    /// Set I to the memory address of the large 8x10 sprite for the hexadecimal digit stored in register VX
+    pub(crate) fn op_fx30(&mut self, register_x: u8) {
+        let register_x_value = self.read_general_purpouse_registers(register_x as usize);
+        let digit = register_x_value & 0xF;
+        self.write_to_index_register(BIG_FONTSET_ADDRESS + digit as u16 * 10);
+    }
+
     /// Store the binary-coded decimal equivalent of the value stored in register VX at addresses I, I + 1, and I + 2
-    pub fn op_fx33(&mut self, register_x: u8) {
+    pub(crate) fn op_fx33(&mut self, register_x: u8) -> Result<(), MachineError> {
         let register_x_value: u8 = self.read_general_purpouse_registers(register_x as usize);
         let hundred = register_x_value / 100;
         let ten = (register_x_value - (hundred * 100)) / 10;
         let one = register_x_value - (hundred * 100) - (ten * 10);
         let data = vec![hundred, ten, one];
         let index_register_value = self.read_index_register();
-        self.copy_to_ram(data, index_register_value);
+        self.copy_to_ram(data, index_register_value)
+    }
+
+    /// Set the audio pitch register to the value of register VX, XO-CHIP
+    pub(crate) fn op_fx3a_pitch(&mut self, register_x: u8) {
+        let register_x_value = self.read_general_purpouse_registers(register_x as usize);
+        self.audio.write_to_pitch(register_x_value);
     }
 
     /// Store the values of registers V0 to VX inclusive in memory starting at address I
-    /// I is set to I + X + 1 after operation²
-    pub fn op_fx55(&mut self, register_x: u8) {
+    /// I is set to I + X + 1 after operation², see the load/store quirk
+    pub(crate) fn op_fx55(&mut self, register_x: u8) -> Result<(), MachineError> {
         let values: Vec<u8> = (0..=register_x)
             .map(|register_address| self.read_general_purpouse_registers(register_address as usize))
             .collect();
 
         let index_register_value = self.read_index_register();
 
-        self.copy_to_ram(values, index_register_value);
+        self.copy_to_ram(values, index_register_value)?;
 
         // this should be just the x value in the instruction, not the value of x register
-        // overflow may happen here, not sure if I need any involvement here but TODO: investigate
-        let new_index_register_value = index_register_value + register_x as u16 + 1;
-        self.write_to_index_register(new_index_register_value);
+        self.increment_index_after_load_store(index_register_value, register_x);
+        Ok(())
     }
 
     /// Fill registers V0 to VX inclusive with the values stored in memory starting at address I
-    /// I is set to I + X + 1 after operation²
-    pub fn op_fx65(&mut self, register_x: u8) {
+    /// I is set to I + X + 1 after operation², see the load/store quirk
+    pub(crate) fn op_fx65(&mut self, register_x: u8) -> Result<(), MachineError> {
         let index_register_value = self.read_index_register();
 
-        let values: Vec<u8> = (0..=register_x)
-            .map(|i| self.read_ram(index_register_value + i as u16))
-            .collect();
+        let values = (0..=register_x)
+            .map(|i| self.read_ram(index_register_value.wrapping_add(i as u16)))
+            .collect::<Result<Vec<u8>, MachineError>>()?;
 
         values.into_iter().enumerate().for_each(|f| {
             self.write_to_general_purpouse_registers(f.0, f.1);
         });
-        let new_index_register_value = index_register_value + register_x as u16 + 1;
+        self.increment_index_after_load_store(index_register_value, register_x);
+        Ok(())
+    }
+
+    /// Store the values of registers V0 to VX inclusive in the persistent flag registers
+    pub(crate) fn op_fx75(&mut self, register_x: u8) {
+        let mut flags = self.read_flag_registers();
+        for (register, flag) in flags.iter_mut().enumerate().take(register_x as usize + 1) {
+            *flag = self.read_general_purpouse_registers(register);
+        }
+        self.write_to_flag_registers(flags);
+    }
+
+    /// Fill registers V0 to VX inclusive with the values stored in the persistent flag registers
+    pub(crate) fn op_fx85(&mut self, register_x: u8) {
+        let flags = self.read_flag_registers();
+        for (register, flag) in flags.into_iter().enumerate().take(register_x as usize + 1) {
+            self.write_to_general_purpouse_registers(register, flag);
+        }
+    }
+}
+
+/// Registers X to Y inclusive in the order 5XY2 and 5XY3 walk them
+fn register_range(register_x: u8, register_y: u8) -> Vec<u8> {
+    match register_x <= register_y {
+        true => (register_x..=register_y).collect(),
+        false => (register_y..=register_x).rev().collect(),
+    }
+}
+
+/// Quirk helpers shared by the operations above
+impl Machine {
+    fn reset_vf_after_logic(&mut self) {
+        if self.read_quirks().vf_reset {
+            self.write_to_general_purpouse_registers(0xF, 0x00);
+        }
+    }
+
+    fn shift_source(&self, register_x: u8, register_y: u8) -> u8 {
+        match self.read_quirks().shift_uses_vy {
+            true => register_y,
+            false => register_x,
+        }
+    }
+
+    fn increment_index_after_load_store(&mut self, index_register_value: u16, register_x: u8) {
+        let new_index_register_value = match self.read_quirks().load_store {
+            IndexIncrement::XPlusOne => index_register_value.wrapping_add(register_x as u16 + 1),
+            IndexIncrement::X => index_register_value.wrapping_add(register_x as u16),
+            IndexIncrement::Unchanged => index_register_value,
+        };
         self.write_to_index_register(new_index_register_value);
     }
 }
@@ -0,0 +1,74 @@
+use std::{fmt, sync::Arc};
+
+use super::{error::MachineError, machine::Machine};
+
+/// Runs in place of an unknown opcode, PC is advanced past it afterwards like for any instruction
+pub type OpcodeHandler = Arc<dyn Fn(&mut Machine, u16) -> Result<(), MachineError> + Send + Sync>;
+
+/// What happens on an opcode no supported variant defines, and on 0NNN, which calls machine code
+/// for the COSMAC VIP's CPU and cannot run here. Seeing these usually means the ROM targets a
+/// different CHIP-8 variant
+#[derive(Clone, Default)]
+pub enum OpcodePolicy {
+    /// Stop with [`MachineError::InvalidOpcode`]
+    #[default]
+    Halt,
+    /// Keep going, the opcode is collected for [`Machine::take_unknown_opcodes`]
+    LogAndSkip,
+    /// Keep going as if the opcode was not there
+    Ignore,
+    Handler(OpcodeHandler),
+}
+
+impl fmt::Debug for OpcodePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpcodePolicy::Halt => write!(f, "Halt"),
+            OpcodePolicy::LogAndSkip => write!(f, "LogAndSkip"),
+            OpcodePolicy::Ignore => write!(f, "Ignore"),
+            OpcodePolicy::Handler(_) => write!(f, "Handler(..)"),
+        }
+    }
+}
+
+/// What the policy did with an opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpcodeDecision {
+    Halted,
+    Skipped,
+    Ignored,
+    Handled,
+}
+
+impl fmt::Display for OpcodeDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpcodeDecision::Halted => write!(f, "halted"),
+            OpcodeDecision::Skipped => write!(f, "skipped"),
+            OpcodeDecision::Ignored => write!(f, "ignored"),
+            OpcodeDecision::Handled => write!(f, "handled"),
+        }
+    }
+}
+
+/// An unknown opcode that was met and what was done about it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownOpcode {
+    pub program_counter: u16,
+    pub opcode: u16,
+    pub decision: OpcodeDecision,
+}
+
+impl fmt::Display for UnknownOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.opcode & 0xF000 {
+            0 => "machine code call",
+            _ => "unknown opcode",
+        };
+        write!(
+            f,
+            "{} {:04X} at {:#05x}: {}",
+            kind, self.opcode, self.program_counter, self.decision
+        )
+    }
+}
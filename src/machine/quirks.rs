@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr};
+
+/// What FX55 and FX65 leave in I after they are done
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is set to I + X + 1, the original COSMAC VIP behavior
+    XPlusOne,
+    /// I is set to I + X, CHIP-48 got this off by one
+    X,
+    /// I is left alone, SUPER-CHIP 1.1
+    Unchanged,
+}
+
+/// Behaviors that differ between interpreters, a ROM written for one era may break on another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY into VX, otherwise VX is shifted in place
+    pub shift_uses_vy: bool,
+    /// How FX55 and FX65 update I
+    pub load_store: IndexIncrement,
+    /// BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// Sprites are cut off at the screen edge, otherwise they wrap around to the other side
+    pub clip_sprites: bool,
+    /// DXYN waits for the next frame before execution continues, at most one draw per frame
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub fn vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store: IndexIncrement::X,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1
+    pub fn superchip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store: IndexIncrement::Unchanged,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo
+    pub fn xochip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::vip()
+    }
+}
+
+/// Named quirk presets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuirkProfile {
+    Vip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl QuirkProfile {
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            QuirkProfile::Vip => Quirks::vip(),
+            QuirkProfile::Chip48 => Quirks::chip48(),
+            QuirkProfile::SuperChip => Quirks::superchip(),
+            QuirkProfile::XoChip => Quirks::xochip(),
+        }
+    }
+}
+
+impl FromStr for QuirkProfile {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "vip" => Ok(QuirkProfile::Vip),
+            "chip48" => Ok(QuirkProfile::Chip48),
+            "schip" => Ok(QuirkProfile::SuperChip),
+            "xochip" => Ok(QuirkProfile::XoChip),
+            _ => Err(format!("unknown quirk profile '{}'", name)),
+        }
+    }
+}
+
+impl fmt::Display for QuirkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QuirkProfile::Vip => "vip",
+            QuirkProfile::Chip48 => "chip48",
+            QuirkProfile::SuperChip => "schip",
+            QuirkProfile::XoChip => "xochip",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,91 @@
+/// Seed used until a program or frontend picks another one
+pub const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Page 1 of the COSMAC VIP CHIP-8 interpreter, 0x0100 to 0x01FF, the table the VIP's CXNN
+/// routine indexes with the low byte of its seed
+pub const VIP_INTERPRETER_PAGE: [u8; 256] = [
+    0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC, 0x22, 0xDC,
+    0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A, 0xF4, 0xAA,
+    0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA, 0x0A, 0xAA,
+    0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A, 0x0E, 0xF5,
+    0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F, 0x56, 0x2A,
+    0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17, 0x1A, 0x3A,
+    0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17, 0x1A, 0x3A,
+    0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA, 0x0F, 0xB5,
+    0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88, 0xD4, 0x45,
+    0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88, 0xD4, 0x3E,
+    0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2, 0xFC, 0x01,
+    0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A, 0xC4, 0x07,
+    0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2, 0x56, 0xF8,
+    0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE, 0xF4, 0x56,
+    0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F, 0xBA, 0xD4,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00, 0x4B,
+];
+
+/// Where CXNN gets its random bytes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Random {
+    /// xorshift64, the same seed always gives the same sequence
+    Seeded { state: u64 },
+    /// The COSMAC VIP interpreter's routine: the 16 bit seed is incremented, its low byte picks
+    /// a byte of [`VIP_INTERPRETER_PAGE`], which is added to the high byte. That sum rotated
+    /// right through the carry, plus the sum again, is the result and the new high byte
+    Vip { low: u8, high: u8 },
+    /// Hands out `values` in order, starting over once they run out, for tests
+    Scripted { values: Vec<u8>, position: usize },
+}
+
+impl Random {
+    pub fn seeded(seed: u64) -> Self {
+        // xorshift gets stuck on 0
+        Random::Seeded { state: seed.max(1) }
+    }
+
+    pub fn vip() -> Self {
+        Random::Vip { low: 0, high: 0 }
+    }
+
+    /// An empty sequence always gives 0
+    pub fn scripted(values: Vec<u8>) -> Self {
+        Random::Scripted {
+            values,
+            position: 0,
+        }
+    }
+
+    /// The next random byte, before CXNN masks it
+    pub fn next_byte(&mut self) -> u8 {
+        match self {
+            Random::Seeded { state } => {
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                (*state >> 32) as u8
+            }
+            Random::Vip { low, high } => {
+                // INC R9, the carry out of the low byte reaches the high one
+                let seed = u16::from_le_bytes([*low, *high]).wrapping_add(1);
+                [*low, *high] = seed.to_le_bytes();
+                // GHI R9, ADD from page 1, then SHRC through the carry of that ADD
+                let (sum, carry) = high.overflowing_add(VIP_INTERPRETER_PAGE[*low as usize]);
+                let rotated = (sum >> 1) | ((carry as u8) << 7);
+                // ADD the stored sum, PHI R9
+                *high = rotated.wrapping_add(sum);
+                *high
+            }
+            Random::Scripted { values, position } => {
+                let Some(value) = values.get(*position).copied() else {
+                    return 0;
+                };
+                *position = (*position + 1) % values.len();
+                value
+            }
+        }
+    }
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Random::seeded(DEFAULT_SEED)
+    }
+}
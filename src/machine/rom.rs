@@ -1,14 +1,19 @@
 use std::{error::Error, fs};
 
-use super::machine::Machine;
+use super::{error::MachineError, machine::Machine};
 
 pub struct Rom;
 
 impl Rom {
-    pub fn load(file_path: &str, mut machine: Machine) -> Result<Machine, Box<dyn Error>> {
-        println!("Loading file: {}", file_path);
+    pub fn load(file_path: &str, machine: Machine) -> Result<Machine, Box<dyn Error>> {
         let contents = fs::read(file_path)?;
-        machine.copy_to_ram(contents, 0x200);
+        Ok(Rom::load_bytes(contents, machine)?)
+    }
+
+    /// Copy an in-memory program to 0x200, where every chip 8 program starts, fails when it
+    /// does not fit in ram
+    pub fn load_bytes(contents: Vec<u8>, mut machine: Machine) -> Result<Machine, MachineError> {
+        machine.copy_to_ram(contents, 0x200)?;
         Ok(machine)
     }
 }
@@ -0,0 +1,306 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    audio::PATTERN_SIZE,
+    machine::{Machine, MemoryMode},
+    quirks::{IndexIncrement, Quirks},
+    random::Random,
+    screen::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH},
+};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Bumped whenever the layout below changes, older states are rejected rather than misread
+pub const SAVE_STATE_VERSION: u16 = 3;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(io::Error),
+    /// Not a save state at all
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The data ended before the state did
+    Truncated,
+    /// A field holds a value no machine can be in
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "{}", error),
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, expected {}",
+                version, SAVE_STATE_VERSION
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self {
+        SaveStateError::Io(error)
+    }
+}
+
+/// File used for numbered save slot `slot` of the ROM at `rom_path`, next to the ROM
+pub fn slot_path(rom_path: impl AsRef<Path>, slot: u8) -> PathBuf {
+    let mut path = rom_path.as_ref().as_os_str().to_owned();
+    path.push(format!(".state{}", slot));
+    PathBuf::from(path)
+}
+
+impl Machine {
+    /// Serialize everything a running program can observe. Debugging settings such as trace
+    /// and watchpoints belong to the session and are not part of the state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&SAVE_STATE_VERSION.to_le_bytes());
+
+        for register in 0..16 {
+            state.push(self.read_general_purpouse_registers(register));
+        }
+        state.extend_from_slice(&self.read_index_register().to_le_bytes());
+        state.extend_from_slice(&self.read_program_counter().to_le_bytes());
+        state.push(self.read_stack_pointer());
+        for entry in self.stack {
+            state.extend_from_slice(&entry.to_le_bytes());
+        }
+
+        state.push(self.read_delay_timer());
+        state.push(self.read_sound_timer());
+        state.extend_from_slice(&self.frame_count.to_le_bytes());
+        state.extend(self.read_pressed_keys().map(u8::from));
+        state.extend_from_slice(&self.read_flag_registers());
+        state.push(u8::from(self.waiting_for_vblank));
+        state.push(u8::from(self.exited));
+        write_random(&mut state, self.read_random());
+
+        write_quirks(&mut state, self.read_quirks());
+
+        state.extend_from_slice(&self.audio.read_pattern());
+        state.push(self.audio.read_pitch());
+
+        state.push(u8::from(self.screen.is_hires()));
+        state.push(self.screen.selected_planes());
+        state.extend_from_slice(&self.screen.display);
+
+        state.push(match self.read_memory_mode() {
+            MemoryMode::Chip8 => 0,
+            MemoryMode::XoChip => 1,
+        });
+        state.extend_from_slice(&self.ram);
+        state
+    }
+
+    /// Restore a state made by [`Machine::save_state`]. Nothing changes if it is rejected
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader { state, position: 0 };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        // fill a copy so a bad state leaves the machine untouched
+        let mut machine = self.clone();
+        for register in 0..16 {
+            machine.write_to_general_purpouse_registers(register, reader.u8()?);
+        }
+        machine.write_to_index_register(reader.u16()?);
+        machine.write_to_program_counter(reader.u16()?);
+        let stack_pointer = reader.u8()?;
+        if stack_pointer as usize > machine.stack.len() {
+            return Err(SaveStateError::Invalid("stack pointer"));
+        }
+        machine.write_to_stack_pointer(stack_pointer);
+        for entry in machine.stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+
+        machine.write_to_delay_timer(reader.u8()?);
+        machine.write_to_sound_timer(reader.u8()?);
+        // sound events from before the load would be stale
+        machine.take_sound_events();
+        machine.frame_count = reader.u64()?;
+        // not through set_pressed_keys, loading is not a key transition for input recording
+        for pressed in machine.pressed_keys.iter_mut() {
+            *pressed = reader.bool()?;
+        }
+        let mut flags = [0; 16];
+        flags.copy_from_slice(reader.bytes(16)?);
+        machine.write_to_flag_registers(flags);
+        machine.waiting_for_vblank = reader.bool()?;
+        machine.exited = reader.bool()?;
+        machine.set_random(read_random(&mut reader)?);
+
+        machine.set_quirks(read_quirks(&mut reader)?);
+
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern.copy_from_slice(reader.bytes(PATTERN_SIZE)?);
+        machine.audio.write_to_pattern(pattern);
+        machine.audio.write_to_pitch(reader.u8()?);
+
+        machine.screen.set_hires(reader.bool()?);
+        machine.screen.select_planes(reader.u8()?);
+        machine
+            .screen
+            .display
+            .copy_from_slice(reader.bytes(HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT)?);
+
+        let memory_mode = match reader.u8()? {
+            0 => MemoryMode::Chip8,
+            1 => MemoryMode::XoChip,
+            _ => return Err(SaveStateError::Invalid("memory mode")),
+        };
+        machine.set_memory_mode(memory_mode);
+        let ram_size = machine.ram_size();
+        machine.ram.copy_from_slice(reader.bytes(ram_size)?);
+        machine.clear_translations();
+
+        if reader.position != state.len() {
+            return Err(SaveStateError::Invalid("length"));
+        }
+        *self = machine;
+        Ok(())
+    }
+
+    pub fn save_state_to_file(&self, path: impl AsRef<Path>) -> Result<(), SaveStateError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_from_file(&mut self, path: impl AsRef<Path>) -> Result<(), SaveStateError> {
+        let state = fs::read(path)?;
+        self.load_state(&state)
+    }
+}
+
+fn write_random(state: &mut Vec<u8>, random: &Random) {
+    match random {
+        Random::Seeded { state: seed_state } => {
+            state.push(0);
+            state.extend_from_slice(&seed_state.to_le_bytes());
+        }
+        Random::Vip { low, high } => {
+            state.push(1);
+            state.push(*low);
+            state.push(*high);
+        }
+        Random::Scripted { values, position } => {
+            state.push(2);
+            state.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            state.extend_from_slice(values);
+            state.extend_from_slice(&(*position as u32).to_le_bytes());
+        }
+    }
+}
+
+fn read_random(reader: &mut StateReader) -> Result<Random, SaveStateError> {
+    match reader.u8()? {
+        0 => Ok(Random::Seeded {
+            state: reader.u64()?,
+        }),
+        1 => Ok(Random::Vip {
+            low: reader.u8()?,
+            high: reader.u8()?,
+        }),
+        2 => {
+            let length = reader.u32()? as usize;
+            let values = reader.bytes(length)?.to_vec();
+            let position = reader.u32()? as usize;
+            if position >= values.len().max(1) {
+                return Err(SaveStateError::Invalid("scripted random position"));
+            }
+            Ok(Random::Scripted { values, position })
+        }
+        _ => Err(SaveStateError::Invalid("random generator")),
+    }
+}
+
+fn write_quirks(state: &mut Vec<u8>, quirks: Quirks) {
+    state.push(u8::from(quirks.shift_uses_vy));
+    state.push(match quirks.load_store {
+        IndexIncrement::XPlusOne => 0,
+        IndexIncrement::X => 1,
+        IndexIncrement::Unchanged => 2,
+    });
+    state.push(u8::from(quirks.jump_uses_vx));
+    state.push(u8::from(quirks.vf_reset));
+    state.push(u8::from(quirks.clip_sprites));
+    state.push(u8::from(quirks.display_wait));
+}
+
+fn read_quirks(reader: &mut StateReader) -> Result<Quirks, SaveStateError> {
+    Ok(Quirks {
+        shift_uses_vy: reader.bool()?,
+        load_store: match reader.u8()? {
+            0 => IndexIncrement::XPlusOne,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::Unchanged,
+            _ => return Err(SaveStateError::Invalid("load/store quirk")),
+        },
+        jump_uses_vx: reader.bool()?,
+        vf_reset: reader.bool()?,
+        clip_sprites: reader.bool()?,
+        display_wait: reader.bool()?,
+    })
+}
+
+struct StateReader<'a> {
+    state: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        let bytes = self
+            .state
+            .get(self.position..self.position + length)
+            .ok_or(SaveStateError::Truncated)?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("flag")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
@@ -1,68 +1,157 @@
-use std::{thread::sleep, time::Duration, u32, usize};
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+/// SUPER-CHIP high resolution mode
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+const SCREEN_SIZE: usize = HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT;
+/// XO-CHIP plane selection masks, plane 1 is the only one classic programs know about
+pub const PLANE_1: u8 = 0b01;
+pub const PLANE_2: u8 = 0b10;
+pub const ALL_PLANES: u8 = PLANE_1 | PLANE_2;
 
-const SCREEN_WIDTH: usize = 64;
-const SCREEN_HEIGHT: usize = 32;
-const SCREEN_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
 #[derive(Clone)]
 pub struct Screen {
     // 32x64, should be easier to set it to a continious memory to deal with overflows per line of
     // display
-    pub display: [bool; SCREEN_SIZE],
+    // sized for hires, in lores only the first 64 * 32 pixels are used
+    // every pixel is a 2 bit color index, bit 0 is plane 1 and bit 1 is plane 2
+    pub display: [u8; SCREEN_SIZE],
+    hires: bool,
+    // planes that drawing, clearing and scrolling affect, set by XO-CHIP FN01
+    selected_planes: u8,
 }
 
 impl Screen {
+    /// Clears the selected planes only
     pub fn clear_screen(&mut self) {
-        self.display = [false; SCREEN_SIZE];
+        let selected_planes = self.selected_planes;
+        self.display
+            .iter_mut()
+            .for_each(|pixel| *pixel &= !selected_planes);
+    }
+
+    pub fn width(&self) -> usize {
+        match self.hires {
+            true => HIRES_SCREEN_WIDTH,
+            false => SCREEN_WIDTH,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.hires {
+            true => HIRES_SCREEN_HEIGHT,
+            false => SCREEN_HEIGHT,
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch between 64x32 and 128x64, every plane is cleared either way
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = [0; SCREEN_SIZE];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// Pick the planes later draws, clears and scrolls apply to, only the lowest 2 bits count
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & ALL_PLANES;
+    }
+
+    /// Color index of the pixel at column `x`, row `y`, 0 is off and 1 is plane 1 only.
+    /// Frontends map the 4 possible values to a palette
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.display[self.width() * y + x]
+    }
+
+    /// Whether the pixel at column `x`, row `y` is lit on any plane
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    /// XOR a single pixel on `plane`, returns true if it was lit before, meaning it got erased
+    pub fn flip_pixel(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let index = self.width() * y + x;
+        let was_lit = self.display[index] & plane != 0;
+        self.display[index] ^= plane;
+        was_lit
     }
 }
 
 impl Screen {
-    pub fn update_screen_state(&mut self, start_x: u8, start_y: u8, data: Vec<u8>) -> bool {
-        let start_target = (start_x as u32) + (start_y as u32 * 64);
-        let mut is_flipped = false;
-
-        let binary: Vec<[u8; 1]> = data.into_iter().map(|f| f.to_be_bytes()).collect();
-        let flattened_binary = binary.as_flattened();
-
-        for (i, bit) in flattened_binary.into_iter().enumerate() {
-            println!("{:?}", bit);
-            sleep(Duration::from_millis(400));
-            match self.display[start_target as usize + i] {
-                true => {
-                    if bit.clone() == 0 {
-                        is_flipped = true
-                    }
-                }
-                false => {
-                    if bit.clone() == 1 {
-                        is_flipped = true
-                    }
-                }
+    /// Move the selected planes down by `rows`, rows scrolled in at the top are blank
+    pub fn scroll_down(&mut self, rows: usize) {
+        let width = self.width();
+        for y in (0..self.height()).rev() {
+            for x in 0..width {
+                let source = match y >= rows {
+                    true => self.display[width * (y - rows) + x],
+                    false => 0,
+                };
+                self.replace_selected(width * y + x, source);
             }
+        }
+    }
+
+    /// Move the selected planes right by `columns`, columns scrolled in at the left are blank
+    pub fn scroll_right(&mut self, columns: usize) {
+        let width = self.width();
+        for y in 0..self.height() {
+            for x in (0..width).rev() {
+                let source = match x >= columns {
+                    true => self.display[width * y + x - columns],
+                    false => 0,
+                };
+                self.replace_selected(width * y + x, source);
+            }
+        }
+    }
 
-            match bit {
-                0 => self.display[start_target as usize + i] = false,
-                _ => self.display[start_target as usize + i] = true,
+    /// Move the selected planes left by `columns`, columns scrolled in at the right are blank
+    pub fn scroll_left(&mut self, columns: usize) {
+        let width = self.width();
+        for y in 0..self.height() {
+            for x in 0..width {
+                let source = match x + columns < width {
+                    true => self.display[width * y + x + columns],
+                    false => 0,
+                };
+                self.replace_selected(width * y + x, source);
             }
         }
-        return is_flipped;
     }
+
+    // take the selected planes' bits from `source`, keep the rest
+    fn replace_selected(&mut self, index: usize, source: u8) {
+        let selected_planes = self.selected_planes;
+        self.display[index] = (self.display[index] & !selected_planes) | (source & selected_planes);
+    }
+}
+
+impl Screen {
     pub fn draw(&self) {
         // Top border
         print!("╔"); // top-left corner
-        for _ in 0..SCREEN_WIDTH {
+        for _ in 0..self.width() {
             print!("═");
         }
         println!("╗"); // top-right corner
 
         // Each row
-        for height in 0..SCREEN_HEIGHT {
+        for height in 0..self.height() {
             print!("║"); // left side
-            for width in 0..SCREEN_WIDTH {
-                let pixel = self.display[64 * height + width];
+            for width in 0..self.width() {
+                let pixel = self.pixel(width, height);
                 match pixel {
-                    true => print!("*"),
-                    false => print!(" "),
+                    0 => print!(" "),
+                    1 => print!("*"),
+                    2 => print!("+"),
+                    _ => print!("#"),
                 }
             }
             println!("║"); // right side
@@ -70,7 +159,7 @@ impl Screen {
 
         // Bottom border
         print!("╚"); // bottom-left corner
-        for _ in 0..SCREEN_WIDTH {
+        for _ in 0..self.width() {
             print!("═");
         }
         println!("╝"); // bottom-right corner
@@ -79,8 +168,16 @@ impl Screen {
 
 impl Screen {
     pub fn new() -> Screen {
-        return Screen {
-            display: [false; SCREEN_SIZE],
-        };
+        Screen {
+            display: [0; SCREEN_SIZE],
+            hires: false,
+            selected_planes: PLANE_1,
+        }
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
     }
 }
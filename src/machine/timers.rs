@@ -0,0 +1,72 @@
+/// Both timers count down at this rate, no matter how many instructions run per frame
+pub const TIMER_FREQUENCY: u32 = 60;
+
+/// Raised when the buzzer should start or stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// The sound timer went from zero to a nonzero value
+    SoundOn,
+    /// The sound timer reached zero
+    SoundOff,
+}
+
+#[derive(Clone)]
+pub struct Timers {
+    delay_timer: u8,
+    sound_timer: u8,
+    sound_events: Vec<SoundEvent>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self {
+            delay_timer: 0,
+            sound_timer: 0,
+            sound_events: Vec::new(),
+        }
+    }
+
+    pub fn read_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn write_to_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn read_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn write_to_sound_timer(&mut self, value: u8) {
+        match (self.sound_timer, value) {
+            (0, 1..) => self.sound_events.push(SoundEvent::SoundOn),
+            (1.., 0) => self.sound_events.push(SoundEvent::SoundOff),
+            _ => {}
+        }
+        self.sound_timer = value;
+    }
+
+    /// Count both timers down by one, called once per 60 Hz frame
+    pub fn tick(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            if self.sound_timer == 0 {
+                self.sound_events.push(SoundEvent::SoundOff);
+            }
+        }
+    }
+
+    /// Sound events raised since the last call, oldest first
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sound_events)
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
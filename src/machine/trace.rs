@@ -0,0 +1,457 @@
+use std::{error::Error, fmt, iter::Peekable, ops::RangeInclusive, str::Chars, str::FromStr};
+
+use super::policy::OpcodeDecision;
+
+/// How trace records are written out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Fixed width columns, one line per instruction
+    #[default]
+    Text,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// Which instructions get a record, both ranges are inclusive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Addresses of the instructions
+    pub addresses: RangeInclusive<u16>,
+    /// Values of [`Machine::read_frame_count`](super::machine::Machine::read_frame_count)
+    /// while they execute
+    pub frames: RangeInclusive<u64>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, program_counter: u16, frame: u64) -> bool {
+        self.addresses.contains(&program_counter) && self.frames.contains(&frame)
+    }
+}
+
+impl Default for TraceFilter {
+    /// Everything
+    fn default() -> Self {
+        Self {
+            addresses: 0..=u16::MAX,
+            frames: 0..=u64::MAX,
+        }
+    }
+}
+
+/// One executed instruction. Records only hold values that follow from the program, so runs
+/// that behave the same produce the same trace line for line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Instructions executed since tracing started, filtered out ones included
+    pub cycle: u64,
+    pub frame: u64,
+    pub program_counter: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    /// Registers the instruction changed, as index and new value. None when a JSON trace left
+    /// them out, which is not the same as changing none
+    pub changed_registers: Option<Vec<(u8, u8)>>,
+    /// I after the instruction
+    pub index_register: u16,
+    /// SP after the instruction
+    pub stack_pointer: u8,
+    /// Bytes the instruction wrote, as address and new value. None when a JSON trace left
+    /// them out
+    pub memory_writes: Option<Vec<(u16, u8)>>,
+    /// What the opcode policy did, for unknown opcodes and 0NNN
+    pub decision: Option<OpcodeDecision>,
+}
+
+impl TraceRecord {
+    pub fn format(&self, format: TraceFormat) -> String {
+        match format {
+            TraceFormat::Text => self.to_string(),
+            TraceFormat::JsonLines => self.to_json(),
+        }
+    }
+
+    /// The record as a single line JSON object
+    pub fn to_json(&self) -> String {
+        let changed_registers = match &self.changed_registers {
+            Some(changed_registers) => {
+                let changed_registers: Vec<String> = changed_registers
+                    .iter()
+                    .map(|(register, value)| format!("\"V{:X}\":{}", register, value))
+                    .collect();
+                format!(",\"changed\":{{{}}}", changed_registers.join(","))
+            }
+            None => String::new(),
+        };
+        let memory_writes = match &self.memory_writes {
+            Some(memory_writes) => {
+                let memory_writes: Vec<String> = memory_writes
+                    .iter()
+                    .map(|(address, value)| format!("[{},{}]", address, value))
+                    .collect();
+                format!(",\"writes\":[{}]", memory_writes.join(","))
+            }
+            None => String::new(),
+        };
+        let decision = match self.decision {
+            Some(decision) => format!(",\"decision\":\"{}\"", decision),
+            None => String::new(),
+        };
+        format!(
+            "{{\"cycle\":{},\"frame\":{},\"pc\":{},\"opcode\":{},\"mnemonic\":\"{}\"{},\"i\":{},\"sp\":{}{}{}}}",
+            self.cycle,
+            self.frame,
+            self.program_counter,
+            self.opcode,
+            self.mnemonic.replace('\\', "\\\\").replace('"', "\\\""),
+            changed_registers,
+            self.index_register,
+            self.stack_pointer,
+            memory_writes,
+            decision
+        )
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:>6} {:04X} {:04X} {:<20} I={:04X} SP={:X}",
+            self.cycle,
+            self.frame,
+            self.program_counter,
+            self.opcode,
+            self.mnemonic,
+            self.index_register,
+            self.stack_pointer
+        )?;
+        for (register, value) in self.changed_registers.iter().flatten() {
+            write!(f, " V{:X}={:02X}", register, value)?;
+        }
+        for (address, value) in self.memory_writes.iter().flatten() {
+            write!(f, " [{:04X}]={:02X}", address, value)?;
+        }
+        if let Some(decision) = self.decision {
+            write!(f, " ({})", decision)?;
+        }
+        Ok(())
+    }
+}
+
+/// A line that is not a trace record in either format
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceParseError {
+    pub line: String,
+}
+
+impl fmt::Display for TraceParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not a trace record: '{}'", self.line)
+    }
+}
+
+impl Error for TraceParseError {}
+
+impl FromStr for TraceRecord {
+    type Err = TraceParseError;
+
+    /// Reads either format back, JSON lines start with `{`. In JSON only cycle, frame, pc,
+    /// opcode, i and sp are required, so traces from other emulators can leave the rest out
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let record = match line.starts_with('{') {
+            true => parse_json_record(line),
+            false => parse_text_record(line),
+        };
+        record.ok_or_else(|| TraceParseError {
+            line: line.to_string(),
+        })
+    }
+}
+
+fn parse_text_record(line: &str) -> Option<TraceRecord> {
+    // the mnemonic has spaces in it, but never " I="
+    let (head, tail) = line.rsplit_once(" I=")?;
+    let mut head = head.split_whitespace();
+    let cycle = head.next()?.parse().ok()?;
+    let frame = head.next()?.parse().ok()?;
+    let program_counter = u16::from_str_radix(head.next()?, 16).ok()?;
+    let opcode = u16::from_str_radix(head.next()?, 16).ok()?;
+    let mnemonic = head.collect::<Vec<_>>().join(" ");
+
+    let mut tail = tail.split_whitespace();
+    let index_register = u16::from_str_radix(tail.next()?, 16).ok()?;
+    let stack_pointer = u8::from_str_radix(tail.next()?.strip_prefix("SP=")?, 16).ok()?;
+    // the text format always lists every change, none listed means none happened
+    let mut changed_registers = Vec::new();
+    let mut memory_writes = Vec::new();
+    let mut decision = None;
+    for field in tail {
+        if let Some(policy_decision) = field.strip_prefix('(') {
+            decision = Some(parse_decision(policy_decision.strip_suffix(')')?)?);
+        } else if let Some(write) = field.strip_prefix('[') {
+            let (address, value) = write.split_once("]=")?;
+            memory_writes.push((
+                u16::from_str_radix(address, 16).ok()?,
+                u8::from_str_radix(value, 16).ok()?,
+            ));
+        } else {
+            let (register, value) = field.strip_prefix('V')?.split_once('=')?;
+            changed_registers.push((
+                u8::from_str_radix(register, 16).ok()?,
+                u8::from_str_radix(value, 16).ok()?,
+            ));
+        }
+    }
+    Some(TraceRecord {
+        cycle,
+        frame,
+        program_counter,
+        opcode,
+        mnemonic,
+        changed_registers: Some(changed_registers),
+        index_register,
+        stack_pointer,
+        memory_writes: Some(memory_writes),
+        decision,
+    })
+}
+
+fn parse_json_record(line: &str) -> Option<TraceRecord> {
+    let Json::Object(fields) = parse_json(&mut line.chars().peekable())? else {
+        return None;
+    };
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    };
+    let number = |name: &str| match field(name)? {
+        Json::Number(number) => Some(*number),
+        _ => None,
+    };
+
+    let changed_registers = match field("changed") {
+        Some(Json::Object(registers)) => Some(
+            registers
+                .iter()
+                .map(|(register, value)| {
+                    let register = u8::from_str_radix(register.strip_prefix('V')?, 16).ok()?;
+                    Some((register, value.as_number()?.try_into().ok()?))
+                })
+                .collect::<Option<_>>()?,
+        ),
+        None => None,
+        _ => return None,
+    };
+    let memory_writes = match field("writes") {
+        Some(Json::Array(writes)) => Some(
+            writes
+                .iter()
+                .map(|write| match write {
+                    Json::Array(pair) => match pair.as_slice() {
+                        [address, value] => Some((
+                            address.as_number()?.try_into().ok()?,
+                            value.as_number()?.try_into().ok()?,
+                        )),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+        ),
+        None => None,
+        _ => return None,
+    };
+    let text = |name: &str| match field(name) {
+        Some(Json::String(text)) => Some(Some(text.as_str())),
+        None => Some(None),
+        _ => None,
+    };
+    let decision = match text("decision")? {
+        Some(decision) => Some(parse_decision(decision)?),
+        None => None,
+    };
+
+    Some(TraceRecord {
+        cycle: number("cycle")?,
+        frame: number("frame")?,
+        program_counter: number("pc")?.try_into().ok()?,
+        opcode: number("opcode")?.try_into().ok()?,
+        mnemonic: text("mnemonic")?.unwrap_or_default().to_string(),
+        changed_registers,
+        index_register: number("i")?.try_into().ok()?,
+        stack_pointer: number("sp")?.try_into().ok()?,
+        memory_writes,
+        decision,
+    })
+}
+
+fn parse_decision(decision: &str) -> Option<OpcodeDecision> {
+    match decision {
+        "halted" => Some(OpcodeDecision::Halted),
+        "skipped" => Some(OpcodeDecision::Skipped),
+        "ignored" => Some(OpcodeDecision::Ignored),
+        "handled" => Some(OpcodeDecision::Handled),
+        _ => None,
+    }
+}
+
+// just enough JSON for trace records: unsigned integers, strings, arrays and objects. Trace
+// values are never negative or fractional, such numbers make the record unreadable
+enum Json {
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn as_number(&self) -> Option<u64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+fn parse_json(chars: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(chars);
+    match chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(fields));
+            }
+            loop {
+                let Json::String(key) = parse_json(chars)? else {
+                    return None;
+                };
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                fields.push((key, parse_json(chars)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Json::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Json::Array(values));
+            }
+            loop {
+                values.push(parse_json(chars)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Json::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next()? {
+                    '"' => return Some(Json::String(text)),
+                    '\\' => text.push(parse_escape(chars)?),
+                    character => text.push(character),
+                }
+            }
+        }
+        character if character.is_ascii_digit() => {
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            digits.parse().ok().map(Json::Number)
+        }
+        _ => None,
+    }
+}
+
+// the character after a backslash, unknown escapes are an error rather than taken literally
+fn parse_escape(chars: &mut Peekable<Chars>) -> Option<char> {
+    match chars.next()? {
+        '"' => Some('"'),
+        '\\' => Some('\\'),
+        '/' => Some('/'),
+        'b' => Some('\u{8}'),
+        'f' => Some('\u{c}'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'u' => {
+            let unit = parse_hex4(chars)?;
+            match unit {
+                // a high surrogate, the low one follows as its own escape
+                0xD800..=0xDBFF => {
+                    chars.next_if_eq(&'\\')?;
+                    chars.next_if_eq(&'u')?;
+                    let low = parse_hex4(chars)?;
+                    if !(0xDC00..=0xDFFF).contains(&low) {
+                        return None;
+                    }
+                    char::from_u32(0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00))
+                }
+                // lone low surrogates are not characters, from_u32 refuses them
+                _ => char::from_u32(unit),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    (0..4).try_fold(0, |unit, _| Some(unit << 4 | chars.next()?.to_digit(16)?))
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars
+        .next_if(|character| character.is_whitespace())
+        .is_some()
+    {}
+}
+
+/// Collects a record per executed instruction that passes the filter
+#[derive(Clone, Debug)]
+pub(crate) struct Tracer {
+    filter: TraceFilter,
+    cycle: u64,
+    records: Vec<TraceRecord>,
+}
+
+impl Tracer {
+    pub(crate) fn new(filter: TraceFilter) -> Self {
+        Self {
+            filter,
+            cycle: 0,
+            records: Vec::new(),
+        }
+    }
+
+    /// Count an instruction, returns its cycle when it should be recorded
+    pub(crate) fn count(&mut self, program_counter: u16, frame: u64) -> Option<u64> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        self.filter.matches(program_counter, frame).then_some(cycle)
+    }
+
+    pub(crate) fn record(&mut self, record: TraceRecord) {
+        self.records.push(record);
+    }
+
+    pub(crate) fn take_records(&mut self) -> Vec<TraceRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
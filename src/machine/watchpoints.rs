@@ -0,0 +1,157 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    ops::RangeInclusive,
+};
+
+/// Which kind of memory access a watchpoint reacts to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// What happens when a watchpoint matches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Record the hit and halt the machine until the hits are taken
+    Stop,
+    /// Record the hit and keep running
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addresses: RangeInclusive<u16>,
+    pub kind: WatchKind,
+    pub action: WatchAction,
+}
+
+impl Watchpoint {
+    pub fn new(addresses: RangeInclusive<u16>, kind: WatchKind, action: WatchAction) -> Self {
+        Self {
+            addresses,
+            kind,
+            action,
+        }
+    }
+
+    fn matches(&self, address: u16, access: Access) -> bool {
+        let kind_matches = matches!(
+            (self.kind, access),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, Access::Read)
+                | (WatchKind::Write, Access::Write)
+        );
+        kind_matches && self.addresses.contains(&address)
+    }
+}
+
+/// A memory access that matched a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction that made the access
+    pub program_counter: u16,
+    pub opcode: u16,
+    pub address: u16,
+    pub access: Access,
+    /// Reads see the same value on both sides
+    pub old_value: u8,
+    pub new_value: u8,
+    pub action: WatchAction,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+        };
+        write!(
+            f,
+            "{} {:#05X} {:02X} -> {:02X} by {:04X} at {:#05X}",
+            access, self.address, self.old_value, self.new_value, self.opcode, self.program_counter
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    // reads go through `&self`, so hits are collected behind a RefCell
+    hits: RefCell<Vec<WatchHit>>,
+    stopped: Cell<bool>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove every watchpoint covering exactly `addresses`, returns false if there was none
+    pub fn remove(&mut self, addresses: &RangeInclusive<u16>) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| &watchpoint.addresses != addresses);
+        self.watchpoints.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Whether a Stop watchpoint was hit and its hits have not been taken yet
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    /// Hits recorded since the last call, oldest first. Resumes a stopped machine
+    pub fn take_hits(&self) -> Vec<WatchHit> {
+        self.stopped.set(false);
+        self.hits.take()
+    }
+
+    /// Check an access against every watchpoint and record the first match
+    pub fn check(
+        &self,
+        (program_counter, opcode): (u16, u16),
+        address: u16,
+        access: Access,
+        old_value: u8,
+        new_value: u8,
+    ) {
+        if let Some(watchpoint) = self
+            .watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.matches(address, access))
+        {
+            if watchpoint.action == WatchAction::Stop {
+                self.stopped.set(true);
+            }
+            self.hits.borrow_mut().push(WatchHit {
+                program_counter,
+                opcode,
+                address,
+                access,
+                old_value,
+                new_value,
+                action: watchpoint.action,
+            });
+        }
+    }
+}
@@ -1,24 +1,465 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+    process,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
-use machine::rom;
+use chip_8_rs::{
+    assembler, compare_screen, compare_screen_hash, disassembler, screen_hash, screen_image,
+    slot_path, write_wav, Debugger, DiffEnd, Differ, GdbStub, Machine, MemoryMode, Movie,
+    QuirkProfile, Random, ReferenceTrace, Rewind, Rom, RunEnd, Runner, SoundEvent, TraceFormat,
+    DEFAULT_SEED, TIMER_FREQUENCY,
+};
+use cli::{CliError, Command, Expectation, Options, TestOptions};
 
-mod machine;
+mod cli;
+
+const AUDIO_SAMPLE_RATE: u32 = 44_100;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TIMER_FREQUENCY as u64);
 
 fn main() {
-    // init machine
-    let mut machine = machine::machine::Machine::new();
+    let options = match Command::parse(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Disassemble { rom_path, origin }) => {
+            disassemble(&rom_path, origin);
+            return;
+        }
+        Ok(Command::Assemble {
+            source_path,
+            output_path,
+        }) => {
+            assemble(&source_path, &output_path);
+            return;
+        }
+        Ok(Command::Test(options)) => {
+            test(&options);
+            return;
+        }
+        Err(CliError::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    if !Path::new(&options.rom_path).is_file() {
+        eprintln!("error: ROM file '{}' does not exist", options.rom_path);
+        process::exit(1);
+    }
+
+    // a movie brings its own quirks, memory mode, seed and instructions per frame
+    let (mut movie, mut machine) = match options.play.is_some() || options.record.is_some() {
+        true => {
+            let (movie, machine) = start_movie(&options);
+            (Some(movie), machine)
+        }
+        false => (None, start_machine(&options)),
+    };
+    let instructions_per_frame = match (&movie, &options.play) {
+        (Some(movie), Some(_)) => movie.instructions_per_frame,
+        _ => options.instructions_per_frame,
+    };
+    machine.set_trace(options.trace.clone());
+    let mut trace_out = open_trace_out(&options);
+    machine.set_opcode_policy(options.opcode_policy.clone());
+    machine.set_execution_engine(options.execution_engine);
+    for watchpoint in &options.watchpoints {
+        machine.add_watchpoint(watchpoint.clone());
+    }
+
+    if let Some(slot) = options.load_slot {
+        let path = slot_path(&options.rom_path, slot);
+        if let Err(error) = machine.load_state_from_file(&path) {
+            eprintln!("error: could not load '{}': {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    if let Some(port) = options.gdb_port {
+        println!("waiting for a gdb client on 127.0.0.1:{}", port);
+        let mut stub = GdbStub::new(Debugger::new(machine, instructions_per_frame));
+        if let Err(error) = stub.listen(("127.0.0.1", port)) {
+            eprintln!("error: gdb connection failed: {}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // the debugger runs the program itself, unless it should start after a number of frames
+    let frames = match (options.debug, &movie, &options.play) {
+        (true, _, _) => Some(options.frames.unwrap_or(0)),
+        (false, Some(movie), Some(_)) => Some(options.frames.unwrap_or(movie.frames)),
+        _ => options.frames,
+    };
+
+    let mut rewind = Rewind::default();
+    let mut recorded_audio = Vec::new();
+    let mut frame: u64 = 0;
+    while frames.is_none_or(|frames| frame < frames) {
+        let frame_start = Instant::now();
+
+        // the frame's inputs are part of its start, a rewind to it has to see them
+        if let (Some(movie), Some(_)) = (&movie, &options.play) {
+            movie.apply_inputs(&mut machine);
+        }
+        rewind.record(&machine);
+        let ran = machine.run_frame(instructions_per_frame);
+        write_trace(&mut machine, &mut trace_out, options.trace_format);
+        if let Err(error) = ran {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+        frame += 1;
+        for hit in machine.take_watch_hits() {
+            eprintln!("watch {}", hit);
+        }
+        for unknown_opcode in machine.take_unknown_opcodes() {
+            eprintln!("{}", unknown_opcode);
+        }
+        if machine.has_exited() {
+            break;
+        }
+
+        if options.audio_out.is_some() {
+            let mut samples = vec![0.0; (AUDIO_SAMPLE_RATE / TIMER_FREQUENCY) as usize];
+            machine.generate_audio(AUDIO_SAMPLE_RATE, &mut samples);
+            recorded_audio.extend(samples);
+        }
+
+        let sound_events = machine.take_sound_events();
+        if !options.headless {
+            if sound_events.contains(&SoundEvent::SoundOn) {
+                // terminal bell
+                print!("\x07");
+            }
+
+            print!("{}[2J", 27 as char);
+            println!("{:#x}", machine.read_index_register());
+            println!("{:#x}", machine.read_program_counter());
+            machine.screen.draw();
+
+            if let Some(remaining) = FRAME_DURATION.checked_sub(frame_start.elapsed()) {
+                sleep(remaining);
+            }
+        }
+    }
+
+    rewind.record(&machine);
+    if let Some(frames) = options.rewind {
+        let target = machine.read_frame_count().saturating_sub(frames);
+        if let Err(error) = rewind.rewind_to(&mut machine, target, instructions_per_frame) {
+            eprintln!("error: could not rewind: {}", error);
+            process::exit(1);
+        }
+        rewind.record(&machine);
+    }
+
+    if options.debug {
+        let mut debugger = Debugger::new(machine, instructions_per_frame);
+        debugger.set_rewind(rewind);
+        debugger.set_rom_path(&options.rom_path);
+        debug(debugger);
+        return;
+    }
+
+    if options.headless {
+        machine.screen.draw();
+    }
+
+    if let (Some(movie), Some(record)) = (&mut movie, &options.record) {
+        movie.finish_recording(&mut machine);
+        if let Err(error) = movie.save(record) {
+            eprintln!("error: could not write movie '{}': {}", record, error);
+            process::exit(1);
+        }
+    }
+
+    if let Some(slot) = options.save_slot {
+        let path = slot_path(&options.rom_path, slot);
+        if let Err(error) = machine.save_state_to_file(&path) {
+            eprintln!("error: could not save '{}': {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+
+    if let Some(audio_out) = &options.audio_out {
+        let written = File::create(audio_out)
+            .and_then(|mut file| write_wav(&mut file, AUDIO_SAMPLE_RATE, &recorded_audio));
+        if let Err(error) = written {
+            eprintln!("error: could not write audio to '{}': {}", audio_out, error);
+            process::exit(1);
+        }
+    }
+}
+
+/// Where trace records go, stderr unless --trace-out names a file
+fn open_trace_out(options: &Options) -> Box<dyn Write> {
+    match &options.trace_out {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(error) => {
+                eprintln!("error: could not create '{}': {}", path, error);
+                process::exit(1);
+            }
+        },
+        None => Box::new(io::stderr()),
+    }
+}
+
+/// Write out the records collected since the last call, flushed so nothing is lost when the
+/// run stops on an error
+fn write_trace(machine: &mut Machine, trace_out: &mut dyn Write, format: TraceFormat) {
+    let records = machine.take_trace();
+    if records.is_empty() {
+        return;
+    }
+    let written = records
+        .iter()
+        .try_for_each(|record| writeln!(trace_out, "{}", record.format(format)))
+        .and_then(|_| trace_out.flush());
+    if let Err(error) = written {
+        eprintln!("error: could not write the trace: {}", error);
+        process::exit(1);
+    }
+}
+
+fn start_machine(options: &Options) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(options.quirks.quirks());
+    if options.quirks == QuirkProfile::XoChip {
+        machine.set_memory_mode(MemoryMode::XoChip);
+    }
+    if let Some(seed) = options.seed {
+        machine.seed_random(seed);
+    }
+    if options.vip_random {
+        machine.set_random(Random::vip());
+    }
 
     // load rom to memory
-    machine = rom::Rom::load("./src/roms/4-flags.ch8", machine).expect("rom load");
+    match Rom::load(&options.rom_path, machine) {
+        Ok(machine) => machine,
+        Err(error) => {
+            eprintln!(
+                "error: could not load ROM '{}': {}",
+                options.rom_path, error
+            );
+            process::exit(1);
+        }
+    }
+}
+
+fn start_movie(options: &Options) -> (Movie, Machine) {
+    let rom = match fs::read(&options.rom_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!(
+                "error: could not load ROM '{}': {}",
+                options.rom_path, error
+            );
+            process::exit(1);
+        }
+    };
+
+    let started = match &options.play {
+        Some(path) => {
+            Movie::load(path).and_then(|movie| movie.machine(&rom).map(|machine| (movie, machine)))
+        }
+        None => {
+            let memory_mode = match options.quirks {
+                QuirkProfile::XoChip => MemoryMode::XoChip,
+                _ => MemoryMode::Chip8,
+            };
+            let seed = options.seed.unwrap_or(DEFAULT_SEED);
+            let movie = Movie::new(
+                &rom,
+                options.quirks.quirks(),
+                memory_mode,
+                seed,
+                options.instructions_per_frame,
+            );
+            movie.start_recording(&rom).map(|machine| (movie, machine))
+        }
+    };
 
+    match started {
+        Ok(started) => started,
+        Err(error) => {
+            eprintln!("error: movie: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn debug(mut debugger: Debugger) {
+    println!("{}", debugger.location());
+    let stdin = io::stdin();
     loop {
-        sleep(Duration::from_millis(50));
-        print!("{}[2J", 27 as char);
+        print!("(chip-8) ");
+        let _ = io::stdout().flush();
 
-        println!("{:#x}", machine.read_index_register());
-        println!("{:#x}", machine.read_program_counter());
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if matches!(line.trim(), "q" | "quit") {
+            break;
+        }
 
-        machine.exec();
-        machine.screen.draw();
+        let output = debugger.execute_command(&line);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+    }
+}
+
+fn test(options: &TestOptions) {
+    let mut machine = Machine::new();
+    machine.set_quirks(options.quirks.quirks());
+    if options.quirks == QuirkProfile::XoChip {
+        machine.set_memory_mode(MemoryMode::XoChip);
+    }
+    if let Some(seed) = options.seed {
+        machine.seed_random(seed);
+    }
+    machine.set_opcode_policy(options.opcode_policy.clone());
+    machine.set_execution_engine(options.execution_engine);
+    let machine = match Rom::load(&options.rom_path, machine) {
+        Ok(machine) => machine,
+        Err(error) => {
+            eprintln!(
+                "error: could not load ROM '{}': {}",
+                options.rom_path, error
+            );
+            process::exit(1);
+        }
+    };
+
+    let mut machine = match &options.reference {
+        Some(path) => check_reference(machine, path, options),
+        None => run_test(machine, options),
+    };
+    for unknown_opcode in machine.take_unknown_opcodes() {
+        eprintln!("{}", unknown_opcode);
+    }
+
+    let screen = &machine.screen;
+    let compared = match &options.expect {
+        None => {
+            print!("{}", screen_image(screen));
+            println!("hash {:016x}", screen_hash(screen));
+            return;
+        }
+        Some(Expectation::Hash(hash)) => compare_screen_hash(screen, *hash),
+        Some(Expectation::Image(path)) => match fs::read_to_string(path) {
+            Ok(expected) => compare_screen(screen, &expected),
+            Err(error) => {
+                eprintln!("error: could not read '{}': {}", path, error);
+                process::exit(1);
+            }
+        },
+    };
+    match compared {
+        Ok(()) => println!("screen matches"),
+        Err(mismatch) => {
+            eprintln!("{}", mismatch);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_test(machine: Machine, options: &TestOptions) -> Machine {
+    let mut runner = Runner::new(machine, options.instructions_per_frame);
+    let end = runner.run(options.frames);
+    let frames = runner.machine().read_frame_count();
+    match end {
+        RunEnd::Frames => println!("ran {} frames", frames),
+        RunEnd::Looped(address) => println!("halted at {:#05x} after {} frames", address, frames),
+        RunEnd::Exited => println!("exited after {} frames", frames),
+        RunEnd::Failed(error) => {
+            eprintln!("error: {} after {} frames", error, frames);
+            process::exit(1);
+        }
+    }
+    runner.into_machine()
+}
+
+/// Run under a [`Differ`], any difference from the reference ends the test
+fn check_reference(machine: Machine, path: &str, options: &TestOptions) -> Machine {
+    let reference = match ReferenceTrace::load(path) {
+        Ok(reference) => reference,
+        Err(error) => {
+            eprintln!("error: could not read trace '{}': {}", path, error);
+            process::exit(1);
+        }
+    };
+    let mut differ = Differ::new(machine, reference, options.instructions_per_frame);
+    match differ.run(options.frames) {
+        DiffEnd::Matched { compared } => println!("matched {} instructions", compared),
+        DiffEnd::Diverged(divergence) => {
+            eprint!("{}", divergence);
+            process::exit(1);
+        }
+        DiffEnd::Incomplete {
+            compared,
+            remaining,
+        } => {
+            eprintln!(
+                "matched {} instructions, the run ended before the other {} in the trace, \
+                 try more --frames",
+                compared, remaining
+            );
+            process::exit(1);
+        }
+        DiffEnd::Failed { error, compared } => {
+            eprintln!("error: {} after matching {} instructions", error, compared);
+            process::exit(1);
+        }
+    }
+    differ.into_machine()
+}
+
+fn disassemble(rom_path: &str, origin: u16) {
+    let rom = match fs::read(rom_path) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("error: could not read ROM '{}': {}", rom_path, error);
+            process::exit(1);
+        }
+    };
+
+    for line in disassembler::disassemble(&rom, origin) {
+        println!("{}", line);
+    }
+}
+
+fn assemble(source_path: &str, output_path: &str) {
+    let source = match fs::read_to_string(source_path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: could not read '{}': {}", source_path, error);
+            process::exit(1);
+        }
+    };
+
+    let assembled = match assembler::assemble(&source) {
+        Ok(assembled) => assembled,
+        Err(error) => {
+            eprintln!("{}:{}", source_path, error);
+            process::exit(1);
+        }
+    };
+
+    if let Err(error) = fs::write(output_path, assembled.bytes) {
+        eprintln!("error: could not write '{}': {}", output_path, error);
+        process::exit(1);
     }
 }
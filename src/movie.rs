@@ -0,0 +1,371 @@
+//! Input movies: every key transition of a run with its frame number, together with what is
+//! needed to start the run over identically, the ROM hash, quirks, memory mode, RNG seed and
+//! instructions per frame.
+//!
+//! Movies are plain text so they can be attached to bug reports and diffed:
+//!
+//! ```text
+//! chip-8-rs movie 1
+//! rom-hash 9c2a0e6b6b1d43f1
+//! instructions-per-frame 10
+//! seed 42
+//! memory-mode chip8
+//! quirks shift_uses_vy=true load_store=x+1 jump_uses_vx=false vf_reset=true clip_sprites=true display_wait=true
+//! frames 600
+//! key 12 5 down
+//! key 20 5 up
+//! ```
+//!
+//! Keys are applied at the start of the frame they were recorded on, so playback is exact
+//! when input only changes between frames, as it does in a frame loop.
+
+use std::{error::Error, fmt, fs, io, path::Path, str::FromStr};
+
+use crate::machine::{
+    error::MachineError,
+    machine::{KeyEvent, Machine, MemoryMode},
+    quirks::{IndexIncrement, Quirks},
+    rom::Rom,
+};
+
+const HEADER: &str = "chip-8-rs movie";
+pub const MOVIE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    /// A line of the movie file could not be understood, lines count from 1
+    Parse {
+        line: usize,
+        message: String,
+    },
+    UnsupportedVersion(u32),
+    /// The movie was recorded with a different ROM
+    RomMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The ROM does not fit in memory, or the program failed during playback
+    Machine(MachineError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(f, "{}", error),
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MovieError::UnsupportedVersion(version) => write!(
+                f,
+                "movie version {} is not supported, expected {}",
+                version, MOVIE_VERSION
+            ),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with ROM {:016x}, this one is {:016x}",
+                expected, actual
+            ),
+            MovieError::Machine(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<io::Error> for MovieError {
+    fn from(error: io::Error) -> Self {
+        MovieError::Io(error)
+    }
+}
+
+impl From<MachineError> for MovieError {
+    fn from(error: MachineError) -> Self {
+        MovieError::Machine(error)
+    }
+}
+
+/// 64 bit FNV-1a of the ROM contents, identifies the ROM a movie belongs to
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub instructions_per_frame: u32,
+    pub seed: u64,
+    pub memory_mode: MemoryMode,
+    pub quirks: Quirks,
+    /// Length of the run
+    pub frames: u64,
+    /// Oldest first
+    pub key_events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    /// An empty movie for `rom`, nothing is recorded yet
+    pub fn new(
+        rom: &[u8],
+        quirks: Quirks,
+        memory_mode: MemoryMode,
+        seed: u64,
+        instructions_per_frame: u32,
+    ) -> Self {
+        Self {
+            rom_hash: rom_hash(rom),
+            instructions_per_frame,
+            seed,
+            memory_mode,
+            quirks,
+            frames: 0,
+            key_events: Vec::new(),
+        }
+    }
+
+    /// A freshly powered on machine with `rom` loaded, set up the way the movie starts
+    pub fn machine(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        let actual = rom_hash(rom);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_hash,
+                actual,
+            });
+        }
+
+        let mut machine = Machine::new();
+        machine.set_quirks(self.quirks);
+        machine.set_memory_mode(self.memory_mode);
+        machine.seed_random(self.seed);
+        Ok(Rom::load_bytes(rom.to_vec(), machine)?)
+    }
+
+    /// Like [`Movie::machine`], with input recording turned on
+    pub fn start_recording(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        let mut machine = self.machine(rom)?;
+        machine.set_input_recording(true);
+        Ok(machine)
+    }
+
+    /// Take the key transitions recorded by `machine` and end the movie at its current frame
+    pub fn finish_recording(&mut self, machine: &mut Machine) {
+        self.key_events.extend(machine.take_key_events());
+        self.frames = machine.read_frame_count();
+    }
+
+    /// Press and release the keys recorded for the frame `machine` is about to run, call this
+    /// right before every frame
+    pub fn apply_inputs(&self, machine: &mut Machine) {
+        let frame = machine.read_frame_count();
+        let start = self
+            .key_events
+            .partition_point(|key_event| key_event.frame < frame);
+        for key_event in self.key_events[start..]
+            .iter()
+            .take_while(|key_event| key_event.frame == frame)
+        {
+            match key_event.pressed {
+                true => machine.set_pressed_keys(key_event.key as u16),
+                false => machine.unset_pressed_keys(key_event.key as u16),
+            }
+        }
+    }
+
+    /// Play the whole movie without a frontend and return the machine as it ends
+    pub fn play(&self, rom: &[u8]) -> Result<Machine, MovieError> {
+        let mut machine = self.machine(rom)?;
+        while machine.read_frame_count() < self.frames && !machine.has_exited() {
+            self.apply_inputs(&mut machine);
+            machine.run_frame(self.instructions_per_frame)?;
+        }
+        Ok(machine)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MovieError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Movie, MovieError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", HEADER, MOVIE_VERSION)?;
+        writeln!(f, "rom-hash {:016x}", self.rom_hash)?;
+        writeln!(f, "instructions-per-frame {}", self.instructions_per_frame)?;
+        writeln!(f, "seed {}", self.seed)?;
+        let memory_mode = match self.memory_mode {
+            MemoryMode::Chip8 => "chip8",
+            MemoryMode::XoChip => "xochip",
+        };
+        writeln!(f, "memory-mode {}", memory_mode)?;
+        let load_store = match self.quirks.load_store {
+            IndexIncrement::XPlusOne => "x+1",
+            IndexIncrement::X => "x",
+            IndexIncrement::Unchanged => "unchanged",
+        };
+        writeln!(
+            f,
+            "quirks shift_uses_vy={} load_store={} jump_uses_vx={} vf_reset={} clip_sprites={} display_wait={}",
+            self.quirks.shift_uses_vy,
+            load_store,
+            self.quirks.jump_uses_vx,
+            self.quirks.vf_reset,
+            self.quirks.clip_sprites,
+            self.quirks.display_wait
+        )?;
+        writeln!(f, "frames {}", self.frames)?;
+        for key_event in &self.key_events {
+            let state = match key_event.pressed {
+                true => "down",
+                false => "up",
+            };
+            writeln!(f, "key {} {:X} {}", key_event.frame, key_event.key, state)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let version = match lines.next() {
+            Some((_, line)) if line.starts_with(HEADER) => line[HEADER.len()..]
+                .trim()
+                .parse()
+                .map_err(|_| parse_error(1, "invalid version"))?,
+            _ => return Err(parse_error(1, "not a chip-8-rs movie")),
+        };
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let mut rom_hash = None;
+        let mut instructions_per_frame = None;
+        let mut seed = None;
+        let mut memory_mode = None;
+        let mut quirks = None;
+        let mut frames = None;
+        let mut key_events: Vec<KeyEvent> = Vec::new();
+        for (line_number, line) in lines {
+            let (field, value) = line.split_once(' ').unwrap_or((line, ""));
+            let invalid = || parse_error(line_number, &format!("invalid {} '{}'", field, value));
+            match field {
+                "rom-hash" => {
+                    rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?)
+                }
+                "instructions-per-frame" => {
+                    instructions_per_frame = Some(value.parse().map_err(|_| invalid())?)
+                }
+                "seed" => seed = Some(value.parse().map_err(|_| invalid())?),
+                "frames" => frames = Some(value.parse().map_err(|_| invalid())?),
+                "memory-mode" => {
+                    memory_mode = match value {
+                        "chip8" => Some(MemoryMode::Chip8),
+                        "xochip" => Some(MemoryMode::XoChip),
+                        _ => return Err(invalid()),
+                    }
+                }
+                "quirks" => quirks = Some(parse_quirks(value).ok_or_else(invalid)?),
+                "key" => {
+                    let key_event = parse_key_event(value).ok_or_else(invalid)?;
+                    if key_events
+                        .last()
+                        .is_some_and(|last| last.frame > key_event.frame)
+                    {
+                        return Err(parse_error(line_number, "key events are out of order"));
+                    }
+                    key_events.push(key_event);
+                }
+                _ => {
+                    return Err(parse_error(
+                        line_number,
+                        &format!("unknown field '{}'", field),
+                    ))
+                }
+            }
+        }
+
+        // a default would start the run differently from the recording without a word
+        let missing = |field: &str| parse_error(1, &format!("missing {}", field));
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom-hash"))?,
+            instructions_per_frame: instructions_per_frame
+                .ok_or_else(|| missing("instructions-per-frame"))?,
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            memory_mode: memory_mode.ok_or_else(|| missing("memory-mode"))?,
+            quirks: quirks.ok_or_else(|| missing("quirks"))?,
+            frames: frames.ok_or_else(|| missing("frames"))?,
+            key_events,
+        })
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> MovieError {
+    MovieError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+// every quirk has to be there, like every header field
+fn parse_quirks(text: &str) -> Option<Quirks> {
+    let mut quirks = Quirks::default();
+    let mut names: Vec<&str> = Vec::new();
+    for setting in text.split_whitespace() {
+        let (name, value) = setting.split_once('=')?;
+        if names.contains(&name) {
+            return None;
+        }
+        names.push(name);
+        match name {
+            "shift_uses_vy" => quirks.shift_uses_vy = value.parse().ok()?,
+            "jump_uses_vx" => quirks.jump_uses_vx = value.parse().ok()?,
+            "vf_reset" => quirks.vf_reset = value.parse().ok()?,
+            "clip_sprites" => quirks.clip_sprites = value.parse().ok()?,
+            "display_wait" => quirks.display_wait = value.parse().ok()?,
+            "load_store" => {
+                quirks.load_store = match value {
+                    "x+1" => IndexIncrement::XPlusOne,
+                    "x" => IndexIncrement::X,
+                    "unchanged" => IndexIncrement::Unchanged,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+    }
+    (names.len() == 6).then_some(quirks)
+}
+
+// `<frame> <key in hex> down|up`
+fn parse_key_event(text: &str) -> Option<KeyEvent> {
+    let mut fields = text.split_whitespace();
+    let frame = fields.next()?.parse().ok()?;
+    let key = u8::from_str_radix(fields.next()?, 16)
+        .ok()
+        .filter(|key| *key < 16)?;
+    let pressed = match fields.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    match fields.next() {
+        Some(_) => None,
+        None => Some(KeyEvent {
+            frame,
+            key,
+            pressed,
+        }),
+    }
+}
@@ -0,0 +1,232 @@
+//! Step execution backwards: a ring buffer of [`Machine`] snapshots taken at frame boundaries,
+//! plus a log of the keypad, so any frame still covered by the buffer can be rebuilt by
+//! restoring the closest earlier snapshot and replaying from it.
+//!
+//! Replaying relies on the run being deterministic, which it is as long as the keypad only
+//! changes between frames, CXNN draws from the machine's own [`Random`](crate::Random).
+
+use std::{collections::VecDeque, error::Error, fmt, ops::Range};
+
+use crate::machine::{error::MachineError, machine::Machine, savestate::SaveStateError};
+
+/// Memory kept for snapshots before the oldest ones are dropped
+pub const DEFAULT_MEMORY_BUDGET: usize = 32 * 1024 * 1024;
+/// Frames between snapshots, every frame by default since a 4 KiB machine is cheap to keep
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 1;
+
+#[derive(Debug)]
+pub enum RewindError {
+    /// The frame is older than the oldest snapshot, or has not happened yet
+    FrameUnavailable(u64),
+    State(SaveStateError),
+    /// Replaying up to the frame failed
+    Machine(MachineError),
+}
+
+impl fmt::Display for RewindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewindError::FrameUnavailable(frame) => {
+                write!(f, "frame {} is not in the rewind buffer", frame)
+            }
+            RewindError::State(error) => write!(f, "{}", error),
+            RewindError::Machine(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for RewindError {}
+
+impl From<SaveStateError> for RewindError {
+    fn from(error: SaveStateError) -> Self {
+        RewindError::State(error)
+    }
+}
+
+impl From<MachineError> for RewindError {
+    fn from(error: MachineError) -> Self {
+        RewindError::Machine(error)
+    }
+}
+
+struct Snapshot {
+    frame: u64,
+    state: Vec<u8>,
+}
+
+pub struct Rewind {
+    snapshots: VecDeque<Snapshot>,
+    // keypad state from every frame it changed on, oldest first
+    inputs: VecDeque<(u64, [bool; 16])>,
+    memory_budget: usize,
+    used_memory: usize,
+    snapshot_interval: u64,
+    newest_frame: Option<u64>,
+}
+
+impl Rewind {
+    pub fn new(memory_budget: usize, snapshot_interval: u64) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            inputs: VecDeque::new(),
+            memory_budget,
+            used_memory: 0,
+            snapshot_interval: snapshot_interval.max(1),
+            newest_frame: None,
+        }
+    }
+
+    /// Call at the start of every frame, before any of its instructions run. Recording a frame
+    /// that is not newer than the last one forgets everything after it, the run branched off
+    pub fn record(&mut self, machine: &Machine) {
+        let frame = machine.read_frame_count();
+        self.forget_from(frame);
+        self.newest_frame = Some(frame);
+
+        let keys = machine.read_pressed_keys();
+        if self
+            .inputs
+            .back()
+            .is_none_or(|(_, last_keys)| *last_keys != keys)
+        {
+            self.inputs.push_back((frame, keys));
+        }
+
+        if frame.is_multiple_of(self.snapshot_interval) {
+            let state = machine.save_state();
+            self.used_memory += state.len();
+            self.snapshots.push_back(Snapshot { frame, state });
+            self.evict();
+        }
+    }
+
+    /// First frame that can still be rewound to
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.frame)
+    }
+
+    /// Last frame that was recorded
+    pub fn newest_frame(&self) -> Option<u64> {
+        self.newest_frame
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Put `machine` back to the start of `frame`, replaying from the closest snapshot with
+    /// `instructions_per_frame`. Everything recorded after `frame` is forgotten
+    pub fn rewind_to(
+        &mut self,
+        machine: &mut Machine,
+        frame: u64,
+        instructions_per_frame: u32,
+    ) -> Result<(), RewindError> {
+        let newest_frame = self.newest_frame();
+        if newest_frame.is_none_or(|newest_frame| frame > newest_frame) {
+            return Err(RewindError::FrameUnavailable(frame));
+        }
+        let snapshot = self
+            .snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.frame <= frame)
+            .ok_or(RewindError::FrameUnavailable(frame))?;
+
+        let snapshot_frame = snapshot.frame;
+        machine.load_state(&snapshot.state)?;
+        // the tracer saw these frames the first time around, replaying them would repeat
+        // their records and push its cycle count ahead
+        let tracer = machine.tracer.take();
+        let replayed = self.replay(machine, snapshot_frame..frame, instructions_per_frame);
+        machine.tracer = tracer;
+        replayed?;
+        if let Some(keys) = self.keys_at(frame) {
+            set_keys(machine, keys);
+        }
+
+        self.forget_from(frame);
+        Ok(())
+    }
+
+    fn replay(
+        &self,
+        machine: &mut Machine,
+        frames: Range<u64>,
+        instructions_per_frame: u32,
+    ) -> Result<(), MachineError> {
+        for frame in frames {
+            if let Some(keys) = self.keys_at(frame) {
+                set_keys(machine, keys);
+            }
+            machine.run_frame(instructions_per_frame)?;
+            // already seen the first time around
+            machine.take_sound_events();
+            machine.take_watch_hits();
+            machine.take_unknown_opcodes();
+        }
+        Ok(())
+    }
+
+    fn keys_at(&self, frame: u64) -> Option<[bool; 16]> {
+        self.inputs
+            .iter()
+            .rev()
+            .find(|(input_frame, _)| *input_frame <= frame)
+            .map(|(_, keys)| *keys)
+    }
+
+    // drop snapshots and inputs of `frame` and later
+    fn forget_from(&mut self, frame: u64) {
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|snapshot| snapshot.frame >= frame)
+        {
+            if let Some(snapshot) = self.snapshots.pop_back() {
+                self.used_memory -= snapshot.state.len();
+            }
+        }
+        while self.inputs.back().is_some_and(|(input, _)| *input >= frame) {
+            self.inputs.pop_back();
+        }
+        if self
+            .newest_frame
+            .is_some_and(|newest_frame| newest_frame >= frame)
+        {
+            self.newest_frame = frame.checked_sub(1);
+        }
+    }
+
+    fn evict(&mut self) {
+        // the newest snapshot always stays, even over budget
+        while self.used_memory > self.memory_budget && self.snapshots.len() > 1 {
+            if let Some(snapshot) = self.snapshots.pop_front() {
+                self.used_memory -= snapshot.state.len();
+            }
+        }
+
+        // keep the last input before the oldest snapshot, it still applies to it
+        let Some(oldest_frame) = self.oldest_frame() else {
+            return;
+        };
+        while self
+            .inputs
+            .get(1)
+            .is_some_and(|(input, _)| *input <= oldest_frame)
+        {
+            self.inputs.pop_front();
+        }
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_BUDGET, DEFAULT_SNAPSHOT_INTERVAL)
+    }
+}
+
+// not through set_pressed_keys, the replayed keypad is not new input for input recording
+fn set_keys(machine: &mut Machine, keys: [bool; 16]) {
+    machine.pressed_keys = keys;
+}
@@ -0,0 +1,222 @@
+//! Run a ROM without a frontend and check what ends up on the screen, the way test ROMs report
+//! their results.
+//!
+//! Expected screens are plain text, one line per row: `.` is off, `#` is plane 1, `+` plane 2
+//! and `%` both planes. Lines starting with `;` and trailing whitespace are ignored, so
+//! expected images can carry a comment about what they show.
+
+use std::{error::Error, fmt};
+
+use crate::machine::{error::MachineError, machine::Machine, screen::Screen};
+
+/// Why a run stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunEnd {
+    /// All requested frames ran
+    Frames,
+    /// PC reached a 1NNN jumping to itself, how test ROMs usually halt, nothing changes after it
+    Looped(u16),
+    /// The program ran 00FD
+    Exited,
+    /// An instruction failed, the machine is left on it
+    Failed(MachineError),
+}
+
+pub struct Runner {
+    machine: Machine,
+    instructions_per_frame: u32,
+}
+
+impl Runner {
+    pub fn new(machine: Machine, instructions_per_frame: u32) -> Self {
+        Self {
+            machine,
+            instructions_per_frame,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    /// Run up to `max_frames` frames, stopping early once the program halts. A halt is only
+    /// noticed between frames, a self loop keeps PC where it is so that is good enough
+    pub fn run(&mut self, max_frames: u64) -> RunEnd {
+        for _ in 0..max_frames {
+            if let Some(end) = self.halted() {
+                return end;
+            }
+            if let Err(error) = self.machine.run_frame(self.instructions_per_frame) {
+                return RunEnd::Failed(error);
+            }
+        }
+        self.halted().unwrap_or(RunEnd::Frames)
+    }
+
+    fn halted(&self) -> Option<RunEnd> {
+        if self.machine.has_exited() {
+            return Some(RunEnd::Exited);
+        }
+        let program_counter = self.machine.read_program_counter();
+        let opcode = self.machine.read_opcode(program_counter);
+        (opcode == 0x1000 | (program_counter & 0x0FFF)).then_some(RunEnd::Looped(program_counter))
+    }
+}
+
+/// The visible part of the screen in the expected image format
+pub fn screen_image(screen: &Screen) -> String {
+    (0..screen.height())
+        .map(|y| {
+            let row: String = (0..screen.width())
+                .map(|x| pixel_char(screen.pixel(x, y)))
+                .collect();
+            row + "\n"
+        })
+        .collect()
+}
+
+/// 64 bit FNV-1a of the visible pixels and the resolution, for tests that do not want to keep
+/// a whole image around
+pub fn screen_hash(screen: &Screen) -> u64 {
+    let size = [screen.width() as u8, screen.height() as u8];
+    let pixels =
+        (0..screen.height()).flat_map(|y| (0..screen.width()).map(move |x| screen.pixel(x, y)));
+    size.into_iter()
+        .chain(pixels)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Compare the screen against an expected image
+pub fn compare_screen(screen: &Screen, expected: &str) -> Result<(), ScreenMismatch> {
+    let expected: Vec<&str> = expected
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.starts_with(';'))
+        .collect();
+    let actual = screen_image(screen);
+    let actual: Vec<&str> = actual.lines().collect();
+
+    let expected_width = expected.first().map_or(0, |row| row.chars().count());
+    if expected.len() != actual.len()
+        || expected
+            .iter()
+            .any(|row| row.chars().count() != expected_width)
+        || expected_width != screen.width()
+    {
+        return Err(ScreenMismatch::Size {
+            expected: (expected_width, expected.len()),
+            actual: (screen.width(), screen.height()),
+        });
+    }
+
+    let rows: Vec<RowDiff> = expected
+        .iter()
+        .zip(&actual)
+        .enumerate()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(|(y, (expected, actual))| RowDiff {
+            y,
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        })
+        .collect();
+    match rows.is_empty() {
+        true => Ok(()),
+        false => Err(ScreenMismatch::Pixels(rows)),
+    }
+}
+
+pub fn compare_screen_hash(screen: &Screen, expected: u64) -> Result<(), ScreenMismatch> {
+    let actual = screen_hash(screen);
+    match actual == expected {
+        true => Ok(()),
+        false => Err(ScreenMismatch::Hash {
+            expected,
+            actual,
+            image: screen_image(screen),
+        }),
+    }
+}
+
+/// A row that differs from the expected image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RowDiff {
+    pub y: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScreenMismatch {
+    /// Width and height, a lores image never matches a hires screen
+    Size {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    Pixels(Vec<RowDiff>),
+    /// The actual image is kept so it can be inspected, or saved as the new expectation
+    Hash {
+        expected: u64,
+        actual: u64,
+        image: String,
+    },
+}
+
+impl fmt::Display for ScreenMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenMismatch::Size { expected, actual } => write!(
+                f,
+                "expected a {}x{} screen, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            ScreenMismatch::Pixels(rows) => {
+                writeln!(f, "screen differs on {} rows", rows.len())?;
+                for row in rows {
+                    // a caret under every pixel that differs
+                    let markers: String = row
+                        .expected
+                        .chars()
+                        .zip(row.actual.chars())
+                        .map(|(expected, actual)| if expected == actual { ' ' } else { '^' })
+                        .collect();
+                    writeln!(f, "row {}:", row.y)?;
+                    writeln!(f, "  expected {}", row.expected)?;
+                    writeln!(f, "  actual   {}", row.actual)?;
+                    writeln!(f, "           {}", markers.trim_end())?;
+                }
+                Ok(())
+            }
+            ScreenMismatch::Hash {
+                expected,
+                actual,
+                image,
+            } => write!(
+                f,
+                "expected screen hash {:016x}, got {:016x}:\n{}",
+                expected, actual, image
+            ),
+        }
+    }
+}
+
+impl Error for ScreenMismatch {}
+
+fn pixel_char(pixel: u8) -> char {
+    match pixel {
+        0 => '.',
+        1 => '#',
+        2 => '+',
+        _ => '%',
+    }
+}
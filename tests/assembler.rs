@@ -0,0 +1,130 @@
+//! Sources assemble to the bytes they describe, and listings assemble back to the same ROM.
+
+use chip_8_rs::{
+    assembler::{assemble, AssembleError},
+    decode,
+    disassembler::disassemble,
+    Operation,
+};
+
+/// Source text for a listing, one instruction or data directive per line
+fn source_of(rom: &[u8], origin: u16) -> String {
+    let mut source = format!("ORG {:#X}\n", origin);
+    for line in disassemble(rom, origin) {
+        source.push_str(&format!("{} {}\n", line.mnemonic, line.operands));
+    }
+    source
+}
+
+#[test]
+fn every_operation_round_trips() {
+    let mut operations: Vec<Operation> = (0..=u16::MAX)
+        .filter_map(|opcode| decode(opcode).ok())
+        .map(|operation| match operation {
+            Operation::OpF000LongI { .. } => Operation::OpF000LongI { address: 0xBEEF },
+            operation => operation,
+        })
+        .collect();
+    operations.dedup();
+    let mut source: String = operations
+        .iter()
+        .map(|operation| format!("{}\n", operation))
+        .collect();
+    // an unknown word and a trailing odd byte
+    source.push_str("DW 0x5AB1\nDB 0xAB\n");
+
+    let rom = assemble(&source).unwrap().bytes;
+    assert_eq!(rom.len(), operations.len() * 2 + 2 + 2 + 1);
+    let listing = disassemble(&rom, 0x200);
+    let listed: Vec<Operation> = listing.iter().filter_map(|line| line.operation).collect();
+    assert_eq!(listed, operations);
+    assert_eq!(listing[listing.len() - 2].mnemonic, "DW");
+    assert_eq!(listing[listing.len() - 1].raw, vec![0xAB]);
+
+    let reassembled = assemble(&source_of(&rom, 0x200)).unwrap();
+    assert_eq!(reassembled.origin, 0x200);
+    assert_eq!(reassembled.bytes, rom);
+}
+
+#[test]
+fn labels_and_constants() {
+    let source = "
+SPEED   EQU 4
+start:  MOV V0, SPEED   ; a comment
+        MOVI sprite
+        JMP start
+sprite: DB 0xF0
+";
+    let assembled = assemble(source).unwrap();
+    assert_eq!(
+        assembled.bytes,
+        vec![0x60, 0x04, 0xA2, 0x06, 0x12, 0x00, 0xF0]
+    );
+    assert_eq!(assembled.labels["start"], 0x200);
+    assert_eq!(assembled.labels["sprite"], 0x206);
+    // constants are not labels
+    assert!(!assembled.labels.contains_key("SPEED"));
+}
+
+#[test]
+fn labels_can_be_used_before_they_are_defined() {
+    let assembled = assemble("CALL later\nlater:\nRET\nLONGI far\nfar: DW later").unwrap();
+    assert_eq!(
+        assembled.bytes,
+        vec![0x22, 0x02, 0x00, 0xEE, 0xF0, 0x00, 0x02, 0x08, 0x02, 0x02]
+    );
+}
+
+#[test]
+fn data_directives() {
+    let assembled = assemble("DB 0b11110000, 0x90, 7\nDW 0xF0F0, 0x12").unwrap();
+    assert_eq!(
+        assembled.bytes,
+        vec![0xF0, 0x90, 0x07, 0xF0, 0xF0, 0x00, 0x12]
+    );
+}
+
+#[test]
+fn org_sets_the_origin_then_pads() {
+    let assembled = assemble("ORG 0x600\nstart: CLS\nORG 0x606\nend: JMP start").unwrap();
+    assert_eq!(assembled.origin, 0x600);
+    assert_eq!(
+        assembled.bytes,
+        vec![0x00, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x16, 0x00]
+    );
+    assert_eq!(assembled.labels["end"], 0x606);
+
+    assert_eq!(
+        assemble("CLS\nORG 0x100").unwrap_err().to_string(),
+        "2:5: ORG cannot move backwards"
+    );
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    let error = |source: &str| assemble(source).unwrap_err();
+    assert_eq!(
+        error("CLS\n  MOV V0, 0x100"),
+        AssembleError {
+            line: 2,
+            column: 11,
+            message: "0x100 does not fit in a byte".to_string()
+        }
+    );
+    assert_eq!(
+        error("start: CLS\n\nstart: RET").to_string(),
+        "3:1: 'start' is defined twice"
+    );
+    assert_eq!(
+        error("   JMP nowhere").to_string(),
+        "1:8: unknown value or label 'nowhere'"
+    );
+    assert_eq!(
+        error("\tFOO V1").to_string(),
+        "1:2: unknown instruction 'FOO'"
+    );
+    assert_eq!(
+        error("DRW V0, 5").to_string(),
+        "1:1: wrong operands for DRW"
+    );
+}
@@ -0,0 +1,94 @@
+//! XO-CHIP audio: the pattern buffer played back at the rate the pitch register sets.
+
+use chip_8_rs::{write_wav, Audio, Machine, QuirkProfile, Rom, DEFAULT_PITCH, PATTERN_SIZE};
+
+// one bit on, then three off, repeated
+const PATTERN: [u8; PATTERN_SIZE] = [0x88; PATTERN_SIZE];
+
+fn square(levels: &[u8]) -> Vec<f32> {
+    levels
+        .iter()
+        .map(|&level| if level == 1 { 1.0 } else { -1.0 })
+        .collect()
+}
+
+#[test]
+fn default_pitch_plays_4000_bits_per_second() {
+    let mut audio = Audio::new();
+    assert_eq!(audio.read_pitch(), DEFAULT_PITCH);
+    assert_eq!(audio.playback_rate(), 4000.0);
+    // one bit per sample, 4 bits on and 4 off
+    let mut output = [0.0; 10];
+    audio.generate(1, 4000, &mut output);
+    assert_eq!(output.to_vec(), square(&[1, 1, 1, 1, 0, 0, 0, 0, 1, 1]));
+}
+
+#[test]
+fn pitch_scales_the_rate() {
+    let mut audio = Audio::new();
+    audio.write_to_pattern(PATTERN);
+    // 48 steps up is an octave, twice the rate, every other bit
+    audio.write_to_pitch(DEFAULT_PITCH + 48);
+    assert_eq!(audio.playback_rate(), 8000.0);
+    let mut output = [0.0; 6];
+    audio.generate(1, 4000, &mut output);
+    assert_eq!(output.to_vec(), square(&[1, 0, 1, 0, 1, 0]));
+
+    // an octave down, half the rate
+    audio.write_to_pitch(DEFAULT_PITCH - 48);
+    assert_eq!(audio.playback_rate(), 2000.0);
+}
+
+#[test]
+fn waveform_continues_between_calls() {
+    let mut audio = Audio::new();
+    audio.write_to_pattern(PATTERN);
+    let mut first = [0.0; 3];
+    let mut second = [0.0; 3];
+    audio.generate(1, 4000, &mut first);
+    audio.generate(1, 4000, &mut second);
+    assert_eq!(first.to_vec(), square(&[1, 0, 0]));
+    assert_eq!(second.to_vec(), square(&[0, 1, 0]));
+}
+
+#[test]
+fn silent_without_the_sound_timer() {
+    let mut audio = Audio::new();
+    let mut output = [0.5; 4];
+    audio.generate(0, 4000, &mut output);
+    assert_eq!(output, [0.0; 4]);
+}
+
+#[test]
+fn programs_load_the_pattern_and_pitch() {
+    // I = 0x300, load the pattern, V0 = 112, pitch = V0, sound timer = V0
+    let program = [0xA3, 0x00, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A, 0xF0, 0x18];
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::XoChip.quirks());
+    let mut machine = Rom::load_bytes(program.to_vec(), machine).unwrap();
+    machine.copy_to_ram(PATTERN.to_vec(), 0x300).unwrap();
+    for _ in 0..5 {
+        machine.exec().unwrap();
+    }
+    assert_eq!(machine.audio.read_pattern(), PATTERN);
+    assert_eq!(machine.audio.read_pitch(), 112);
+
+    let mut output = [0.0; 4];
+    machine.generate_audio(4000, &mut output);
+    assert_eq!(output.to_vec(), square(&[1, 0, 1, 0]));
+}
+
+#[test]
+fn wav_files_hold_16_bit_samples() {
+    let mut wav = Vec::new();
+    write_wav(&mut wav, 8000, &[1.0, -1.0, 0.0]).unwrap();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 8000);
+    assert_eq!(u32::from_le_bytes(wav[40..44].try_into().unwrap()), 6);
+    let samples: Vec<i16> = wav[44..]
+        .chunks(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+        .collect();
+    assert_eq!(samples, vec![i16::MAX, -i16::MAX, 0]);
+}
@@ -0,0 +1,115 @@
+//! The block engine ends every frame in exactly the state the interpreter does.
+
+use std::{fs, path::Path};
+
+use chip_8_rs::{
+    Access, ExecutionEngine, Machine, MachineError, MemoryMode, QuirkProfile, Rom, WatchAction,
+    WatchKind, Watchpoint,
+};
+
+const PROFILES: [QuirkProfile; 4] = [
+    QuirkProfile::Vip,
+    QuirkProfile::Chip48,
+    QuirkProfile::SuperChip,
+    QuirkProfile::XoChip,
+];
+
+fn load(program: &[u8], quirks: QuirkProfile, engine: ExecutionEngine) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(quirks.quirks());
+    if quirks == QuirkProfile::XoChip {
+        machine.set_memory_mode(MemoryMode::XoChip);
+    }
+    machine.set_execution_engine(engine);
+    Rom::load_bytes(program.to_vec(), machine).unwrap()
+}
+
+/// Run both engines side by side and compare their save states after every frame
+fn assert_lockstep(program: &[u8], quirks: QuirkProfile, ipf: u32, frames: u64) {
+    let mut interpreter = load(program, quirks, ExecutionEngine::Interpreter);
+    let mut blocks = load(program, quirks, ExecutionEngine::Blocks);
+    for frame in 0..frames {
+        let interpreted = interpreter.run_frame(ipf);
+        let translated = blocks.run_frame(ipf);
+        assert_eq!(interpreted, translated, "frame {} with {:?}", frame, quirks);
+        assert!(
+            interpreter.save_state() == blocks.save_state(),
+            "state differs after frame {} with {:?}, PC {:#05x} and {:#05x}",
+            frame,
+            quirks,
+            interpreter.read_program_counter(),
+            blocks.read_program_counter()
+        );
+        if interpreted.is_err() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn roms_match_the_interpreter() {
+    let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/roms");
+    for rom in ["2-ibm-logo.ch8", "4-flags.ch8", "test_opcode.ch8"] {
+        let program = fs::read(roms.join(rom)).unwrap();
+        for quirks in PROFILES {
+            // an odd budget ends frames in the middle of blocks
+            assert_lockstep(&program, quirks, 7, 200);
+            assert_lockstep(&program, quirks, 1000, 20);
+        }
+    }
+}
+
+#[test]
+fn self_modifying_loop() {
+    // counts V2 up, every pass stores V0 and V1 over the ADD at 0x20A so it adds 1, 2, 3..
+    let program = [
+        0x60, 0x72, // V0 = 0x72
+        0x61, 0x00, // V1 = 0
+        0x71, 0x01, // 0x204: V1 += 1
+        0xA2, 0x0A, // I = 0x20A
+        0xF1, 0x55, // store V0 and V1 at I
+        0x72, 0x00, // 0x20A: V2 += V1, rewritten every pass
+        0x12, 0x04, // jump 0x204
+    ];
+    for quirks in PROFILES {
+        assert_lockstep(&program, quirks, 9, 50);
+    }
+
+    let mut machine = load(&program, QuirkProfile::Chip48, ExecutionEngine::Blocks);
+    machine.run_frame(2 + 5 * 3).unwrap();
+    // 1 + 2 + 3
+    assert_eq!(machine.read_general_purpouse_registers(2), 6);
+}
+
+#[test]
+fn errors_leave_the_same_state() {
+    // 0x200: V0 += 1, 0x202: call 0x200
+    let program = [0x70, 0x01, 0x22, 0x00];
+    assert_lockstep(&program, QuirkProfile::Vip, 100, 1);
+
+    let mut machine = load(&program, QuirkProfile::Vip, ExecutionEngine::Blocks);
+    assert_eq!(
+        machine.run_frame(100),
+        Err(MachineError::StackOverflow {
+            program_counter: 0x202
+        })
+    );
+    // 16 calls fit, the 17th overflows
+    assert_eq!(machine.read_general_purpouse_registers(0), 17);
+}
+
+#[test]
+fn watchpoints_still_fire() {
+    // I = 0x300, then store V0 at I forever
+    let program = [0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+    let mut machine = load(&program, QuirkProfile::Chip48, ExecutionEngine::Blocks);
+    machine.add_watchpoint(Watchpoint::new(
+        0x300..=0x300,
+        WatchKind::Write,
+        WatchAction::Log,
+    ));
+    machine.run_frame(10).unwrap();
+    let hits = machine.take_watch_hits();
+    assert_eq!(hits.len(), 5);
+    assert!(hits.iter().all(|hit| hit.access == Access::Write));
+}
@@ -0,0 +1,79 @@
+//! The decode cache never runs an instruction that is no longer in memory.
+
+use chip_8_rs::{Machine, Rom};
+
+fn load(program: &[u8]) -> Machine {
+    Rom::load_bytes(program.to_vec(), Machine::new()).unwrap()
+}
+
+// calls a subroutine, rewrites its first instruction with FX55 and calls it again
+const REWRITES_SUBROUTINE: [u8; 16] = [
+    0xA2, 0x0C, // I = 0x20C
+    0x22, 0x0C, // call 0x20C
+    0x60, 0x60, // V0 = 0x60
+    0x61, 0x99, // V1 = 0x99
+    0xF1, 0x55, // store V0 and V1 at I, 0x20C becomes V0 = 0x99
+    0x22, 0x0C, // call 0x20C
+    0x60, 0x11, // 0x20C: V0 = 0x11
+    0x00, 0xEE, // return
+];
+
+fn run_rewrites_subroutine(cache: bool) -> Machine {
+    let mut machine = load(&REWRITES_SUBROUTINE);
+    machine.set_decode_cache(cache);
+    for _ in 0..4 {
+        machine.exec().unwrap();
+    }
+    assert_eq!(machine.read_general_purpouse_registers(0), 0x11);
+    for _ in 0..6 {
+        machine.exec().unwrap();
+    }
+    machine
+}
+
+#[test]
+fn store_registers_invalidates() {
+    let machine = run_rewrites_subroutine(true);
+    assert!(machine.is_decode_cache_enabled());
+    assert_eq!(machine.read_general_purpouse_registers(0), 0x99);
+    assert_eq!(machine.read_program_counter(), 0x20C);
+}
+
+#[test]
+fn same_result_without_cache() {
+    let machine = run_rewrites_subroutine(false);
+    assert!(!machine.is_decode_cache_enabled());
+    assert_eq!(machine.read_general_purpouse_registers(0), 0x99);
+}
+
+#[test]
+fn bcd_invalidates() {
+    let mut machine = load(&[
+        0x60, 0x07, // V0 = 7
+        0x12, 0x06, // jump 0x206
+        0x00, 0x00, //
+        0xA2, 0x01, // I = 0x201
+        0xF5, 0x33, // BCD of V5 at I, 0x200 becomes V0 = 1
+        0x12, 0x00, // jump 0x200
+    ]);
+    machine.write_to_general_purpouse_registers(5, 123);
+    for _ in 0..5 {
+        machine.exec().unwrap();
+    }
+    assert_eq!(machine.read_general_purpouse_registers(0), 7);
+    machine.exec().unwrap();
+    assert_eq!(machine.read_general_purpouse_registers(0), 1);
+}
+
+#[test]
+fn writing_the_long_operand_invalidates() {
+    // I = 0x1234 through the word after F000
+    let mut machine = load(&[0xF0, 0x00, 0x12, 0x34]);
+    machine.exec().unwrap();
+    assert_eq!(machine.read_index_register(), 0x1234);
+
+    machine.copy_to_ram(vec![0x56], 0x203).unwrap();
+    machine.write_to_program_counter(0x200);
+    machine.exec().unwrap();
+    assert_eq!(machine.read_index_register(), 0x1256);
+}
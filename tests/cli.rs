@@ -0,0 +1,227 @@
+//! Command line parsing, and what the binary does with arguments it cannot use.
+
+// the parser lives in the binary, which tests cannot link against
+#[allow(dead_code)]
+#[path = "../src/cli.rs"]
+mod cli;
+
+use std::process::Command as Process;
+
+use chip_8_rs::{ExecutionEngine, OpcodePolicy, QuirkProfile, TraceFormat};
+use cli::{CliError, Command, Expectation, Options};
+
+fn parse(arguments: &[&str]) -> Result<Command, CliError> {
+    Command::parse(arguments.iter().map(|argument| argument.to_string()))
+}
+
+fn run_options(arguments: &[&str]) -> Result<Options, CliError> {
+    Options::parse(arguments.iter().map(|argument| argument.to_string()))
+}
+
+fn error(arguments: &[&str]) -> String {
+    parse(arguments).unwrap_err().to_string()
+}
+
+#[test]
+fn defaults() {
+    let options = run_options(&["game.ch8"]).unwrap();
+    assert_eq!(options.rom_path, "game.ch8");
+    assert_eq!(options.instructions_per_frame, 10);
+    assert_eq!(options.quirks, QuirkProfile::Vip);
+    assert!(!options.headless && !options.debug && !options.vip_random);
+    assert_eq!(options.frames, None);
+    assert_eq!(options.seed, None);
+    assert!(options.trace.is_none());
+    assert_eq!(options.trace_format, TraceFormat::Text);
+    assert!(matches!(options.opcode_policy, OpcodePolicy::Halt));
+    assert_eq!(options.execution_engine, ExecutionEngine::Interpreter);
+    assert!(options.record.is_none() && options.play.is_none());
+    assert!(options.watchpoints.is_empty());
+
+    match parse(&["test", "game.ch8"]).unwrap() {
+        Command::Test(options) => {
+            assert_eq!(options.rom_path, "game.ch8");
+            assert_eq!(options.instructions_per_frame, 10);
+            assert_eq!(options.quirks, QuirkProfile::Vip);
+            assert_eq!(options.frames, 600);
+            assert!(options.expect.is_none() && options.reference.is_none());
+        }
+        command => panic!("parsed as {:?}", command),
+    }
+    assert!(matches!(
+        parse(&["disasm", "game.ch8"]).unwrap(),
+        Command::Disassemble { origin: 0x200, .. }
+    ));
+}
+
+#[test]
+fn values_are_parsed() {
+    let options = run_options(&[
+        "--ipf",
+        "20",
+        "game.ch8",
+        "--quirks",
+        "xochip",
+        "--seed",
+        "7",
+        "--trace-format",
+        "jsonl",
+    ])
+    .unwrap();
+    assert_eq!(options.instructions_per_frame, 20);
+    assert_eq!(options.quirks, QuirkProfile::XoChip);
+    assert_eq!(options.seed, Some(7));
+    assert_eq!(options.trace_format, TraceFormat::JsonLines);
+    // any trace flag turns tracing on
+    assert!(options.trace.is_some());
+
+    match parse(&["test", "game.ch8", "--expect-hash", "0xBEEF"]).unwrap() {
+        Command::Test(options) => {
+            assert!(matches!(options.expect, Some(Expectation::Hash(0xBEEF))))
+        }
+        command => panic!("parsed as {:?}", command),
+    }
+}
+
+#[test]
+fn missing_rom() {
+    for arguments in [&[][..], &["--headless"], &["test"], &["disasm"]] {
+        assert!(matches!(parse(arguments), Err(CliError::MissingRom)));
+    }
+    assert_eq!(error(&["--ipf", "5"]), "no ROM path given");
+}
+
+#[test]
+fn missing_values() {
+    assert!(matches!(
+        parse(&["game.ch8", "--ipf"]),
+        Err(CliError::MissingValue(flag)) if flag == "--ipf"
+    ));
+    assert_eq!(error(&["asm", "game.8o"]), "--output expects a value");
+}
+
+#[test]
+fn invalid_values() {
+    assert_eq!(
+        error(&["game.ch8", "--ipf", "abc"]),
+        "invalid value 'abc' for --ipf"
+    );
+    assert_eq!(
+        error(&["game.ch8", "--ipf", "-1"]),
+        "invalid value '-1' for --ipf"
+    );
+    assert_eq!(
+        error(&["game.ch8", "--gdb", "70000"]),
+        "invalid value '70000' for --gdb"
+    );
+    assert_eq!(
+        error(&["game.ch8", "--quirks", "cosmac"]),
+        "invalid value 'cosmac' for --quirks"
+    );
+    assert_eq!(
+        error(&["test", "game.ch8", "--quirks", "cosmac"]),
+        "invalid value 'cosmac' for --quirks"
+    );
+    assert_eq!(
+        error(&["game.ch8", "--random", "true"]),
+        "invalid value 'true' for --random"
+    );
+    assert!(matches!(
+        parse(&["game.ch8", "--trace-format", "xml"]),
+        Err(CliError::InvalidValue { flag, value }) if flag == "--trace-format" && value == "xml"
+    ));
+}
+
+#[test]
+fn unknown_arguments() {
+    assert_eq!(error(&["game.ch8", "--fast"]), "unknown argument '--fast'");
+    assert_eq!(
+        error(&["game.ch8", "other.ch8"]),
+        "unknown argument 'other.ch8'"
+    );
+    assert!(matches!(
+        parse(&["test", "game.ch8", "--headless"]),
+        Err(CliError::UnknownArgument(argument)) if argument == "--headless"
+    ));
+    assert!(matches!(
+        parse(&["disasm", "game.ch8", "--ipf", "5"]),
+        Err(CliError::UnknownArgument(argument)) if argument == "--ipf"
+    ));
+    assert!(matches!(parse(&["game.ch8", "-h"]), Err(CliError::Help)));
+}
+
+#[test]
+fn conflicting_options() {
+    assert!(matches!(
+        parse(&["game.ch8", "--play", "a.movie", "--record", "b.movie"]),
+        Err(CliError::ConflictingOptions("--record", "--play"))
+    ));
+    assert_eq!(
+        error(&["game.ch8", "--record", "a.movie", "--play", "b.movie"]),
+        "--record cannot be combined with --play"
+    );
+    assert_eq!(
+        error(&["game.ch8", "--random", "vip", "--play", "a.movie"]),
+        "--random vip cannot be combined with --play"
+    );
+    // the later --random wins
+    assert!(run_options(&["game.ch8", "--random", "vip", "--random", "seeded"]).is_ok());
+}
+
+#[test]
+fn the_binary_reports_bad_arguments() {
+    for (arguments, message) in [
+        (
+            &["--ipf", "abc", "game.ch8"][..],
+            "invalid value 'abc' for --ipf",
+        ),
+        (
+            &["--quirks", "cosmac"],
+            "invalid value 'cosmac' for --quirks",
+        ),
+        (&[], "no ROM path given"),
+    ] {
+        let output = Process::new(env!("CARGO_BIN_EXE_chip-8-rs"))
+            .args(arguments)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(output.status.code(), Some(2), "{}", stderr);
+        assert!(
+            stderr.starts_with(&format!("error: {}\n\nUsage:", message)),
+            "{}",
+            stderr
+        );
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn recording_is_refused_without_key_input() {
+    assert!(matches!(
+        parse(&["game.ch8", "--record", "a.movie"]),
+        Err(CliError::Unavailable("--record", _))
+    ));
+    assert_eq!(
+        error(&["--record", "a.movie", "game.ch8", "--headless"]),
+        "--record is not available yet, the terminal frontend reads no keys to record"
+    );
+    // conflicts are still reported as such
+    assert!(matches!(
+        parse(&["game.ch8", "--record", "a.movie", "--play", "b.movie"]),
+        Err(CliError::ConflictingOptions("--record", "--play"))
+    ));
+
+    // and the binary writes no empty movie
+    let movie = std::env::temp_dir().join(format!("chip-8-rs-{}.movie", std::process::id()));
+    let output = Process::new(env!("CARGO_BIN_EXE_chip-8-rs"))
+        .args(["game.ch8", "--headless", "--record"])
+        .arg(&movie)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("error: --record is not available yet"));
+    assert!(!movie.exists());
+}
@@ -0,0 +1,147 @@
+//! The debugger driven from code and through its prompt commands.
+
+use chip_8_rs::{
+    debugger::{parse_address, parse_address_range},
+    Debugger, Machine, Rom, StopReason,
+};
+
+// 0x200: V0 = 5, call 0x208, V1 += 1, 0x206: jump to itself, 0x208: V0 += 2, return
+const PROGRAM: [u8; 12] = [
+    0x60, 0x05, 0x22, 0x08, 0x71, 0x01, 0x12, 0x06, 0x70, 0x02, 0x00, 0xEE,
+];
+
+fn debugger() -> Debugger {
+    let machine = Rom::load_bytes(PROGRAM.to_vec(), Machine::new()).unwrap();
+    Debugger::new(machine, 10)
+}
+
+fn registers(debugger: &Debugger) -> (u8, u8, u16, u8) {
+    let machine = debugger.machine();
+    (
+        machine.read_general_purpouse_registers(0),
+        machine.read_general_purpouse_registers(1),
+        machine.read_program_counter(),
+        machine.read_stack_pointer(),
+    )
+}
+
+#[test]
+fn step() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.step(1), StopReason::Stepped);
+    assert_eq!(registers(&debugger), (5, 0, 0x202, 0));
+    // into the call
+    assert_eq!(debugger.step(2), StopReason::Stepped);
+    assert_eq!(registers(&debugger), (7, 0, 0x20A, 1));
+}
+
+#[test]
+fn breakpoint_hit() {
+    let mut debugger = debugger();
+    debugger.add_breakpoint(0x208);
+    assert_eq!(debugger.resume(), StopReason::Breakpoint(0x208));
+    // stopped before the instruction at the breakpoint
+    assert_eq!(registers(&debugger), (5, 0, 0x208, 1));
+
+    // the instruction at PC always runs, so continuing gets past it
+    debugger.add_breakpoint(0x206);
+    assert_eq!(debugger.resume(), StopReason::Breakpoint(0x206));
+    assert_eq!(registers(&debugger), (7, 1, 0x206, 0));
+
+    assert!(debugger.remove_breakpoint(0x208));
+    assert!(!debugger.remove_breakpoint(0x208));
+    assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), vec![0x206]);
+}
+
+#[test]
+fn breakpoints_stop_a_multi_step() {
+    let mut debugger = debugger();
+    debugger.add_breakpoint(0x204);
+    assert_eq!(debugger.step(10), StopReason::Breakpoint(0x204));
+    assert_eq!(registers(&debugger), (7, 0, 0x204, 0));
+}
+
+#[test]
+fn step_over_a_call() {
+    let mut debugger = debugger();
+    debugger.step(1);
+    assert_eq!(debugger.step_over(), StopReason::Returned);
+    // the whole subroutine ran
+    assert_eq!(registers(&debugger), (7, 0, 0x204, 0));
+    // anything else is a single step
+    assert_eq!(debugger.step_over(), StopReason::Stepped);
+    assert_eq!(registers(&debugger), (7, 1, 0x206, 0));
+}
+
+#[test]
+fn step_over_stops_at_breakpoints_inside_the_call() {
+    let mut debugger = debugger();
+    debugger.step(1);
+    debugger.add_breakpoint(0x20A);
+    assert_eq!(debugger.step_over(), StopReason::Breakpoint(0x20A));
+    assert_eq!(debugger.finish(), StopReason::Returned);
+    assert_eq!(registers(&debugger), (7, 0, 0x204, 0));
+}
+
+#[test]
+fn instruction_limit() {
+    let mut debugger = debugger();
+    debugger.set_instruction_limit(100);
+    assert_eq!(debugger.resume(), StopReason::InstructionLimit);
+    assert_eq!(debugger.machine().read_program_counter(), 0x206);
+}
+
+#[test]
+fn inspection() {
+    let mut debugger = debugger();
+    debugger.step(2);
+    assert_eq!(
+        debugger.registers(),
+        "V0=05 V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+         V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00\n\
+         I=0x000 PC=0x208 SP=1"
+    );
+    // the call, execution continues after it
+    assert_eq!(debugger.stack(), "#1 0x202");
+    assert_eq!(
+        debugger.memory(0x200, 20),
+        "0x0200  60 05 22 08 71 01 12 06 70 02 00 EE 00 00 00 00\n0x0210  00 00 00 00"
+    );
+    assert_eq!(debugger.location(), "0x208  7002      ADD V0, 0x02");
+}
+
+#[test]
+fn prompt_commands() {
+    let mut debugger = debugger();
+    assert_eq!(debugger.execute_command("b 0X20A"), "breakpoint at 0x20A");
+    assert_eq!(
+        debugger.execute_command("c"),
+        "breakpoint at 0x20A\n0x20A  00EE      RET"
+    );
+    assert_eq!(debugger.execute_command("x 0x208 2"), "0x0208  70 02");
+    assert_eq!(
+        debugger.execute_command("n"),
+        "0x204  7101      ADD V1, 0x01"
+    );
+    assert_eq!(
+        debugger.execute_command("d 20a"),
+        "deleted breakpoint at 0x20A"
+    );
+    assert_eq!(debugger.execute_command("b zz"), "invalid address 'zz'");
+    assert_eq!(
+        debugger.execute_command("frobnicate"),
+        "unknown command 'frobnicate', try help"
+    );
+}
+
+#[test]
+fn addresses_are_hex() {
+    assert_eq!(parse_address("0x200"), Some(0x200));
+    assert_eq!(parse_address("0X2Fa"), Some(0x2FA));
+    assert_eq!(parse_address("20A"), Some(0x20A));
+    assert_eq!(parse_address("0x0x20A"), None);
+    assert_eq!(parse_address("0x10000"), None);
+    assert_eq!(parse_address_range("0x300..0x30F"), Some(0x300..=0x30F));
+    assert_eq!(parse_address_range("300"), Some(0x300..=0x300));
+    assert_eq!(parse_address_range("300.."), None);
+}
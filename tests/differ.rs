@@ -0,0 +1,211 @@
+//! Runs are checked instruction by instruction against a reference trace. To update
+//! `tests/traces/4-flags.trace` after an intended change, run
+//! `chip-8-rs src/roms/4-flags.ch8 --headless --frames 147 --trace-out tests/traces/4-flags.trace`.
+
+use std::{fs, path::Path};
+
+use chip_8_rs::{
+    DiffEnd, DiffError, Differ, Machine, MachineError, OpcodeDecision, OpcodePolicy,
+    ReferenceTrace, Rom, TraceFilter, TraceFormat, TraceRecord,
+};
+
+const INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+// VF = 0x10, V0 = 0xFF, VF += V0 which carries, the flag has to win over the sum
+const ADD_INTO_VF: [u8; 6] = [0x6F, 0x10, 0x60, 0xFF, 0x8F, 0x04];
+
+fn differ(program: &[u8], reference: &str) -> Differ {
+    let machine = Rom::load_bytes(program.to_vec(), Machine::new()).unwrap();
+    Differ::new(machine, reference.parse().unwrap(), INSTRUCTIONS_PER_FRAME)
+}
+
+#[test]
+fn flags_rom_matches_its_recorded_trace() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let program = fs::read(root.join("src/roms/4-flags.ch8")).unwrap();
+    let reference = fs::read_to_string(root.join("tests/traces/4-flags.trace")).unwrap();
+    let mut differ = differ(&program, &reference);
+    match differ.run(600) {
+        DiffEnd::Matched { compared } => assert!(compared > 900),
+        end => panic!("{}", describe(end)),
+    }
+}
+
+fn describe(end: DiffEnd) -> String {
+    match end {
+        DiffEnd::Diverged(divergence) => divergence.to_string(),
+        end => format!("{:?}", end),
+    }
+}
+
+#[test]
+fn flag_written_after_the_sum() {
+    let reference = "\
+         0      0 0200 6F10 MOV VF, 0x10         I=0000 SP=0 VF=10
+         1      0 0202 60FF MOV V0, 0xFF         I=0000 SP=0 V0=FF
+         2      0 0204 8F04 ADD VF, V0           I=0000 SP=0 VF=01
+";
+    assert_eq!(
+        differ(&ADD_INTO_VF, reference).run(1),
+        DiffEnd::Matched { compared: 3 }
+    );
+}
+
+#[test]
+fn first_divergence_shows_expected_and_actual_state() {
+    // what a build writing VF before VX would record
+    let reference = "\
+         0      0 0200 6F10 MOV VF, 0x10         I=0000 SP=0 VF=10
+         1      0 0202 60FF MOV V0, 0xFF         I=0000 SP=0 V0=FF
+         2      0 0204 8F04 ADD VF, V0           I=0000 SP=0 VF=0F
+";
+    let mut differ = differ(&ADD_INTO_VF, reference);
+    let DiffEnd::Diverged(divergence) = differ.run(1) else {
+        panic!("no divergence");
+    };
+    assert_eq!(divergence.actual.cycle, 2);
+    assert_eq!(divergence.expected_registers[0xF], 0x0F);
+    assert_eq!(divergence.actual_registers[0xF], 0x01);
+    // stopped right there
+    assert_eq!(differ.machine().read_program_counter(), 0x206);
+
+    let report = divergence.to_string();
+    assert!(
+        report.starts_with("divergence at cycle 2, frame 0: 0204 8F04 ADD VF, V0\n"),
+        "{}",
+        report
+    );
+    assert!(report.contains("  differs  VF\n"), "{}", report);
+    assert!(report.contains("VE=00 VF=0F I=0000 SP=0\n"), "{}", report);
+}
+
+#[test]
+fn memory_writes_are_compared() {
+    // I = 0x300, V0 = 5, store V0 at I
+    let program = [0xA3, 0x00, 0x60, 0x05, 0xF0, 0x55];
+    let reference = r#"
+{"cycle":0,"frame":0,"pc":512,"opcode":41728,"i":768,"sp":0}
+{"cycle":1,"frame":0,"pc":514,"opcode":24581,"changed":{"V0":5},"i":768,"sp":0}
+{"cycle":2,"frame":0,"pc":516,"opcode":61525,"i":769,"sp":0,"writes":[[768,6]]}
+"#;
+    let DiffEnd::Diverged(divergence) = differ(&program, reference).run(1) else {
+        panic!("no divergence");
+    };
+    assert_eq!(divergence.actual.memory_writes, Some(vec![(0x300, 5)]));
+    let report = divergence.to_string();
+    assert!(
+        report.ends_with("  memory writes\n  expected [0300]=06\n  actual   [0300]=05\n"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn omitted_changes_and_writes_are_not_compared() {
+    // I = 0x300, V0 = 5, store V0 at I
+    let program = [0xA3, 0x00, 0x60, 0x05, 0xF0, 0x55];
+    let reference = r#"
+{"cycle":0,"frame":0,"pc":512,"opcode":41728,"i":768,"sp":0}
+{"cycle":1,"frame":0,"pc":514,"opcode":24581,"i":768,"sp":0}
+{"cycle":2,"frame":0,"pc":516,"opcode":61525,"i":769,"sp":0}
+"#;
+    assert_eq!(
+        differ(&program, reference).run(1),
+        DiffEnd::Matched { compared: 3 }
+    );
+
+    // present but empty says nothing changed
+    let reference = r#"
+{"cycle":0,"frame":0,"pc":512,"opcode":41728,"i":768,"sp":0}
+{"cycle":1,"frame":0,"pc":514,"opcode":24581,"changed":{},"i":768,"sp":0}
+"#;
+    let DiffEnd::Diverged(divergence) = differ(&program, reference).run(1) else {
+        panic!("no divergence");
+    };
+    assert_eq!(divergence.expected_registers[0], 0);
+    assert!(divergence.to_string().contains("  differs  V0\n"));
+
+    let reference = r#"
+{"cycle":0,"frame":0,"pc":512,"opcode":41728,"i":768,"sp":0}
+{"cycle":1,"frame":0,"pc":514,"opcode":24581,"i":768,"sp":0}
+{"cycle":2,"frame":0,"pc":516,"opcode":61525,"i":769,"sp":0,"writes":[]}
+"#;
+    let DiffEnd::Diverged(divergence) = differ(&program, reference).run(1) else {
+        panic!("no divergence");
+    };
+    assert!(divergence
+        .to_string()
+        .ends_with("  memory writes\n  expected none\n  actual   [0300]=05\n"));
+}
+
+#[test]
+fn filtered_reference_checks_what_is_there() {
+    let reference = "\
+         2      0 0204 8F04 ADD VF, V0           I=0000 SP=0 VF=01
+";
+    assert_eq!(
+        differ(&ADD_INTO_VF, reference).run(1),
+        DiffEnd::Matched { compared: 1 }
+    );
+}
+
+#[test]
+fn machine_errors_end_the_run() {
+    let reference = "\
+         0      0 0200 6F10 MOV VF, 0x10         I=0000 SP=0 VF=10
+         1      0 0202 60FF MOV V0, 0xFF         I=0000 SP=0 V0=FF
+         2      0 0204 8F04 ADD VF, V0           I=0000 SP=0 VF=01
+         3      0 0206 1206 JMP 0x206            I=0000 SP=0
+";
+    // the program runs into 0000 after the ADD
+    assert_eq!(
+        differ(&ADD_INTO_VF, reference).run(1),
+        DiffEnd::Failed {
+            error: MachineError::InvalidOpcode {
+                program_counter: 0x206,
+                opcode: 0
+            },
+            compared: 3
+        }
+    );
+}
+
+#[test]
+fn run_ending_early_is_incomplete() {
+    let reference = "\
+         0      0 0200 00FD EXIT                 I=0000 SP=0
+         1      0 0202 1202 JMP 0x202            I=0000 SP=0
+";
+    assert_eq!(
+        differ(&[0x00, 0xFD], reference).run(1),
+        DiffEnd::Incomplete {
+            compared: 1,
+            remaining: 1
+        }
+    );
+}
+
+#[test]
+fn records_read_back_in_both_formats() {
+    let mut machine =
+        Rom::load_bytes(vec![0xA3, 0x00, 0xF0, 0x55, 0x5A, 0xB1], Machine::new()).unwrap();
+    machine.set_opcode_policy(OpcodePolicy::LogAndSkip);
+    machine.set_trace(Some(TraceFilter::default()));
+    for _ in 0..3 {
+        machine.exec().unwrap();
+    }
+    let records = machine.take_trace();
+    assert_eq!(records[1].memory_writes, Some(vec![(0x300, 0)]));
+    assert_eq!(records[2].decision, Some(OpcodeDecision::Skipped));
+    for record in &records {
+        for format in [TraceFormat::Text, TraceFormat::JsonLines] {
+            let line = record.format(format);
+            assert_eq!(&line.parse::<TraceRecord>().unwrap(), record, "{}", line);
+        }
+    }
+    assert!("not a trace".parse::<TraceRecord>().is_err());
+    assert!(matches!(
+        "\n{\"cycle\":0}".parse::<ReferenceTrace>(),
+        Err(DiffError::Parse { line: 2, .. })
+    ));
+}
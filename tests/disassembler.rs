@@ -0,0 +1,50 @@
+//! Listings cover every byte of a ROM, instructions where they decode and data elsewhere.
+
+use chip_8_rs::{disassembler::disassemble, Operation};
+
+#[test]
+fn instructions_and_data() {
+    // CLS, an unknown word, JMP 0x200
+    let lines = disassemble(&[0x00, 0xE0, 0x5A, 0xB1, 0x12, 0x00], 0x200);
+    let listing: Vec<String> = lines.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        listing,
+        vec![
+            "0x200  00E0      CLS",
+            "0x202  5AB1      DW 0x5AB1",
+            "0x204  1200      JMP 0x200",
+        ]
+    );
+    assert_eq!(lines[0].operation, Some(Operation::Op00e0Cls));
+    assert_eq!(lines[1].operation, None);
+}
+
+#[test]
+fn long_i_load_covers_four_bytes() {
+    let lines = disassemble(&[0xF0, 0x00, 0xE0, 0x00, 0x00, 0xE0], 0x200);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].raw, vec![0xF0, 0x00, 0xE0, 0x00]);
+    assert_eq!(
+        lines[0].operation,
+        Some(Operation::OpF000LongI { address: 0xE000 })
+    );
+    assert_eq!(lines[1].address, 0x204);
+}
+
+#[test]
+fn truncated_long_i_load_is_data() {
+    let lines = disassemble(&[0xF0, 0x00, 0xE0], 0x200);
+    let listing: Vec<String> = lines.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        listing,
+        vec!["0x200  F000      DW 0xF000", "0x202  E0        DB 0xE0"]
+    );
+}
+
+#[test]
+fn origin_sets_the_addresses() {
+    let lines = disassemble(&[0x00, 0xE0, 0x00, 0xEE, 0xFF], 0x600);
+    let addresses: Vec<u16> = lines.iter().map(|line| line.address).collect();
+    assert_eq!(addresses, vec![0x600, 0x602, 0x604]);
+    assert_eq!(lines[2].raw, vec![0xFF]);
+}
@@ -0,0 +1,140 @@
+//! Broken programs report an error with the faulting PC instead of panicking, unless the
+//! opcode policy says otherwise.
+
+use std::sync::Arc;
+
+use chip_8_rs::{
+    Machine, MachineError, OpcodeDecision, OpcodePolicy, Rom, StepOutcome, UnknownOpcode,
+};
+
+fn load(program: &[u8]) -> Machine {
+    Rom::load_bytes(program.to_vec(), Machine::new()).unwrap()
+}
+
+#[test]
+fn stack_overflow() {
+    // 0x200: call 0x200
+    let mut machine = load(&[0x22, 0x00]);
+    // all 16 entries are usable
+    for depth in 1..=16 {
+        assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+        assert_eq!(machine.read_stack_pointer(), depth);
+    }
+    assert_eq!(machine.read_stack(), [0x200; 16]);
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::StackOverflow {
+            program_counter: 0x200
+        })
+    );
+    assert_eq!(machine.read_stack_pointer(), 16);
+}
+
+#[test]
+fn stack_underflow() {
+    let mut machine = load(&[0x00, 0xEE]);
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::StackUnderflow {
+            program_counter: 0x200
+        })
+    );
+    assert_eq!(machine.read_program_counter(), 0x200);
+}
+
+#[test]
+fn memory_out_of_range() {
+    // I = 0xFFF, load V0 and V1 from I
+    let mut machine = load(&[0xAF, 0xFF, 0xF1, 0x65]);
+    assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::MemoryOutOfRange {
+            program_counter: 0x202,
+            address: 0x1000
+        })
+    );
+    // nothing was loaded
+    assert_eq!(machine.read_general_purpouse_registers(0), 0);
+}
+
+#[test]
+fn fetch_past_end_of_ram() {
+    let mut machine = Machine::new();
+    machine.write_to_program_counter(0xFFF);
+    assert_eq!(machine.exec().unwrap_err().program_counter(), 0xFFF);
+}
+
+#[test]
+fn invalid_opcode() {
+    let mut machine = load(&[0x5A, 0xB1]);
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::InvalidOpcode {
+            program_counter: 0x200,
+            opcode: 0x5AB1
+        })
+    );
+}
+
+#[test]
+fn rom_too_large() {
+    assert!(Rom::load_bytes(vec![0; 4096], Machine::new()).is_err());
+}
+
+#[test]
+fn machine_code_call_halts_by_default() {
+    let mut machine = load(&[0x01, 0x23]);
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::InvalidOpcode {
+            program_counter: 0x200,
+            opcode: 0x0123
+        })
+    );
+}
+
+#[test]
+fn skipped_opcodes_are_logged() {
+    let mut machine = load(&[0x5A, 0xB1, 0x01, 0x23]);
+    machine.set_opcode_policy(OpcodePolicy::LogAndSkip);
+    assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+    assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+    assert_eq!(machine.read_program_counter(), 0x204);
+
+    let skipped = machine.take_unknown_opcodes();
+    assert_eq!(skipped.len(), 2);
+    assert_eq!(
+        skipped[0].to_string(),
+        "unknown opcode 5AB1 at 0x200: skipped"
+    );
+    assert_eq!(
+        skipped[1],
+        UnknownOpcode {
+            program_counter: 0x202,
+            opcode: 0x0123,
+            decision: OpcodeDecision::Skipped
+        }
+    );
+}
+
+#[test]
+fn ignored_opcodes_are_not_logged() {
+    let mut machine = load(&[0x5A, 0xB1]);
+    machine.set_opcode_policy(OpcodePolicy::Ignore);
+    assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+    assert!(machine.take_unknown_opcodes().is_empty());
+}
+
+#[test]
+fn handler_runs_in_place_of_the_opcode() {
+    // pretend 0NNN loads NN into V0
+    let mut machine = load(&[0x00, 0x42]);
+    machine.set_opcode_policy(OpcodePolicy::Handler(Arc::new(|machine, opcode| {
+        machine.write_to_general_purpouse_registers(0, opcode as u8);
+        Ok(())
+    })));
+    assert_eq!(machine.exec(), Ok(StepOutcome::Executed));
+    assert_eq!(machine.read_general_purpouse_registers(0), 0x42);
+    assert_eq!(machine.read_program_counter(), 0x202);
+}
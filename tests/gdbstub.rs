@@ -0,0 +1,196 @@
+//! A scripted client talks to the stub over loopback with real protocol packets.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+};
+
+use chip_8_rs::{Debugger, GdbStub, Machine, Rom};
+
+// 0x200: V0 = 5, call 0x208, V1 += 1, 0x206: jump to itself, 0x208: V0 += 2, return
+const PROGRAM: [u8; 12] = [
+    0x60, 0x05, 0x22, 0x08, 0x71, 0x01, 0x12, 0x06, 0x70, 0x02, 0x00, 0xEE,
+];
+
+struct Client {
+    stream: TcpStream,
+    stub: JoinHandle<GdbStub>,
+}
+
+impl Client {
+    fn connect() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stub = thread::spawn(move || {
+            let machine = Rom::load_bytes(PROGRAM.to_vec(), Machine::new()).unwrap();
+            let mut stub = GdbStub::new(Debugger::new(machine, 10));
+            let (stream, _) = listener.accept().unwrap();
+            stub.serve(stream).unwrap();
+            stub
+        });
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Self { stream, stub }
+    }
+
+    /// Send a packet and return the reply once the stub acknowledged it
+    fn send(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+', "{} was not acknowledged", data);
+        self.read_reply()
+    }
+
+    fn read_reply(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut reply = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            reply.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Detach and hand back the stub to look at the machine
+    fn detach(mut self) -> GdbStub {
+        assert_eq!(self.send("D"), "OK");
+        self.stub.join().unwrap()
+    }
+}
+
+#[test]
+fn target_description() {
+    let mut client = Client::connect();
+    assert!(client
+        .send("qSupported:multiprocess+;xmlRegisters=i386")
+        .contains("qXfer:features:read+"));
+
+    let description = client.send("qXfer:features:read:target.xml:0,fff");
+    assert!(description.starts_with("l<?xml"), "{}", description);
+    assert!(description.contains("<architecture>chip8</architecture>"));
+    assert!(description.contains("<reg name=\"v0\" bitsize=\"8\" type=\"uint8\"/>"));
+    assert!(description.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+
+    // read in chunks, m while there is more
+    let first = client.send("qXfer:features:read:target.xml:0,10");
+    assert_eq!(first, "m<?xml version=\"1");
+    let rest = client.send("qXfer:features:read:target.xml:10,fff");
+    assert_eq!(format!("{}{}", &first[1..], &rest[1..]), description[1..]);
+    client.detach();
+}
+
+#[test]
+fn registers() {
+    let mut client = Client::connect();
+    assert_eq!(client.send("?"), "S05");
+    // V0 to VF, I, PC little endian, SP, DT, ST
+    assert_eq!(
+        client.send("g"),
+        format!("{}{}{}{}", "00".repeat(16), "0000", "0002", "000000")
+    );
+
+    let registers = format!(
+        "{}{}{}{}",
+        "01020304050607080910111213141516", "3412", "0402", "0a1e00"
+    );
+    assert_eq!(client.send(&format!("G{}", registers)), "OK");
+    assert_eq!(client.send("g"), registers);
+    assert_eq!(client.send("G00"), "E01");
+
+    assert_eq!(client.send("p11"), "0402");
+    assert_eq!(client.send("P0=2a"), "OK");
+    assert_eq!(client.send("P10=0003"), "OK");
+    assert_eq!(client.send("p0"), "2a");
+    assert_eq!(client.send("p15"), "E01");
+    assert_eq!(client.send("P1=0003"), "E01");
+
+    let stub = client.detach();
+    let machine = stub.into_debugger().into_machine();
+    assert_eq!(machine.read_general_purpouse_registers(0), 0x2A);
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 0x16);
+    assert_eq!(machine.read_index_register(), 0x300);
+    assert_eq!(machine.read_program_counter(), 0x204);
+    assert_eq!(machine.read_stack_pointer(), 0x0A);
+    assert_eq!(machine.read_delay_timer(), 0x1E);
+}
+
+#[test]
+fn memory() {
+    let mut client = Client::connect();
+    assert_eq!(client.send("m200,4"), "60052208");
+    assert_eq!(client.send("M300,3:abcdef"), "OK");
+    assert_eq!(client.send("m300,3"), "abcdef");
+    // lengths that do not match the data, past the end of ram
+    assert_eq!(client.send("M300,2:ab"), "E01");
+    assert_eq!(client.send("mfff,2"), "E01");
+
+    let machine = client.detach().into_debugger().into_machine();
+    assert_eq!(machine.read_ram(0x301), Ok(0xCD));
+}
+
+#[test]
+fn breakpoints_step_and_continue() {
+    let mut client = Client::connect();
+    assert_eq!(client.send("Z0,208,2"), "OK");
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p11"), "0802");
+    assert_eq!(client.send("p0"), "05");
+
+    assert_eq!(client.send("z0,208,2"), "OK");
+    assert_eq!(client.send("s"), "S05");
+    assert_eq!(client.send("p11"), "0a02");
+    assert_eq!(client.send("p0"), "07");
+    // hardware breakpoints are not supported
+    assert_eq!(client.send("Z1,206,2"), "");
+
+    // resuming from an address
+    assert_eq!(client.send("Z0,206,2"), "OK");
+    assert_eq!(client.send("c204"), "S05");
+    assert_eq!(client.send("p11"), "0602");
+    assert_eq!(client.send("p1"), "01");
+
+    let stub = client.detach();
+    let debugger = stub.into_debugger();
+    assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), vec![0x206]);
+    assert_eq!(debugger.machine().read_program_counter(), 0x206);
+}
+
+#[test]
+fn interrupting_a_running_program() {
+    let mut client = Client::connect();
+    // nothing stops the jump to itself but the client
+    assert_eq!(client.send("M200,2:1200"), "OK");
+    write!(client.stream, "$c#63").unwrap();
+    assert_eq!(client.read_byte(), b'+');
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.read_reply(), "S02");
+    assert_eq!(client.send("p11"), "0002");
+    client.detach();
+}
+
+#[test]
+fn kill_closes_the_connection() {
+    let mut client = Client::connect();
+    write!(client.stream, "$k#6b").unwrap();
+    assert_eq!(client.read_byte(), b'+');
+    client.stub.join().unwrap();
+    let mut rest = Vec::new();
+    client.stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
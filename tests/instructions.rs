@@ -0,0 +1,50 @@
+//! Every opcode decodes to one operation, which encodes back to the same opcode and prints in
+//! a syntax the assembler reads back.
+
+use chip_8_rs::{assembler, decode, DecodeError, Operation};
+
+#[test]
+fn decode_encode_round_trip() {
+    for opcode in 0..=u16::MAX {
+        if let Ok(operation) = decode(opcode) {
+            assert_eq!(operation.encode(), opcode, "{}", operation);
+        }
+    }
+}
+
+#[test]
+fn display_assembles_back() {
+    for opcode in 0..=u16::MAX {
+        let Ok(operation) = decode(opcode) else {
+            continue;
+        };
+        let assembled = assembler::assemble(&operation.to_string()).unwrap();
+        assert_eq!(
+            &assembled.bytes[..2],
+            &opcode.to_be_bytes(),
+            "{}",
+            operation
+        );
+    }
+}
+
+#[test]
+fn shifts_keep_register_y() {
+    assert_eq!(
+        decode(0x8126),
+        Ok(Operation::Op8xy6Shr {
+            register_x: 1,
+            register_y: 2
+        })
+    );
+    assert_eq!(decode(0x812E).unwrap().to_string(), "SHL V1, V2");
+}
+
+#[test]
+fn unknown_opcodes() {
+    assert_eq!(decode(0x5AB1), Err(DecodeError::UnknownOpcode(0x5AB1)));
+    assert_eq!(
+        decode(0xE0FF).unwrap_err().to_string(),
+        "unknown opcode E0FF"
+    );
+}
@@ -0,0 +1,120 @@
+//! Playing a recorded movie reproduces the run bit for bit.
+
+use chip_8_rs::{Machine, MemoryMode, Movie, MovieError, QuirkProfile, Rewind};
+
+// V3 = 5, then loop: V0 = random digit, I = its sprite, draw it at V1, V2, V1 += 1,
+// unless key 5 is up V2 += 1
+const ROM: [u8; 16] = [
+    0x63, 0x05, 0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x01, 0xE3, 0xA1, 0x72, 0x01, 0x12, 0x02,
+];
+
+fn movie() -> Movie {
+    Movie::new(
+        &ROM,
+        QuirkProfile::SuperChip.quirks(),
+        MemoryMode::Chip8,
+        99,
+        9,
+    )
+}
+
+/// Record `frames` frames of key 5 and key A going up and down
+fn record(frames: u64) -> (Movie, Machine) {
+    let mut movie = movie();
+    let mut machine = movie.start_recording(&ROM).unwrap();
+    for frame in 0..frames {
+        match frame % 5 {
+            1 => machine.set_pressed_keys(0x5),
+            3 => machine.unset_pressed_keys(0x5),
+            _ => {}
+        }
+        if frame % 11 == 4 {
+            machine.set_pressed_keys(0xA);
+            machine.unset_pressed_keys(0xA);
+        }
+        machine.run_frame(movie.instructions_per_frame).unwrap();
+    }
+    movie.finish_recording(&mut machine);
+    (movie, machine)
+}
+
+#[test]
+fn playback_is_bit_exact() {
+    let (movie, recorded) = record(40);
+    assert_eq!(movie.frames, 40);
+    assert!(movie.key_events.len() > 10);
+
+    let parsed: Movie = movie.to_string().parse().unwrap();
+    assert_eq!(parsed, movie);
+    let played = parsed.play(&ROM).unwrap();
+
+    assert_eq!(played.screen.display, recorded.screen.display);
+    for register in 0..16 {
+        assert_eq!(
+            played.read_general_purpouse_registers(register),
+            recorded.read_general_purpouse_registers(register)
+        );
+    }
+    assert_eq!(played.read_index_register(), recorded.read_index_register());
+    assert_eq!(
+        played.read_program_counter(),
+        recorded.read_program_counter()
+    );
+    for address in 0..played.ram_size() as u16 {
+        assert_eq!(played.read_ram(address), recorded.read_ram(address));
+    }
+    assert_eq!(played.save_state(), recorded.save_state());
+}
+
+#[test]
+fn rewinding_during_playback_sees_the_frames_inputs() {
+    let (movie, _) = record(40);
+    let mut machine = movie.machine(&ROM).unwrap();
+    let mut rewind = Rewind::new(usize::MAX, 10);
+    // the frame loop of the binary, inputs first
+    while machine.read_frame_count() < movie.frames {
+        movie.apply_inputs(&mut machine);
+        rewind.record(&machine);
+        machine.run_frame(movie.instructions_per_frame).unwrap();
+    }
+
+    // key 5 goes down at the start of frame 26
+    rewind
+        .rewind_to(&mut machine, 26, movie.instructions_per_frame)
+        .unwrap();
+    assert!(machine.read_pressed_keys()[0x5]);
+    let mut played = movie.clone();
+    played.frames = 26;
+    let mut expected = played.play(&ROM).unwrap();
+    movie.apply_inputs(&mut expected);
+    assert_eq!(machine.save_state(), expected.save_state());
+}
+
+#[test]
+fn header_fields_are_required() {
+    let text = movie().to_string();
+    for field in [
+        "rom-hash",
+        "instructions-per-frame",
+        "seed",
+        "memory-mode",
+        "quirks",
+        "frames",
+    ] {
+        let without: String = text
+            .lines()
+            .filter(|line| !line.starts_with(field))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let error = without.parse::<Movie>().unwrap_err();
+        assert!(matches!(error, MovieError::Parse { .. }));
+        assert_eq!(error.to_string(), format!("line 1: missing {}", field));
+    }
+
+    // and so is every quirk
+    let partial = text.replace(" display_wait=false", "");
+    assert_eq!(
+        partial.parse::<Movie>().unwrap_err().to_string(),
+        "line 6: invalid quirks 'shift_uses_vy=false load_store=unchanged jump_uses_vx=true vf_reset=false clip_sprites=true'"
+    );
+}
@@ -0,0 +1,85 @@
+//! XO-CHIP bitplanes: drawing, clearing and scrolling only touch the selected planes.
+
+use chip_8_rs::{Machine, QuirkProfile, Rom, ALL_PLANES, PLANE_1, PLANE_2};
+
+// plane 1 gets the first two pixels, plane 2 only the first
+const SPRITE: [u8; 2] = [0xC0, 0x80];
+
+fn load(program: &[u8]) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::XoChip.quirks());
+    let mut machine = Rom::load_bytes(program.to_vec(), machine).unwrap();
+    machine.copy_to_ram(SPRITE.to_vec(), 0x300).unwrap();
+    machine
+}
+
+fn run(machine: &mut Machine, instructions: usize) {
+    for _ in 0..instructions {
+        machine.exec().unwrap();
+    }
+}
+
+fn row(machine: &Machine, y: usize) -> [u8; 5] {
+    std::array::from_fn(|x| machine.screen.pixel(x, y))
+}
+
+// select both planes, I = SPRITE, draw one row at 0, 0
+const DRAW_BOTH: [u8; 6] = [0xF3, 0x01, 0xA3, 0x00, 0xD0, 0x01];
+
+#[test]
+fn sprites_draw_plane_after_plane() {
+    let mut machine = load(&DRAW_BOTH);
+    run(&mut machine, 3);
+    assert_eq!(machine.screen.selected_planes(), ALL_PLANES);
+    assert_eq!(row(&machine, 0), [3, 1, 0, 0, 0]);
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 0);
+}
+
+#[test]
+fn collisions_are_per_plane() {
+    // plane 2, I = SPRITE + 1, draw at 0, 0, plane 1, I = SPRITE + 1, draw at 0, 0
+    let mut machine = load(&[
+        0xF2, 0x01, 0xA3, 0x01, 0xD0, 0x01, 0xF1, 0x01, 0xA3, 0x01, 0xD0, 0x01,
+    ]);
+    run(&mut machine, 3);
+    assert_eq!(row(&machine, 0), [PLANE_2, 0, 0, 0, 0]);
+    // lit on plane 2 does not count for plane 1
+    run(&mut machine, 3);
+    assert_eq!(row(&machine, 0), [ALL_PLANES, 0, 0, 0, 0]);
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 0);
+}
+
+#[test]
+fn clear_keeps_unselected_planes() {
+    // plane 2, clear, plane 1, clear
+    let mut program = DRAW_BOTH.to_vec();
+    program.extend([0xF2, 0x01, 0x00, 0xE0, 0xF1, 0x01, 0x00, 0xE0]);
+    let mut machine = load(&program);
+    run(&mut machine, 5);
+    assert_eq!(row(&machine, 0), [PLANE_1, PLANE_1, 0, 0, 0]);
+    run(&mut machine, 2);
+    assert_eq!(row(&machine, 0), [0; 5]);
+}
+
+#[test]
+fn scroll_keeps_unselected_planes() {
+    // plane 1, scroll down 2, plane 2, scroll right 4
+    let mut program = DRAW_BOTH.to_vec();
+    program.extend([0xF1, 0x01, 0x00, 0xC2, 0xF2, 0x01, 0x00, 0xFB]);
+    let mut machine = load(&program);
+    run(&mut machine, 5);
+    assert_eq!(row(&machine, 0), [PLANE_2, 0, 0, 0, 0]);
+    assert_eq!(row(&machine, 2), [PLANE_1, PLANE_1, 0, 0, 0]);
+    run(&mut machine, 2);
+    assert_eq!(row(&machine, 0), [0, 0, 0, 0, PLANE_2]);
+    assert_eq!(row(&machine, 2), [PLANE_1, PLANE_1, 0, 0, 0]);
+}
+
+#[test]
+fn no_planes_selected_draws_nothing() {
+    // no planes, I = SPRITE, draw at 0, 0
+    let mut machine = load(&[0xF0, 0x01, 0xA3, 0x00, 0xD0, 0x01]);
+    machine.screen.flip_pixel(4, 0, PLANE_1);
+    run(&mut machine, 3);
+    assert_eq!(row(&machine, 0), [0, 0, 0, 0, PLANE_1]);
+}
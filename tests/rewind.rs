@@ -0,0 +1,123 @@
+//! Rewinding rebuilds earlier frames exactly, within the memory it was given.
+
+use chip_8_rs::{
+    rewind::DEFAULT_MEMORY_BUDGET, Machine, QuirkProfile, Rewind, RewindError, Rom, TraceFilter,
+};
+
+// V0 = random digit, I = its sprite, draw it at V1, V2, V1 += 1, unless key 0 is up
+// V2 += 1, loop
+const PROGRAM: [u8; 14] = [
+    0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x01, 0xE3, 0xA1, 0x72, 0x01, 0x12, 0x00,
+];
+const INSTRUCTIONS_PER_FRAME: u32 = 9;
+
+fn machine() -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::SuperChip.quirks());
+    Rom::load_bytes(PROGRAM.to_vec(), machine).unwrap()
+}
+
+/// Run `frames` frames, holding key 0 down on some of them, and return the state at the start
+/// of every frame
+fn run(machine: &mut Machine, rewind: &mut Rewind, frames: u64) -> Vec<Vec<u8>> {
+    let mut states = Vec::new();
+    for frame in 0..frames {
+        match frame % 7 < 3 {
+            true => machine.set_pressed_keys(0),
+            false => machine.unset_pressed_keys(0),
+        }
+        rewind.record(machine);
+        states.push(machine.save_state());
+        machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+    }
+    states
+}
+
+#[test]
+fn rewinding_matches_a_snapshot_of_that_frame() {
+    let mut machine = machine();
+    let mut rewind = Rewind::new(DEFAULT_MEMORY_BUDGET, 5);
+    let states = run(&mut machine, &mut rewind, 20);
+
+    // between snapshots, so frames 10 and 11 are replayed
+    rewind
+        .rewind_to(&mut machine, 12, INSTRUCTIONS_PER_FRAME)
+        .unwrap();
+    assert_eq!(machine.read_frame_count(), 12);
+    assert_eq!(machine.save_state(), states[12]);
+    assert_eq!(rewind.newest_frame(), Some(11));
+
+    // and on a snapshot
+    rewind
+        .rewind_to(&mut machine, 5, INSTRUCTIONS_PER_FRAME)
+        .unwrap();
+    assert_eq!(machine.save_state(), states[5]);
+    assert!(matches!(
+        rewind.rewind_to(&mut machine, 6, INSTRUCTIONS_PER_FRAME),
+        Err(RewindError::FrameUnavailable(6))
+    ));
+}
+
+#[test]
+fn replaying_is_not_new_input_or_trace() {
+    let mut machine = machine();
+    let mut rewind = Rewind::new(DEFAULT_MEMORY_BUDGET, 5);
+    machine.set_input_recording(true);
+    machine.set_trace(Some(TraceFilter::default()));
+    let states = run(&mut machine, &mut rewind, 20);
+    let trace = machine.take_trace();
+    assert_eq!(trace.len(), 20 * INSTRUCTIONS_PER_FRAME as usize);
+    assert!(!machine.take_key_events().is_empty());
+
+    rewind
+        .rewind_to(&mut machine, 13, INSTRUCTIONS_PER_FRAME)
+        .unwrap();
+    assert_eq!(machine.save_state(), states[13]);
+    assert!(machine.take_key_events().is_empty());
+    assert!(machine.take_trace().is_empty());
+
+    // tracing carries on as before, cycles count on from where the run was
+    machine.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+    let rerun = machine.take_trace();
+    let first_time = &trace[13 * INSTRUCTIONS_PER_FRAME as usize..][..rerun.len()];
+    for (rerun, first_time) in rerun.iter().zip(first_time) {
+        assert_eq!(rerun.program_counter, first_time.program_counter);
+        assert_eq!(rerun.changed_registers, first_time.changed_registers);
+        assert_eq!(
+            rerun.cycle,
+            first_time.cycle + 7 * INSTRUCTIONS_PER_FRAME as u64
+        );
+    }
+}
+
+#[test]
+fn the_budget_evicts_the_oldest_snapshots() {
+    let size = machine().save_state().len();
+    let mut machine = machine();
+    let mut rewind = Rewind::new(3 * size, 1);
+    let states = run(&mut machine, &mut rewind, 10);
+    assert_eq!(rewind.used_memory(), 3 * size);
+    assert_eq!(rewind.oldest_frame(), Some(7));
+    assert_eq!(rewind.newest_frame(), Some(9));
+
+    assert!(matches!(
+        rewind.rewind_to(&mut machine, 6, INSTRUCTIONS_PER_FRAME),
+        Err(RewindError::FrameUnavailable(6))
+    ));
+    rewind
+        .rewind_to(&mut machine, 7, INSTRUCTIONS_PER_FRAME)
+        .unwrap();
+    assert_eq!(machine.save_state(), states[7]);
+}
+
+#[test]
+fn the_newest_snapshot_stays_over_budget() {
+    let mut machine = machine();
+    let mut rewind = Rewind::new(0, 1);
+    let states = run(&mut machine, &mut rewind, 4);
+    assert_eq!(rewind.oldest_frame(), Some(3));
+    rewind
+        .rewind_to(&mut machine, 3, INSTRUCTIONS_PER_FRAME)
+        .unwrap();
+    assert_eq!(machine.save_state(), states[3]);
+}
@@ -0,0 +1,102 @@
+//! Test ROMs run headless, their final screen is compared with the images in `tests/screens`.
+//! To update an image, run `chip-8-rs test <rom>` and copy the printed screen.
+
+use std::{fs, path::Path};
+
+use chip_8_rs::{
+    compare_screen, compare_screen_hash, screen_hash, ExecutionEngine, Machine, QuirkProfile, Rom,
+    RunEnd, Runner,
+};
+
+const INSTRUCTIONS_PER_FRAME: u32 = 10;
+const MAX_FRAMES: u64 = 600;
+// screens must match whichever engine runs the ROM
+const ENGINES: [ExecutionEngine; 2] = [ExecutionEngine::Interpreter, ExecutionEngine::Blocks];
+
+fn run(rom: &str, quirks: QuirkProfile) -> Runner {
+    run_with(rom, quirks, ExecutionEngine::Interpreter)
+}
+
+fn run_with(rom: &str, quirks: QuirkProfile, engine: ExecutionEngine) -> Runner {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/roms")
+        .join(rom);
+    let mut machine = Machine::new();
+    machine.set_quirks(quirks.quirks());
+    machine.set_execution_engine(engine);
+    let machine = Rom::load_bytes(fs::read(path).unwrap(), machine).unwrap();
+
+    let mut runner = Runner::new(machine, INSTRUCTIONS_PER_FRAME);
+    let end = runner.run(MAX_FRAMES);
+    assert!(
+        matches!(end, RunEnd::Looped(_)),
+        "{} did not halt with {:?}: {:?}",
+        rom,
+        engine,
+        end
+    );
+    runner
+}
+
+fn assert_screen(runner: &Runner, image: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/screens")
+        .join(image);
+    let expected = fs::read_to_string(path).unwrap();
+    if let Err(mismatch) = compare_screen(&runner.machine().screen, &expected) {
+        panic!("{}", mismatch);
+    }
+}
+
+#[test]
+fn ibm_logo() {
+    for engine in ENGINES {
+        let runner = run_with("2-ibm-logo.ch8", QuirkProfile::Vip, engine);
+        assert_screen(&runner, "2-ibm-logo.txt");
+    }
+}
+
+#[test]
+fn flags() {
+    for engine in ENGINES {
+        let runner = run_with("4-flags.ch8", QuirkProfile::Vip, engine);
+        assert_screen(&runner, "4-flags.txt");
+    }
+}
+
+#[test]
+fn opcodes() {
+    for engine in ENGINES {
+        let runner = run_with("test_opcode.ch8", QuirkProfile::Vip, engine);
+        assert_screen(&runner, "test_opcode.txt");
+    }
+}
+
+#[test]
+fn hash_matches_image() {
+    let runner = run("2-ibm-logo.ch8", QuirkProfile::Vip);
+    let screen = &runner.machine().screen;
+    assert!(compare_screen_hash(screen, screen_hash(screen)).is_ok());
+    assert!(compare_screen_hash(screen, 0).is_err());
+}
+
+#[test]
+fn mismatch_points_at_pixels() {
+    let runner = run("2-ibm-logo.ch8", QuirkProfile::Vip);
+    let expected = fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/screens/2-ibm-logo.txt"),
+    )
+    .unwrap();
+    // turn off the first pixel of the logo
+    let broken = expected.replacen("............#", ".............", 1);
+
+    let mismatch = compare_screen(&runner.machine().screen, &broken).unwrap_err();
+    let report = mismatch.to_string();
+    assert!(report.starts_with("screen differs on 1 rows"), "{}", report);
+    assert!(report.contains("row 8:"), "{}", report);
+    assert!(
+        report.contains(&format!("{}^", " ".repeat(11 + 12))),
+        "{}",
+        report
+    );
+}
@@ -0,0 +1,193 @@
+//! Save states restore everything a program can observe, and reject anything they cannot read.
+
+use chip_8_rs::{
+    Machine, MemoryMode, QuirkProfile, Quirks, Random, Rom, SaveStateError, ALL_PLANES,
+    PATTERN_SIZE, SAVE_STATE_VERSION,
+};
+
+// hires, VA = 60, both timers = VA, V0 = random, call 0x210, 0x20E: jump to itself,
+// 0x210: I = digit V0, draw it at V0, V1, 0x214: jump to itself
+const PROGRAM: [u8; 22] = [
+    0x00, 0xFF, 0x6A, 0x3C, 0xFA, 0x15, 0xFA, 0x18, 0xC0, 0xFF, 0x22, 0x10, 0x12, 0x0E, 0x00, 0x00,
+    0xF0, 0x29, 0xD0, 0x15, 0x12, 0x14,
+];
+
+/// Everything [`Machine::save_state`] promises to keep
+#[derive(Debug, PartialEq)]
+struct State {
+    registers: [u8; 16],
+    index_register: u16,
+    program_counter: u16,
+    stack_pointer: u8,
+    stack: [u16; 16],
+    timers: (u8, u8),
+    frame_count: u64,
+    pressed_keys: [bool; 16],
+    flag_registers: [u8; 16],
+    waiting_for_vblank: bool,
+    exited: bool,
+    random: Random,
+    quirks: Quirks,
+    audio: ([u8; PATTERN_SIZE], u8),
+    screen: (bool, u8, Vec<u8>),
+    memory_mode: MemoryMode,
+    ram: Vec<u8>,
+}
+
+fn state(machine: &Machine) -> State {
+    State {
+        registers: std::array::from_fn(|register| {
+            machine.read_general_purpouse_registers(register)
+        }),
+        index_register: machine.read_index_register(),
+        program_counter: machine.read_program_counter(),
+        stack_pointer: machine.read_stack_pointer(),
+        stack: machine.read_stack(),
+        timers: (machine.read_delay_timer(), machine.read_sound_timer()),
+        frame_count: machine.read_frame_count(),
+        pressed_keys: machine.read_pressed_keys(),
+        flag_registers: machine.read_flag_registers(),
+        waiting_for_vblank: machine.is_waiting_for_vblank(),
+        exited: machine.has_exited(),
+        random: machine.read_random().clone(),
+        quirks: machine.read_quirks(),
+        audio: (machine.audio.read_pattern(), machine.audio.read_pitch()),
+        screen: (
+            machine.screen.is_hires(),
+            machine.screen.selected_planes(),
+            machine.screen.display.to_vec(),
+        ),
+        memory_mode: machine.read_memory_mode(),
+        ram: (0..machine.ram_size())
+            .map(|address| machine.read_ram(address as u16).unwrap())
+            .collect(),
+    }
+}
+
+/// A machine that differs from a fresh one in every part of the state
+fn busy_machine() -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::XoChip.quirks());
+    machine.set_memory_mode(MemoryMode::XoChip);
+    let mut machine = Rom::load_bytes(PROGRAM.to_vec(), machine).unwrap();
+    machine.seed_random(7);
+    for _ in 0..3 {
+        machine.run_frame(10).unwrap();
+    }
+    machine.set_pressed_keys(0x3);
+    machine.set_pressed_keys(0xA);
+    machine.write_to_flag_registers(std::array::from_fn(|flag| flag as u8 + 1));
+    machine.audio.write_to_pattern([0x5A; PATTERN_SIZE]);
+    machine.audio.write_to_pitch(100);
+    machine.screen.select_planes(ALL_PLANES);
+    machine.copy_to_ram(vec![0xAB, 0xCD], 0xFFFE).unwrap();
+    machine
+}
+
+#[test]
+fn round_trip_restores_everything() {
+    let mut original = busy_machine();
+    let saved = original.save_state();
+    assert_eq!(&saved[..4], b"C8SS");
+    assert_eq!(saved[4..6], SAVE_STATE_VERSION.to_le_bytes());
+
+    let mut restored = Machine::new();
+    restored.load_state(&saved).unwrap();
+    let before = state(&original);
+    assert_eq!(state(&restored), before);
+    assert_eq!(before.stack_pointer, 1);
+    assert!(before.screen.2.iter().any(|&pixel| pixel != 0));
+    assert_eq!(restored.save_state(), saved);
+
+    // and both carry on the same way
+    for _ in 0..5 {
+        original.run_frame(10).unwrap();
+        restored.run_frame(10).unwrap();
+    }
+    assert_eq!(state(&restored), state(&original));
+}
+
+#[test]
+fn loading_does_not_record_key_events() {
+    let saved = busy_machine().save_state();
+    let mut machine = Machine::new();
+    machine.set_input_recording(true);
+    machine.load_state(&saved).unwrap();
+    assert!(machine.take_key_events().is_empty());
+    assert!(machine.read_pressed_keys()[0xA]);
+
+    // real presses are still recorded, from the loaded frame on
+    machine.unset_pressed_keys(0xA);
+    let events = machine.take_key_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].frame, 3);
+}
+
+fn rejected(state: &[u8]) -> SaveStateError {
+    let mut machine = busy_machine();
+    let before = machine.save_state();
+    let error = machine.load_state(state).unwrap_err();
+    // nothing changed
+    assert_eq!(machine.save_state(), before);
+    error
+}
+
+#[test]
+fn bad_magic() {
+    let mut saved = busy_machine().save_state();
+    saved[0] = b'X';
+    assert!(matches!(rejected(&saved), SaveStateError::BadMagic));
+    assert!(matches!(rejected(b"PNG"), SaveStateError::Truncated));
+}
+
+#[test]
+fn unknown_version() {
+    let mut saved = busy_machine().save_state();
+    saved[4..6].copy_from_slice(&(SAVE_STATE_VERSION + 1).to_le_bytes());
+    let error = rejected(&saved);
+    assert!(matches!(
+        error,
+        SaveStateError::UnsupportedVersion(version) if version == SAVE_STATE_VERSION + 1
+    ));
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "save state version {} is not supported, expected {}",
+            SAVE_STATE_VERSION + 1,
+            SAVE_STATE_VERSION
+        )
+    );
+}
+
+#[test]
+fn truncated() {
+    let saved = Machine::new().save_state();
+    // every cut through the fixed size fields, then a few through the screen and ram
+    let header = saved.len() - 4096 - 128 * 64;
+    let lengths = (6..header).chain([header + 100, saved.len() - 4096, saved.len() - 1]);
+    for length in lengths {
+        assert!(
+            matches!(rejected(&saved[..length]), SaveStateError::Truncated),
+            "{} bytes",
+            length
+        );
+    }
+}
+
+#[test]
+fn invalid_fields() {
+    let mut saved = Machine::new().save_state();
+    saved.push(0);
+    assert!(matches!(
+        rejected(&saved),
+        SaveStateError::Invalid("length")
+    ));
+
+    // the stack pointer follows the 16 registers, I and PC
+    let mut saved = Machine::new().save_state();
+    saved[6 + 16 + 4] = 17;
+    assert!(matches!(
+        rejected(&saved),
+        SaveStateError::Invalid("stack pointer")
+    ));
+}
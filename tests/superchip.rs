@@ -0,0 +1,128 @@
+//! SUPER-CHIP hires mode, scrolling and 16x16 sprites.
+
+use chip_8_rs::{
+    Machine, QuirkProfile, Rom, HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, SCREEN_WIDTH,
+};
+
+// a single lit pixel in the top left corner of a sprite
+const DOT: u16 = 0x300;
+// 16 rows of 16 lit pixels
+const BLOCK: u16 = 0x310;
+
+fn load(program: &[u8]) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::SuperChip.quirks());
+    let mut machine = Rom::load_bytes(program.to_vec(), machine).unwrap();
+    machine.copy_to_ram(vec![0x80], DOT).unwrap();
+    machine.copy_to_ram(vec![0xFF; 32], BLOCK).unwrap();
+    machine
+}
+
+fn run(machine: &mut Machine, instructions: usize) {
+    for _ in 0..instructions {
+        machine.exec().unwrap();
+    }
+}
+
+fn lit_pixels(machine: &Machine) -> Vec<(usize, usize)> {
+    let screen = &machine.screen;
+    (0..screen.height())
+        .flat_map(|y| (0..screen.width()).map(move |x| (x, y)))
+        .filter(|&(x, y)| screen.is_lit(x, y))
+        .collect()
+}
+
+#[test]
+fn hires_toggle_clears_the_screen() {
+    // I = DOT, V0 = 100, V1 = 50, hires, draw at V0, V1, lores
+    let mut machine = load(&[
+        0xA3, 0x00, 0x60, 0x64, 0x61, 0x32, 0x00, 0xFF, 0xD0, 0x11, 0x00, 0xFE,
+    ]);
+    run(&mut machine, 4);
+    assert!(machine.screen.is_hires());
+    assert_eq!(
+        (machine.screen.width(), machine.screen.height()),
+        (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT)
+    );
+
+    // past the lores screen, only hires has room for it
+    run(&mut machine, 1);
+    assert_eq!(lit_pixels(&machine), vec![(100, 50)]);
+
+    run(&mut machine, 1);
+    assert!(!machine.screen.is_hires());
+    assert_eq!(machine.screen.width(), SCREEN_WIDTH);
+    assert!(lit_pixels(&machine).is_empty());
+}
+
+#[test]
+fn scrolling() {
+    // I = DOT, draw at 0, 0, scroll down 3, right 4, right 4, left 4
+    let mut machine = load(&[
+        0xA3, 0x00, 0xD0, 0x01, 0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFB, 0x00, 0xFC,
+    ]);
+    run(&mut machine, 3);
+    assert_eq!(lit_pixels(&machine), vec![(0, 3)]);
+    run(&mut machine, 1);
+    assert_eq!(lit_pixels(&machine), vec![(4, 3)]);
+    run(&mut machine, 1);
+    assert_eq!(lit_pixels(&machine), vec![(8, 3)]);
+    run(&mut machine, 1);
+    assert_eq!(lit_pixels(&machine), vec![(4, 3)]);
+}
+
+#[test]
+fn scrolling_drops_what_leaves_the_screen() {
+    // I = DOT, V0 = 126, hires, draw at V0, 0, scroll right 4
+    let mut machine = load(&[0xA3, 0x00, 0x60, 0x7E, 0x00, 0xFF, 0xD0, 0x11, 0x00, 0xFB]);
+    run(&mut machine, 4);
+    assert_eq!(lit_pixels(&machine), vec![(126, 0)]);
+    // nothing comes back in at the left
+    run(&mut machine, 1);
+    assert!(lit_pixels(&machine).is_empty());
+
+    // I = DOT, hires, draw at 0, 0, scroll down 15 five times
+    let mut machine = load(&[
+        0xA3, 0x00, 0x00, 0xFF, 0xD1, 0x11, 0x00, 0xCF, 0x00, 0xCF, 0x00, 0xCF, 0x00, 0xCF, 0x00,
+        0xCF,
+    ]);
+    run(&mut machine, 6);
+    assert_eq!(lit_pixels(&machine), vec![(0, 45)]);
+    run(&mut machine, 1);
+    assert_eq!(lit_pixels(&machine), vec![(0, 60)]);
+    run(&mut machine, 1);
+    assert!(lit_pixels(&machine).is_empty());
+}
+
+#[test]
+fn sixteen_by_sixteen_sprites_collide() {
+    // I = BLOCK, V0 = 8, V1 = 4, hires, draw 16x16 at V0, V1 twice
+    let mut machine = load(&[
+        0xA3, 0x10, 0x60, 0x08, 0x61, 0x04, 0x00, 0xFF, 0xD0, 0x10, 0xD0, 0x10,
+    ]);
+    run(&mut machine, 5);
+    let lit = lit_pixels(&machine);
+    assert_eq!(lit.len(), 16 * 16);
+    assert_eq!(lit.first(), Some(&(8, 4)));
+    assert_eq!(lit.last(), Some(&(23, 19)));
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 0);
+
+    run(&mut machine, 1);
+    assert!(lit_pixels(&machine).is_empty());
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 1);
+}
+
+#[test]
+fn sixteen_by_sixteen_collision_in_the_right_half() {
+    // I = BLOCK, hires, draw 16x16 at 0, 0, I = DOT, V0 = 15, draw at V0, V1
+    let mut machine = load(&[
+        0xA3, 0x10, 0x00, 0xFF, 0xD1, 0x10, 0xA3, 0x00, 0x60, 0x0F, 0xD0, 0x11,
+    ]);
+    run(&mut machine, 3);
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 0);
+    // the dot lands on the last column of the first row, which the second byte drew
+    run(&mut machine, 3);
+    assert_eq!(machine.read_general_purpouse_registers(0xF), 1);
+    assert!(!machine.screen.is_lit(15, 0));
+    assert_eq!(lit_pixels(&machine).len(), 16 * 16 - 1);
+}
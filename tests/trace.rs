@@ -0,0 +1,211 @@
+//! One trace record per executed instruction, in a format that lines up between runs.
+
+use std::sync::Arc;
+
+use chip_8_rs::{
+    ExecutionEngine, Machine, MachineError, OpcodeDecision, OpcodePolicy, QuirkProfile, Rom,
+    TraceFilter, TraceFormat, TraceRecord,
+};
+
+// V0 = 0x12, V1 = 0x34, V0 += V1, I = 0x210, call 0x20C, 0x20A: jump to itself,
+// 0x20C: V1 >>= 1 from V0 where VY is shifted, return
+const PROGRAM: [u8; 16] = [
+    0x60, 0x12, 0x61, 0x34, 0x80, 0x14, 0xA2, 0x10, 0x22, 0x0C, 0x12, 0x0A, 0x81, 0x06, 0x00, 0xEE,
+];
+
+fn traced(quirks: QuirkProfile, filter: TraceFilter) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(quirks.quirks());
+    machine.set_trace(Some(filter));
+    Rom::load_bytes(PROGRAM.to_vec(), machine).unwrap()
+}
+
+#[test]
+fn one_record_per_instruction() {
+    let mut machine = traced(QuirkProfile::Vip, TraceFilter::default());
+    machine.run_frame(8).unwrap();
+    let records = machine.take_trace();
+    assert_eq!(records.len(), 8);
+    assert!(machine.take_trace().is_empty());
+
+    let add = &records[2];
+    assert_eq!(add.cycle, 2);
+    assert_eq!(add.program_counter, 0x204);
+    assert_eq!(add.opcode, 0x8014);
+    // VF was 0 already
+    assert_eq!(add.changed_registers, Some(vec![(0, 0x46)]));
+    assert_eq!(
+        add.format(TraceFormat::Text),
+        "         2      0 0204 8014 ADD V0, V1           I=0000 SP=0 V0=46"
+    );
+
+    let call = &records[4];
+    assert_eq!(call.stack_pointer, 1);
+    assert_eq!(call.index_register, 0x210);
+    assert_eq!(
+        call.format(TraceFormat::JsonLines),
+        "{\"cycle\":4,\"frame\":0,\"pc\":520,\"opcode\":8716,\"mnemonic\":\"CALL 0x20C\",\"changed\":{},\"i\":528,\"sp\":1,\"writes\":[]}"
+    );
+}
+
+#[test]
+fn filters_keep_counting_cycles() {
+    let mut machine = traced(
+        QuirkProfile::Vip,
+        TraceFilter {
+            addresses: 0x20A..=0x20A,
+            frames: 1..=1,
+        },
+    );
+    for _ in 0..3 {
+        machine.run_frame(8).unwrap();
+    }
+    let records = machine.take_trace();
+    // the jump to itself, only during the second frame
+    assert_eq!(records.len(), 8);
+    assert_eq!(records[0].cycle, 8);
+    assert!(records
+        .iter()
+        .all(|record| record.program_counter == 0x20A && record.frame == 1));
+}
+
+#[test]
+fn quirk_profiles_line_up() {
+    let mut vip = traced(QuirkProfile::Vip, TraceFilter::default());
+    let mut chip48 = traced(QuirkProfile::Chip48, TraceFilter::default());
+    vip.run_frame(10).unwrap();
+    chip48.run_frame(10).unwrap();
+    let (vip, chip48) = (vip.take_trace(), chip48.take_trace());
+    assert_eq!(vip.len(), chip48.len());
+
+    // the same instructions at the same cycles, only the shift differs
+    let differing: Vec<u64> = vip
+        .iter()
+        .zip(&chip48)
+        .inspect(|(vip, chip48)| {
+            assert_eq!(
+                (vip.cycle, vip.program_counter),
+                (chip48.cycle, chip48.program_counter)
+            )
+        })
+        .filter(|(vip, chip48)| vip != chip48)
+        .map(|(vip, _)| vip.cycle)
+        .collect();
+    assert_eq!(differing, vec![5]);
+}
+
+#[test]
+fn policy_decisions_are_traced() {
+    let mut machine = Rom::load_bytes(vec![0x5A, 0xB1], Machine::new()).unwrap();
+    machine.set_opcode_policy(OpcodePolicy::Ignore);
+    machine.set_trace(Some(TraceFilter::default()));
+    machine.exec().unwrap();
+    let record = &machine.take_trace()[0];
+    assert_eq!(record.decision, Some(OpcodeDecision::Ignored));
+    assert!(record
+        .format(TraceFormat::Text)
+        .ends_with("DW 0x5AB1            I=0000 SP=0 (ignored)"));
+}
+
+#[test]
+fn halting_on_an_unknown_opcode_is_traced() {
+    // V0 = 1, then an opcode no variant defines, under the default policy
+    let mut machine = Rom::load_bytes(vec![0x60, 0x01, 0x5A, 0xB1], Machine::new()).unwrap();
+    machine.set_trace(Some(TraceFilter::default()));
+    assert_eq!(
+        machine.run_frame(10),
+        Err(MachineError::InvalidOpcode {
+            program_counter: 0x202,
+            opcode: 0x5AB1
+        })
+    );
+    let trace = machine.take_trace();
+    assert_eq!(trace.len(), 2);
+    let last = &trace[1];
+    assert_eq!((last.program_counter, last.opcode), (0x202, 0x5AB1));
+    assert_eq!(last.decision, Some(OpcodeDecision::Halted));
+    assert!(last.format(TraceFormat::Text).ends_with("(halted)"));
+    // the run stays on the faulting instruction
+    assert_eq!(machine.read_program_counter(), 0x202);
+}
+
+#[test]
+fn failing_handlers_are_traced() {
+    let mut machine = Rom::load_bytes(vec![0x5A, 0xB1], Machine::new()).unwrap();
+    machine.set_opcode_policy(OpcodePolicy::Handler(Arc::new(|_, opcode| {
+        Err(MachineError::InvalidOpcode {
+            program_counter: 0x200,
+            opcode,
+        })
+    })));
+    machine.set_trace(Some(TraceFilter::default()));
+    assert!(machine.exec().is_err());
+    let trace = machine.take_trace();
+    assert_eq!(trace.len(), 1);
+    assert_eq!(trace[0].decision, Some(OpcodeDecision::Handled));
+}
+
+#[test]
+fn engines_trace_the_same() {
+    let mut interpreter = traced(QuirkProfile::Vip, TraceFilter::default());
+    let mut blocks = traced(QuirkProfile::Vip, TraceFilter::default());
+    blocks.set_execution_engine(ExecutionEngine::Blocks);
+    interpreter.run_frame(20).unwrap();
+    blocks.run_frame(20).unwrap();
+    assert_eq!(interpreter.take_trace(), blocks.take_trace());
+}
+
+fn json_record(mnemonic: &str, pc: &str) -> String {
+    format!(
+        r#"{{"cycle":1,"frame":0,"pc":{},"opcode":224,"mnemonic":"{}","i":0,"sp":0}}"#,
+        pc, mnemonic
+    )
+}
+
+#[test]
+fn json_strings_unescape() {
+    let record: TraceRecord = json_record(r#"a\"b\\c\/\n\t\r\b\f\u0041\ud83d\ude00"#, "512")
+        .parse()
+        .unwrap();
+    assert_eq!(record.mnemonic, "a\"b\\c/\n\t\r\u{8}\u{c}A\u{1F600}");
+
+    // what to_json writes reads back the same
+    let mut machine = traced(QuirkProfile::Vip, TraceFilter::default());
+    machine.run_frame(5).unwrap();
+    for mut record in machine.take_trace() {
+        record.mnemonic.push_str(" \"quoted\" \\");
+        assert_eq!(record.to_json().parse::<TraceRecord>().unwrap(), record);
+    }
+}
+
+#[test]
+fn bad_json_escapes_are_errors() {
+    for mnemonic in [
+        r"\x41",
+        r"\u00",
+        r"\u00G1",
+        r"\ud83d",
+        r"\ud83d\u0041",
+        r"\ude00",
+    ] {
+        let line = json_record(mnemonic, "512");
+        let error = line.parse::<TraceRecord>().unwrap_err();
+        assert_eq!(error.to_string(), format!("not a trace record: '{}'", line));
+    }
+}
+
+#[test]
+fn json_numbers() {
+    let spaced = r#" { "cycle" : 1 , "frame":	0, "pc" :  512 ,"opcode":224, "i":0,"sp":0 } "#;
+    let record: TraceRecord = spaced.parse().unwrap();
+    assert_eq!((record.cycle, record.program_counter), (1, 0x200));
+
+    // negative, fractional, exponents and values too large for the field
+    for pc in ["-2", "1.5", "5e2", "65536", "18446744073709551616"] {
+        assert!(
+            json_record("CLS", pc).parse::<TraceRecord>().is_err(),
+            "{}",
+            pc
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! XO-CHIP 64 KiB memory, the long I load, register range save/load and the flag registers.
+
+use chip_8_rs::{Machine, MachineError, MemoryMode, QuirkProfile, Rom};
+
+fn load(program: &[u8]) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(QuirkProfile::XoChip.quirks());
+    Rom::load_bytes(program.to_vec(), machine).unwrap()
+}
+
+fn run(machine: &mut Machine, instructions: usize) {
+    for _ in 0..instructions {
+        machine.exec().unwrap();
+    }
+}
+
+fn registers(machine: &Machine) -> [u8; 4] {
+    std::array::from_fn(|register| machine.read_general_purpouse_registers(register))
+}
+
+fn ram(machine: &Machine, address: u16, length: u16) -> Vec<u8> {
+    (address..address + length)
+        .map(|address| machine.read_ram(address).unwrap())
+        .collect()
+}
+
+#[test]
+fn long_i_load_is_four_bytes() {
+    // I = 0xE000, V0 = 1
+    let mut machine = load(&[0xF0, 0x00, 0xE0, 0x00, 0x60, 0x01]);
+    run(&mut machine, 1);
+    assert_eq!(machine.read_index_register(), 0xE000);
+    assert_eq!(machine.read_program_counter(), 0x204);
+    run(&mut machine, 1);
+    assert_eq!(registers(&machine), [1, 0, 0, 0]);
+}
+
+#[test]
+fn skips_jump_over_long_i_loads() {
+    // skip if V0 == 0, I = 0x1234, V1 = 1, skip if V0 != 0, I = 0x5678
+    let mut machine = load(&[
+        0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01, 0x40, 0x00, 0xF0, 0x00, 0x56, 0x78,
+    ]);
+    run(&mut machine, 1);
+    assert_eq!(machine.read_program_counter(), 0x206);
+    run(&mut machine, 1);
+    assert_eq!(registers(&machine), [0, 1, 0, 0]);
+    assert_eq!(machine.read_index_register(), 0);
+
+    // not skipped, the long load runs as usual
+    run(&mut machine, 2);
+    assert_eq!(machine.read_index_register(), 0x5678);
+    assert_eq!(machine.read_program_counter(), 0x20E);
+}
+
+#[test]
+fn sixty_four_kib_of_memory() {
+    // I = 0xE000, V0 = 0x42, store V0 at I
+    let program = [0xF0, 0x00, 0xE0, 0x00, 0x60, 0x42, 0xF0, 0x55];
+    let mut machine = load(&program);
+    run(&mut machine, 2);
+    assert_eq!(
+        machine.exec(),
+        Err(MachineError::MemoryOutOfRange {
+            program_counter: 0x206,
+            address: 0xE000
+        })
+    );
+
+    let mut machine = load(&program);
+    machine.set_memory_mode(MemoryMode::XoChip);
+    assert_eq!(machine.ram_size(), 0x10000);
+    // the program is still there
+    run(&mut machine, 3);
+    assert_eq!(machine.read_ram(0xE000), Ok(0x42));
+}
+
+// V1 = 1, V2 = 2, V3 = 3, I = 0x300
+const REGISTERS: [u8; 8] = [0x61, 0x01, 0x62, 0x02, 0x63, 0x03, 0xA3, 0x00];
+
+#[test]
+fn register_range_save_in_both_directions() {
+    // save V1 to V3, I = 0x310, save V3 to V1
+    let mut program = REGISTERS.to_vec();
+    program.extend([0x51, 0x32, 0xA3, 0x10, 0x53, 0x12]);
+    let mut machine = load(&program);
+    run(&mut machine, 5);
+    assert_eq!(ram(&machine, 0x300, 4), vec![1, 2, 3, 0]);
+    // I stays where it was
+    assert_eq!(machine.read_index_register(), 0x300);
+    run(&mut machine, 2);
+    assert_eq!(ram(&machine, 0x310, 4), vec![3, 2, 1, 0]);
+    assert_eq!(machine.read_index_register(), 0x310);
+}
+
+#[test]
+fn register_range_load_in_both_directions() {
+    // load V0 to V2, load V2 to V0
+    let mut machine = load(&[0xA3, 0x00, 0x50, 0x23, 0x52, 0x03]);
+    machine.copy_to_ram(vec![7, 8, 9], 0x300).unwrap();
+    run(&mut machine, 2);
+    assert_eq!(registers(&machine), [7, 8, 9, 0]);
+    assert_eq!(machine.read_index_register(), 0x300);
+    run(&mut machine, 1);
+    assert_eq!(registers(&machine), [9, 8, 7, 0]);
+}
+
+#[test]
+fn register_range_of_one() {
+    // save V2 to V2
+    let mut program = REGISTERS.to_vec();
+    program.extend([0x52, 0x22]);
+    let mut machine = load(&program);
+    run(&mut machine, 5);
+    assert_eq!(ram(&machine, 0x300, 2), vec![2, 0]);
+}
+
+#[test]
+fn flag_registers_outlive_the_machine() {
+    // store V0 to V2 in the flags, clear them, load V0 to V1 back
+    let mut program = REGISTERS.to_vec();
+    program.extend([0x60, 0x05, 0xF2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00]);
+    program.extend([0xF1, 0x85]);
+    let mut machine = load(&program);
+    run(&mut machine, 9);
+    assert_eq!(registers(&machine), [0, 0, 0, 3]);
+    let flags = machine.read_flag_registers();
+    assert_eq!(flags[..4], [5, 1, 2, 0]);
+    // nothing in ram knows about them
+    assert_eq!(ram(&machine, 0x300, 3), vec![0, 0, 0]);
+    run(&mut machine, 1);
+    assert_eq!(registers(&machine), [5, 1, 0, 3]);
+
+    // a frontend hands them to the next run, a save state keeps them too
+    let mut next_run = load(&[0xF2, 0x85]);
+    next_run.write_to_flag_registers(flags);
+    let mut restored = Machine::new();
+    restored.load_state(&next_run.save_state()).unwrap();
+    run(&mut restored, 1);
+    assert_eq!(registers(&restored), [5, 1, 2, 0]);
+}